@@ -0,0 +1,118 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use crate::entry::write_atomically;
+
+// AudioStreamBasicDescription constants in CoreAudioTypes.h
+const LINEAR_PCM: u32 = u32::from_be_bytes(*b"lpcm");
+const FLAG_IS_FLOAT: u32 = 1 << 0;
+const FLAG_IS_BIG_ENDIAN: u32 = 1 << 1;
+
+/**
+ * Format of the raw capture of the system audio. It's written in the sidecar header `{raw file}.json`
+ * when the capture starts, from the AudioStreamBasicDescription of the stream.
+ */
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct CaptureFormat {
+    pub sample_rate: u32,
+    // Interleaved channels in the file. The non-interleaved buffers are written to the separate files.
+    pub channels: u16,
+    pub bits_per_sample: u16,
+    pub float: bool,
+    #[serde(default)]
+    pub big_endian: bool,
+}
+
+impl CaptureFormat {
+    /**
+     * The format which was assumed before the header was written.
+     */
+    pub fn legacy() -> CaptureFormat {
+        CaptureFormat { sample_rate: 48000, channels: 1, bits_per_sample: 32, float: true, big_endian: false }
+    }
+
+    /**
+     * `channels` is the number of the channels in the audio buffer, not in the stream.
+     */
+    pub fn from_stream_description(sample_rate: f64, format_id: u32, format_flags: u32, bits_per_channel: u32, channels: u32) -> Result<CaptureFormat> {
+        if format_id != LINEAR_PCM {
+            return Err(anyhow!("Unsupported audio format: {:?}", String::from_utf8_lossy(&format_id.to_be_bytes())));
+        }
+        let float = format_flags & FLAG_IS_FLOAT != 0;
+        let supported = if float { bits_per_channel == 32 } else { matches!(bits_per_channel, 16 | 32) };
+        if !supported || channels == 0 || sample_rate < 1.0 {
+            return Err(anyhow!("Unsupported audio format: rate={}, bits={}, float={}, channels={}",
+                sample_rate, bits_per_channel, float, channels));
+        }
+        Ok(CaptureFormat {
+            sample_rate: sample_rate.round() as u32,
+            channels: channels as u16,
+            bits_per_sample: bits_per_channel as u16,
+            float,
+            big_endian: format_flags & FLAG_IS_BIG_ENDIAN != 0,
+        })
+    }
+
+    pub fn bytes_per_sample(&self) -> usize {
+        self.bits_per_sample as usize / 8
+    }
+}
+
+pub fn header_path(raw_file: &Path) -> PathBuf {
+    let mut path = raw_file.as_os_str().to_owned();
+    path.push(".json");
+    PathBuf::from(path)
+}
+
+pub fn save_header(raw_file: &Path, format: &CaptureFormat) -> Result<()> {
+    let content = serde_json::to_string_pretty(format)?;
+    write_atomically(&header_path(raw_file), content.as_str())
+}
+
+/**
+ * Load the header of the raw file. The captures recorded before the header was introduced have no header.
+ */
+pub fn load_header(raw_file: &Path) -> Result<CaptureFormat> {
+    let path = header_path(raw_file);
+    if !path.exists() {
+        log::info!("Missing {:?}, assuming {:?}", path, CaptureFormat::legacy());
+        return Ok(CaptureFormat::legacy());
+    }
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Cannot read {:?}", path))?;
+    serde_json::from_str(content.as_str())
+        .with_context(|| format!("Cannot parse {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_stream_description() {
+        // ScreenCaptureKit delivers the non-interleaved float32 stereo. Each buffer is one channel.
+        let format = CaptureFormat::from_stream_description(48000.0, LINEAR_PCM, FLAG_IS_FLOAT | (1 << 5), 32, 1).unwrap();
+        assert_eq!(format, CaptureFormat::legacy());
+
+        let format = CaptureFormat::from_stream_description(44100.0, LINEAR_PCM, FLAG_IS_BIG_ENDIAN | (1 << 2), 16, 2).unwrap();
+        assert_eq!(format, CaptureFormat { sample_rate: 44100, channels: 2, bits_per_sample: 16, float: false, big_endian: true });
+
+        assert!(CaptureFormat::from_stream_description(48000.0, u32::from_be_bytes(*b"aac "), 0, 0, 2).is_err());
+        assert!(CaptureFormat::from_stream_description(48000.0, LINEAR_PCM, FLAG_IS_FLOAT, 64, 1).is_err());
+        assert!(CaptureFormat::from_stream_description(48000.0, LINEAR_PCM, 0, 24, 1).is_err());
+    }
+
+    #[test]
+    fn test_header() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let raw_file = tmp.path().join("20240101100000-0.raw");
+        assert_eq!(header_path(&raw_file), tmp.path().join("20240101100000-0.raw.json"));
+        assert_eq!(load_header(&raw_file)?, CaptureFormat::legacy());
+
+        let format = CaptureFormat { sample_rate: 44100, channels: 2, bits_per_sample: 16, float: false, big_endian: false };
+        save_header(&raw_file, &format)?;
+        assert_eq!(load_header(&raw_file)?, format);
+        Ok(())
+    }
+}
@@ -6,9 +6,19 @@ use anyhow::anyhow;
 use serde::{Deserialize, Serialize};
 use TranscriberType::WhisperCppTranscriberType;
 use crate::config::SummarizerType::{OpenAISummarizerType, TFIDFSummarizerType};
+use crate::encoder::{AacEncoder, AudioFormat, ChannelMode, Encoder, FlacEncoder, Mp3Encoder, OpusEncoder};
+use crate::hooks::PostprocessHook;
+use crate::openai::OpenAICustomizedClient;
+use crate::openai;
+use crate::openai_summarizer;
 use crate::openai_summarizer::OpenAISummarizer;
+use crate::openai_transcriber::OpenAITranscriber;
+use crate::processing_state::{ProcessingStep, validate_pipeline};
+use crate::retention::RetentionPolicy;
 use crate::summarizer::Summarizer;
 use crate::tf_idf_summarizer::TFIDFSummarizer;
+use crate::transcriber::Transcriber;
+use crate::whisper_cpp::WhisperTranscriber;
 use crate::window::WindowPattern;
 
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
@@ -42,12 +52,102 @@ pub struct MeetNoteConfig {
     // Target language
     #[serde(default = "default_language")]
     pub language: String,
+    // Transcribe the mic and the system audio separately, and label the cues by the source.
+    #[serde(default)]
+    pub speaker_attribution: bool,
+    // Transcribe only the speech. The silence wastes the time, and whisper.cpp hallucinates in it.
+    // Opt-in, the speech detection is heuristic.
+    #[serde(default)]
+    pub trim_silence: bool,
+    // Speaker name for the mic audio
+    #[serde(default = "default_local_speaker_name")]
+    pub local_speaker_name: String,
+    // Speaker name for the system audio
+    #[serde(default = "default_remote_speaker_name")]
+    pub remote_speaker_name: String,
+    // Cues which consist of these phrases are removed from the transcript.
+    #[serde(default = "default_hallucination_blocklist")]
+    pub hallucination_blocklist: Vec<String>,
+    // When to delete the old recordings
+    #[serde(default)]
+    pub retention: RetentionPolicy,
+    // Deleted entries are purged from the trash after this.
+    #[serde(default = "default_trash_retention_days")]
+    pub trash_retention_days: u32,
+    // Number of the entries which are postprocessed at once. Applied after the restart.
+    #[serde(default = "default_postprocess_workers")]
+    pub postprocess_workers: usize,
+    // Steps which run at once, for the local commands(whisper.cpp, merging the audio, encoders).
+    #[serde(default = "default_max_cpu_jobs")]
+    pub max_cpu_jobs: usize,
+    // Steps which run at once, for the OpenAI API.
+    #[serde(default = "default_max_network_jobs")]
+    pub max_network_jobs: usize,
+    // Steps of the postprocessing. e.g. omit Cleanup to keep the raw audio files.
+    #[serde(default = "default_pipeline")]
+    pub pipeline: Vec<ProcessingStep>,
+    // User commands which run after the steps.
+    #[serde(default)]
+    pub hooks: Vec<PostprocessHook>,
+    // Format of the recording which is kept after the cleanup.
+    #[serde(default)]
+    pub audio_format: AudioFormat,
+    // Not used for FLAC.
+    #[serde(default = "default_audio_bitrate_kbps")]
+    pub audio_bitrate_kbps: u32,
+    #[serde(default)]
+    pub audio_channel_mode: ChannelMode,
 }
 
 fn default_language() -> String {
     "ja".to_string()
 }
 
+fn default_local_speaker_name() -> String {
+    "Me".to_string()
+}
+
+fn default_remote_speaker_name() -> String {
+    "Others".to_string()
+}
+
+// "ご視聴ありがとうございました" をやたらと whisper.cpp は生成する。
+// "(ボタンを押す音)" はタイピング音だけのときに文字起こしされる
+fn default_hallucination_blocklist() -> Vec<String> {
+    vec![
+        String::from("ご視聴ありがとうございました"),
+        String::from("(ボタンを押す音)"),
+        String::from("[音声なし]"),
+        String::from("(笑い声)"),
+        String::from("[音楽]"),
+        String::from("(音楽)"),
+    ]
+}
+
+fn default_trash_retention_days() -> u32 {
+    30
+}
+
+fn default_postprocess_workers() -> usize {
+    2
+}
+
+fn default_max_cpu_jobs() -> usize {
+    1
+}
+
+fn default_max_network_jobs() -> usize {
+    2
+}
+
+fn default_audio_bitrate_kbps() -> u32 {
+    58
+}
+
+fn default_pipeline() -> Vec<ProcessingStep> {
+    ProcessingStep::ALL.to_vec()
+}
+
 fn default_transcriber_type() -> TranscriberType {
     WhisperCppTranscriberType
 }
@@ -57,6 +157,73 @@ fn default_summarizer_type() -> SummarizerType {
 }
 
 impl MeetNoteConfig {
+    pub fn build_transcriber(&self) -> anyhow::Result<Box<dyn Transcriber>> {
+        match self.transcriber_type {
+            TranscriberType::WhisperCppTranscriberType => {
+                Ok(Box::new(WhisperTranscriber::new(
+                    // 1.5.2 to 1.5.4 are not works.
+                    "v1.5.1".to_string(), self.whisper_model.to_string(), self.language.to_string(),
+                )))
+            }
+            TranscriberType::OpenAITranscriberType => {
+                let token = match &self.openai_api_token {
+                    Some(token) => { token }
+                    None => {
+                        return Err(anyhow!("OpenAI transcriber requires OpenAI token. But it's missing."));
+                    }
+                };
+                let openai = match OpenAICustomizedClient::new(
+                    token.as_str()
+                ) {
+                    Ok(openai) => { openai }
+                    Err(err) => {
+                        return Err(anyhow!("Cannot create openai client: {:?}", err))
+                    }
+                };
+
+                Ok(Box::new(OpenAITranscriber::new(
+                    openai,
+                    self.language.to_string(),
+                )))
+            }
+        }
+    }
+
+    /**
+     * (name, model) of the transcriber, to record in the entry metadata.
+     */
+    pub fn transcriber_description(&self) -> (String, String) {
+        match self.transcriber_type {
+            TranscriberType::WhisperCppTranscriberType => {
+                ("whisper.cpp".to_string(), self.whisper_model.to_string())
+            }
+            TranscriberType::OpenAITranscriberType => {
+                ("OpenAI".to_string(), openai::TRANSCRIPTION_MODEL.to_string())
+            }
+        }
+    }
+
+    /**
+     * (name, model) of the summarizer, to record in the entry metadata.
+     */
+    pub fn summarizer_description(&self) -> (String, Option<String>) {
+        match self.summarizer_type {
+            TFIDFSummarizerType => ("TF-IDF".to_string(), None),
+            OpenAISummarizerType => ("OpenAI".to_string(), Some(openai_summarizer::CHAT_MODEL.to_string())),
+        }
+    }
+
+    pub fn build_encoder(&self) -> Box<dyn Encoder> {
+        let bitrate_kbps = self.audio_bitrate_kbps;
+        let channel_mode = self.audio_channel_mode;
+        match self.audio_format {
+            AudioFormat::Mp3 => Box::new(Mp3Encoder { bitrate_kbps, channel_mode }),
+            AudioFormat::Opus => Box::new(OpusEncoder { bitrate_kbps, channel_mode }),
+            AudioFormat::Flac => Box::new(FlacEncoder { channel_mode }),
+            AudioFormat::Aac => Box::new(AacEncoder { bitrate_kbps, channel_mode }),
+        }
+    }
+
     pub fn build_summarizer(&self) -> anyhow::Result<Box<dyn Summarizer>>{
         match &self.summarizer_type {
             TFIDFSummarizerType => {
@@ -85,6 +252,21 @@ impl Default for MeetNoteConfig {
             target_device: None,
             whisper_model: "small".to_string(),
             language: "ja".to_string(),
+            speaker_attribution: false,
+            trim_silence: false,
+            local_speaker_name: default_local_speaker_name(),
+            remote_speaker_name: default_remote_speaker_name(),
+            hallucination_blocklist: default_hallucination_blocklist(),
+            retention: Default::default(),
+            trash_retention_days: default_trash_retention_days(),
+            postprocess_workers: default_postprocess_workers(),
+            max_cpu_jobs: default_max_cpu_jobs(),
+            max_network_jobs: default_max_network_jobs(),
+            pipeline: default_pipeline(),
+            hooks: Vec::new(),
+            audio_format: Default::default(),
+            audio_bitrate_kbps: default_audio_bitrate_kbps(),
+            audio_channel_mode: Default::default(),
             window_patterns: vec![
                 WindowPattern {
                     bundle_id: String::from("us.zoom.xos"),
@@ -149,6 +331,11 @@ pub fn load_config() -> anyhow::Result<MeetNoteConfig> {
 }
 
 pub fn save_config(config: &MeetNoteConfig) -> anyhow::Result<()> {
+    validate_pipeline(&config.pipeline)?;
+    if config.audio_bitrate_kbps == 0 {
+        return Err(anyhow!("The bitrate of the encoded audio must be positive"));
+    }
+
     let config_path = get_config_path()?;
     let tmp_path = config_path.with_extension("tmp");
 
@@ -1,29 +1,569 @@
+use std::collections::HashSet;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use anyhow::anyhow;
-use chrono::Local;
+use chrono::{Local, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use crate::encoder::AudioFormat;
 use crate::entry::Entry;
+use crate::processing_state::ProcessingStage;
+use crate::webvtt::{clip_captions, concat_captions, write_webvtt};
 
 pub struct DataRepo {
     pub data_dir: PathBuf,
+    // Deleted entries are moved here.
+    pub trash_dir: PathBuf,
+}
+
+// Written in the trashed entry directory.
+const TRASH_INFO_FILE: &str = "trash_info.json";
+
+// Length of the summary excerpt in the entry list, in characters.
+const EXCERPT_LENGTH: usize = 200;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum EntrySortOrder {
+    #[default]
+    Newest,
+    Oldest,
+    Longest,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct EntryQuery {
+    #[serde(default)]
+    pub offset: usize,
+    // Returns all entries if it's None.
+    #[serde(default)]
+    pub limit: Option<usize>,
+    // "%Y-%m-%d". Both ends are inclusive.
+    #[serde(default)]
+    pub since: Option<String>,
+    #[serde(default)]
+    pub until: Option<String>,
+    #[serde(default)]
+    pub sort: EntrySortOrder,
+}
+
+/**
+ * The lightweight view of the entry, for the entry list.
+ */
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct EntryListItem {
+    pub path: String,
+    pub basename: String,
+    pub title: Option<String>,
+    pub duration_millis: Option<u64>,
+    pub has_mic_wav: bool,
+    // The encoded recording which exists.
+    pub audio_format: Option<AudioFormat>,
+    pub has_vtt: bool,
+    pub has_md: bool,
+    // The head of the summary, without the markdown headings.
+    pub summary_excerpt: Option<String>,
+    pub processing_stage: ProcessingStage,
+    // Why the postprocessing failed, if the stage is Failed.
+    pub processing_error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TrashItem {
+    // Directory in the trash
+    pub path: String,
+    pub basename: String,
+    // Where the entry was before the deletion
+    pub original_path: String,
+    // "%Y-%m-%dT%H:%M:%S"
+    pub deleted_at: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EntryList {
+    pub entries: Vec<EntryListItem>,
+    // Number of the entries matched to the query, before the pagination.
+    pub total: usize,
 }
 
 impl DataRepo {
     pub fn new() -> anyhow::Result<Self> {
-        let app_data_dir = get_app_data_dir()?;
-        Ok(DataRepo { data_dir: app_data_dir.join("data") })
+        Ok(Self::with_dir(get_app_data_dir()?.as_path()))
+    }
+
+    pub fn with_dir(app_data_dir: &Path) -> Self {
+        DataRepo {
+            data_dir: app_data_dir.join("data"),
+            trash_dir: app_data_dir.join("trash"),
+        }
     }
 
     pub fn new_entry(&self) -> anyhow::Result<Entry> {
         let now = Local::now();
         let dirname = now.format("%Y%m%d%H%M%S").to_string();
 
-        let dir = self.data_dir.join(dirname);
+        let dir = self.entry_dir(dirname.as_str());
 
         fs::create_dir_all(&dir)?;
 
         Ok(Entry::new(dir))
     }
+
+    /**
+     * The directory of the entry, in the `data/%Y%m%d/%Y%m%d%H%M%S/` layout.
+     * The basename which isn't the timestamp is put in the data directory directly.
+     */
+    pub fn entry_dir(&self, basename: &str) -> PathBuf {
+        match Entry::new(self.data_dir.join(basename)).shard_name() {
+            Some(shard) => self.data_dir.join(shard).join(basename),
+            None => self.data_dir.join(basename),
+        }
+    }
+
+    /**
+     * True if the entry exists, in either of the layouts.
+     */
+    pub fn has_entry(&self, basename: &str) -> bool {
+        self.entry_dir(basename).exists() || self.data_dir.join(basename).exists()
+    }
+
+    /**
+     * All entries in the data directory, in no particular order.
+     * The entries in the old flat layout(`data/%Y%m%d%H%M%S/`) are included, until they are migrated.
+     */
+    pub fn entries(&self) -> anyhow::Result<Vec<Entry>> {
+        if !self.data_dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut entries = Vec::new();
+        let mut flat_entries = Vec::new();
+        for dir_entry in fs::read_dir(&self.data_dir)? {
+            let path = dir_entry?.path();
+            // Skip .DS_Store, the import staging directories, etc.
+            if !path.is_dir() || is_hidden(&path) {
+                continue;
+            }
+
+            if is_shard_dir(&path) {
+                for child in fs::read_dir(&path)? {
+                    let child = child?.path();
+                    if child.is_dir() {
+                        entries.push(Entry::new(child));
+                    }
+                }
+            } else {
+                flat_entries.push(Entry::new(path));
+            }
+        }
+
+        // The half-migrated entry exists in both layouts. The new one wins.
+        let basenames: HashSet<String> = entries.iter()
+            .map(|entry| entry.basename.clone())
+            .collect();
+        entries.extend(flat_entries.into_iter()
+            .filter(|entry| !basenames.contains(&entry.basename)));
+        Ok(entries)
+    }
+
+    /**
+     * Move the entries in the flat layout into the per-day directories.
+     *
+     * It's safe to run it many times. If it was interrupted, the next run moves the rest. When the
+     * destination exists already, the files which aren't there yet are moved one by one, and the
+     * conflicting files are left in the old directory.
+     *
+     * Returns the number of the migrated entries.
+     */
+    pub fn migrate_to_sharded_layout(&self) -> anyhow::Result<usize> {
+        if !self.data_dir.exists() {
+            return Ok(0);
+        }
+
+        let mut migrated = 0;
+        for dir_entry in fs::read_dir(&self.data_dir)? {
+            let path = dir_entry?.path();
+            if !path.is_dir() || is_hidden(&path) || is_shard_dir(&path) {
+                continue;
+            }
+
+            let entry = Entry::new(path);
+            let dest = self.entry_dir(entry.basename.as_str());
+            if dest == entry.dir {
+                // Not a timestamp. Leave it in the flat layout.
+                continue;
+            }
+
+            if let Err(err) = move_entry_dir(&entry.dir, &dest) {
+                log::error!("Cannot migrate {:?} to {:?}: {:?}", entry.dir, dest, err);
+                continue;
+            }
+            log::info!("Migrated {:?} to {:?}", entry.dir, dest);
+            migrated += 1;
+        }
+        Ok(migrated)
+    }
+
+    /**
+     * Where the entry in the flat layout was migrated to. None if it's not migrated, or it's not
+     * in the flat layout.
+     */
+    pub fn migrated_entry_dir(&self, flat_dir: &Path) -> Option<PathBuf> {
+        if flat_dir.parent() != Some(self.data_dir.as_path()) {
+            return None;
+        }
+        let dest = self.entry_dir(flat_dir.file_name()?.to_str()?);
+        (dest != flat_dir && dest.exists()).then_some(dest)
+    }
+
+    /**
+     * List the entries in the data directory.
+     */
+    pub fn list_entries(&self, query: &EntryQuery) -> anyhow::Result<EntryList> {
+        let since = parse_date(&query.since)?;
+        let until = parse_date(&query.until)?;
+
+        let mut entries = Vec::new();
+        for entry in self.entries()? {
+            if since.is_some() || until.is_some() {
+                let Some(date) = entry.started_at().map(|it| it.date()) else {
+                    continue;
+                };
+                if since.is_some_and(|since| date < since) || until.is_some_and(|until| date > until) {
+                    continue;
+                }
+            }
+            entries.push(entry);
+        }
+        let total = entries.len();
+
+        let limit = query.limit.unwrap_or(usize::MAX);
+        let items = match query.sort {
+            EntrySortOrder::Newest | EntrySortOrder::Oldest => {
+                entries.sort_by(|a, b| a.basename.cmp(&b.basename));
+                if query.sort == EntrySortOrder::Newest {
+                    entries.reverse();
+                }
+                // Read the files of the current page only.
+                entries.iter()
+                    .skip(query.offset)
+                    .take(limit)
+                    .map(list_item)
+                    .collect()
+            }
+            EntrySortOrder::Longest => {
+                // The duration is in meta.json. Every entry has to be loaded.
+                let mut items: Vec<EntryListItem> = entries.iter().map(list_item).collect();
+                items.sort_by(|a, b| b.duration_millis.cmp(&a.duration_millis)
+                    .then_with(|| b.basename.cmp(&a.basename)));
+                items.into_iter()
+                    .skip(query.offset)
+                    .take(limit)
+                    .collect()
+            }
+        };
+
+        Ok(EntryList { entries: items, total })
+    }
+
+    /**
+     * Move the entry into the trash. It can be restored until the trash is purged.
+     */
+    pub fn trash_entry(&self, entry: &Entry, now: NaiveDateTime) -> anyhow::Result<TrashItem> {
+        fs::create_dir_all(&self.trash_dir)?;
+        // Prefixed by the deletion time, the same entry may be deleted again after the restore.
+        let path = self.trash_dir.join(format!("{}-{}", now.format("%Y%m%d%H%M%S"), entry.basename));
+        if path.exists() {
+            return Err(anyhow!("{:?} exists in the trash already", path));
+        }
+        // The recorder is still writing into it.
+        if entry.load_processing_state()?.stage == ProcessingStage::Recording {
+            return Err(anyhow!("{} is being recorded", entry.basename));
+        }
+
+        let item = TrashItem {
+            path: path.to_str().unwrap().to_string(),
+            basename: entry.basename.clone(),
+            original_path: entry.dir.to_str().unwrap().to_string(),
+            deleted_at: now.format(TRASH_TIME_FORMAT).to_string(),
+        };
+        // Written before the move, the trashed entry always has it.
+        let info_path = entry.dir.join(TRASH_INFO_FILE);
+        fs::write(&info_path, serde_json::to_string_pretty(&item)?)?;
+        if let Err(err) = fs::rename(&entry.dir, &path) {
+            if let Err(err) = fs::remove_file(&info_path) {
+                log::error!("Cannot remove {:?}: {:?}", info_path, err);
+            }
+            return Err(anyhow!("Cannot move {:?} to the trash: {:?}", entry.dir, err));
+        }
+        log::info!("Moved {:?} to {:?}", entry.dir, path);
+        Ok(item)
+    }
+
+    /**
+     * Entries in the trash. The recently deleted one comes first.
+     */
+    pub fn list_trash(&self) -> anyhow::Result<Vec<TrashItem>> {
+        if !self.trash_dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut items = Vec::new();
+        for dir_entry in fs::read_dir(&self.trash_dir)? {
+            let path = dir_entry?.path();
+            if !path.is_dir() {
+                continue;
+            }
+            match read_trash_info(&path) {
+                Ok(item) => items.push(item),
+                Err(err) => log::error!("Cannot read the trash info of {:?}: {:?}", path, err),
+            }
+        }
+        items.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at).then(b.basename.cmp(&a.basename)));
+        Ok(items)
+    }
+
+    /**
+     * Move the entry back to the data directory. The existing entry is never overwritten.
+     */
+    pub fn restore_from_trash(&self, trash_path: &Path) -> anyhow::Result<Entry> {
+        let item = read_trash_info(trash_path)?;
+        if self.has_entry(item.basename.as_str()) {
+            return Err(anyhow!("{} exists already", item.basename));
+        }
+        let dest = self.entry_dir(item.basename.as_str());
+
+        fs::remove_file(trash_path.join(TRASH_INFO_FILE))?;
+        fs::create_dir_all(dest.parent().unwrap())?;
+        fs::rename(trash_path, &dest)
+            .map_err(|err| anyhow!("Cannot restore {:?} to {:?}: {:?}", trash_path, dest, err))?;
+        log::info!("Restored {:?} to {:?}", trash_path, dest);
+        Ok(Entry::new(dest))
+    }
+
+    /**
+     * Delete the trashed entries permanently. Only the entries deleted before `deleted_before`
+     * are purged if it's given.
+     * Returns the number of the purged entries.
+     */
+    pub fn purge_trash(&self, deleted_before: Option<NaiveDateTime>) -> anyhow::Result<usize> {
+        let mut purged = 0;
+        for item in self.list_trash()? {
+            if let Some(deleted_before) = deleted_before {
+                match NaiveDateTime::parse_from_str(item.deleted_at.as_str(), TRASH_TIME_FORMAT) {
+                    Ok(deleted_at) if deleted_at >= deleted_before => continue,
+                    Ok(_) => {}
+                    Err(err) => {
+                        log::error!("Invalid deletion time of {}: {:?}", item.path, err);
+                        continue;
+                    }
+                }
+            }
+            if let Err(err) = fs::remove_dir_all(&item.path) {
+                log::error!("Cannot purge {} from the trash: {:?}", item.path, err);
+                continue;
+            }
+            log::info!("Purged {} from the trash", item.path);
+            purged += 1;
+        }
+        Ok(purged)
+    }
+
+    /**
+     * Create the new entry which has the transcripts of both entries, on the timeline of the
+     * earlier one. The offset is derived from the recording start times.
+     */
+    pub fn merge_entries(&self, a: &Entry, b: &Entry) -> anyhow::Result<Entry> {
+        let (first, second) = match (a.started_at(), b.started_at()) {
+            (Some(a_start), Some(b_start)) if b_start < a_start => (b, a),
+            _ => (a, b),
+        };
+        let first_captions = first.read_captions()?;
+        let second_captions = second.read_captions()?;
+
+        let offset = match (first.started_at(), second.started_at()) {
+            (Some(first_start), Some(second_start)) => {
+                (second_start - first_start).to_std()?
+            }
+            // The basename isn't the timestamp. Put it just after the first transcript.
+            _ => first_captions.iter()
+                .map(|caption| caption.end_time)
+                .max()
+                .unwrap_or(Duration::ZERO),
+        };
+
+        let captions = concat_captions(&first_captions, &second_captions, offset);
+
+        // No audio in the merged entry.
+        let mut meta = first.load_meta()?;
+        let second_meta = second.load_meta()?;
+        for tag in second_meta.tags {
+            if !meta.tags.contains(&tag) {
+                meta.tags.push(tag);
+            }
+        }
+        for participant in second_meta.participants {
+            if !meta.participants.contains(&participant) {
+                meta.participants.push(participant);
+            }
+        }
+        meta.duration_millis = match (meta.duration_millis, second_meta.duration_millis) {
+            (Some(first_millis), Some(second_millis)) => Some(first_millis.max(offset.as_millis() as u64 + second_millis)),
+            _ => None,
+        };
+        meta.audio_format = None;
+        meta.audio_channel_mode = None;
+
+        let entry = self.create_free_entry(first.started_at())?;
+        entry.save_meta(&meta)?;
+        fs::write(entry.webvtt_path_string(), write_webvtt(&captions))?;
+        log::info!("Merged {:?} and {:?} into {:?}", first.dir, second.dir, entry.dir);
+        Ok(entry)
+    }
+
+    /**
+     * Create the new entry which has the transcript in [start, end) of the entry.
+     */
+    pub fn clip_entry(&self, entry: &Entry, start: Duration, end: Duration) -> anyhow::Result<Entry> {
+        if end <= start {
+            return Err(anyhow!("Invalid range: {:?} - {:?}", start, end));
+        }
+        let captions = clip_captions(&entry.read_captions()?, start, end);
+
+        let mut meta = entry.load_meta()?;
+        meta.duration_millis = meta.duration_millis
+            .map(|millis| millis.saturating_sub(start.as_millis() as u64).min((end - start).as_millis() as u64));
+        meta.audio_format = None;
+        meta.audio_channel_mode = None;
+
+        let started_at = entry.started_at()
+            .map(|started_at| started_at + chrono::Duration::milliseconds(start.as_millis() as i64));
+        let clipped = self.create_free_entry(started_at)?;
+        clipped.save_meta(&meta)?;
+        fs::write(clipped.webvtt_path_string(), write_webvtt(&captions))?;
+        log::info!("Clipped {:?} ({:?} - {:?}) into {:?}", entry.dir, start, end, clipped.dir);
+        Ok(clipped)
+    }
+
+    /**
+     * Create the entry directory named by the timestamp, or the next free second. The other entry,
+     * e.g. the recording, may have the same timestamp.
+     */
+    fn create_free_entry(&self, started_at: Option<NaiveDateTime>) -> anyhow::Result<Entry> {
+        let started_at = started_at.unwrap_or_else(|| Local::now().naive_local());
+        for seconds in 0..3600 {
+            let basename = (started_at + chrono::Duration::seconds(seconds)).format("%Y%m%d%H%M%S").to_string();
+            if self.has_entry(basename.as_str()) {
+                continue;
+            }
+            let dir = self.entry_dir(basename.as_str());
+            fs::create_dir_all(dir.parent().unwrap())?;
+            // Fails if the other one created it in the meantime.
+            match fs::create_dir(&dir) {
+                Ok(()) => return Ok(Entry::new(dir)),
+                Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Err(anyhow!("Cannot find the free name for {}", started_at))
+    }
+}
+
+const TRASH_TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+fn read_trash_info(trash_path: &Path) -> anyhow::Result<TrashItem> {
+    let content = fs::read_to_string(trash_path.join(TRASH_INFO_FILE))?;
+    let mut item: TrashItem = serde_json::from_str(content.as_str())?;
+    // The trash directory may be moved with the app data directory.
+    item.path = trash_path.to_str().unwrap().to_string();
+    Ok(item)
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with('.'))
+}
+
+// "%Y%m%d"
+fn is_shard_dir(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.len() == 8 && name.chars().all(|c| c.is_ascii_digit()))
+}
+
+fn move_entry_dir(src: &Path, dest: &Path) -> anyhow::Result<()> {
+    fs::create_dir_all(dest.parent().unwrap())?;
+    if !dest.exists() {
+        // Same file system. It's atomic.
+        fs::rename(src, dest)?;
+        return Ok(());
+    }
+
+    // The previous migration was interrupted, or the entry was re-created in the new layout.
+    let mut conflicts = Vec::new();
+    for file in fs::read_dir(src)? {
+        let file = file?.path();
+        let dest_file = dest.join(file.file_name().unwrap());
+        if dest_file.exists() {
+            conflicts.push(file);
+        } else {
+            fs::rename(&file, &dest_file)?;
+        }
+    }
+    if !conflicts.is_empty() {
+        return Err(anyhow!("Files already exist in {:?}, kept in the old directory: {:?}", dest, conflicts));
+    }
+    fs::remove_dir(src)?;
+    Ok(())
+}
+
+fn parse_date(src: &Option<String>) -> anyhow::Result<Option<NaiveDate>> {
+    src.as_ref()
+        .map(|src| NaiveDate::parse_from_str(src, "%Y-%m-%d")
+            .map_err(|err| anyhow!("Invalid date({}): {:?}", src, err)))
+        .transpose()
+}
+
+fn list_item(entry: &Entry) -> EntryListItem {
+    // Broken meta.json shouldn't hide the entry from the list.
+    let meta = entry.load_meta().unwrap_or_else(|err| {
+        log::error!("Cannot load metadata of {:?}: {:?}", entry.dir, err);
+        Default::default()
+    });
+
+    let processing_state = entry.load_processing_state().unwrap_or_else(|err| {
+        log::error!("Cannot load processing state of {:?}: {:?}", entry.dir, err);
+        Default::default()
+    });
+
+    let md_path = entry.md_path();
+    let summary_excerpt = fs::read_to_string(&md_path).ok()
+        .map(|summary| summary_excerpt(summary.as_str()));
+
+    EntryListItem {
+        path: entry.dir.to_str().unwrap().to_string(),
+        basename: entry.basename.clone(),
+        title: meta.title,
+        duration_millis: meta.duration_millis,
+        has_mic_wav: entry.mic_wav_path().exists(),
+        audio_format: entry.find_encoded_audio().map(|encoded| encoded.format),
+        has_vtt: PathBuf::from(entry.webvtt_path_string()).exists(),
+        has_md: PathBuf::from(md_path).exists(),
+        summary_excerpt,
+        processing_stage: processing_state.stage,
+        processing_error: processing_state.error,
+    }
+}
+
+fn summary_excerpt(summary: &str) -> String {
+    summary.lines()
+        .map(|line| line.trim_start_matches('#').trim())
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(EXCERPT_LENGTH)
+        .collect()
 }
 
 // TODO make this private...
@@ -34,3 +574,203 @@ pub fn get_app_data_dir() -> anyhow::Result<PathBuf> {
     fs::create_dir_all(&app_data_dir)?;
     Ok(app_data_dir)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::test_support::EntryFixture;
+    use crate::entry_meta::EntryMeta;
+    use crate::processing_state::ProcessingState;
+
+    fn create_entry(repo: &DataRepo, basename: &str, duration_millis: Option<u64>, summary: Option<&str>) -> Entry {
+        EntryFixture {
+            summary,
+            meta: duration_millis.map(|duration_millis| EntryMeta { duration_millis: Some(duration_millis), ..Default::default() }),
+            ..Default::default()
+        }.create(repo.data_dir.join(basename))
+    }
+
+    fn basenames(list: &EntryList) -> Vec<&str> {
+        list.entries.iter().map(|item| item.basename.as_str()).collect()
+    }
+
+    #[test]
+    fn test_migrate_to_sharded_layout() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let repo = DataRepo::with_dir(tmp.path());
+        create_entry(&repo, "20240101100000", Some(60_000), Some("summary"));
+        create_entry(&repo, "20240101110000", None, Some("summary"));
+        create_entry(&repo, "not-a-timestamp", None, None);
+        fs::write(repo.data_dir.join(".DS_Store"), "")?;
+
+        // Interrupted in the middle of the previous migration.
+        let partial = Entry::new(repo.entry_dir("20240101110000"));
+        fs::create_dir_all(&partial.dir)?;
+        fs::write(partial.webvtt_path_string(), "WEBVTT\n")?;
+
+        // Readable before the migration
+        assert_eq!(repo.entries()?.len(), 3);
+        let mut list = repo.list_entries(&EntryQuery::default())?;
+        assert_eq!(list.total, 3);
+
+        assert_eq!(repo.migrate_to_sharded_layout()?, 2);
+        assert!(repo.data_dir.join("20240101").join("20240101100000").join("20240101100000.md").exists());
+        assert!(repo.data_dir.join("20240101").join("20240101110000").join("20240101110000.md").exists());
+        assert!(partial.dir.join("20240101110000.vtt").exists());
+        assert!(!repo.data_dir.join("20240101100000").exists());
+        assert!(!repo.data_dir.join("20240101110000").exists());
+        assert!(repo.data_dir.join("not-a-timestamp").exists());
+
+        // Idempotent
+        assert_eq!(repo.migrate_to_sharded_layout()?, 0);
+        assert_eq!(repo.migrated_entry_dir(&repo.data_dir.join("20240101100000")), Some(repo.entry_dir("20240101100000")));
+        assert_eq!(repo.migrated_entry_dir(&repo.data_dir.join("not-a-timestamp")), None);
+        assert_eq!(repo.migrated_entry_dir(&repo.entry_dir("20240101100000")), None);
+
+        list = repo.list_entries(&EntryQuery::default())?;
+        assert_eq!(list.total, 3);
+        assert_eq!(list.entries[2].basename, "20240101100000");
+        assert_eq!(list.entries[2].duration_millis, Some(60_000));
+
+        let entry = repo.new_entry()?;
+        assert_eq!(entry.dir.parent().unwrap().file_name().unwrap().to_str(), entry.shard_name().as_deref());
+        Ok(())
+    }
+
+    #[test]
+    fn test_trash() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let repo = DataRepo::with_dir(tmp.path());
+        let entry = create_entry(&repo, "20240101100000", None, Some("summary"));
+        let datetime = |src: &str| NaiveDateTime::parse_from_str(src, "%Y%m%d%H%M%S").unwrap();
+
+        let item = repo.trash_entry(&entry, datetime("20240201100000"))?;
+        assert!(!entry.dir.exists());
+        assert!(repo.entries()?.is_empty());
+        assert_eq!(repo.list_trash()?, vec![item.clone()]);
+        assert_eq!(item.deleted_at, "2024-02-01T10:00:00");
+        assert_eq!(item.original_path, entry.dir.to_str().unwrap());
+
+        // Don't clobber the entry which has the same name.
+        create_entry(&repo, "20240101100000", None, None);
+        assert!(repo.restore_from_trash(Path::new(&item.path)).is_err());
+        fs::remove_dir_all(&entry.dir)?;
+
+        let restored = repo.restore_from_trash(Path::new(&item.path))?;
+        assert_eq!(fs::read_to_string(restored.md_path())?, "summary");
+        assert!(!restored.dir.join(TRASH_INFO_FILE).exists());
+        assert!(repo.list_trash()?.is_empty());
+
+        let other = create_entry(&repo, "20240102100000", None, None);
+        repo.trash_entry(&restored, datetime("20240201100000"))?;
+        repo.trash_entry(&other, datetime("20240210100000"))?;
+        assert_eq!(repo.purge_trash(Some(datetime("20240205000000")))?, 1);
+        assert_eq!(repo.list_trash()?.iter().map(|item| item.basename.as_str()).collect::<Vec<_>>(), vec!["20240102100000"]);
+        assert_eq!(repo.purge_trash(None)?, 1);
+        assert!(repo.list_trash()?.is_empty());
+
+        // Being recorded.
+        let recording = create_entry(&repo, "20240103100000", None, None);
+        recording.save_processing_state(&ProcessingState::recording())?;
+        assert!(repo.trash_entry(&recording, datetime("20240210100000")).is_err());
+        assert!(recording.dir.exists());
+        assert!(!recording.dir.join(TRASH_INFO_FILE).exists());
+
+        // Skip the broken one, purge the others.
+        recording.save_processing_state(&ProcessingState::default())?;
+        let broken = repo.trash_entry(&recording, datetime("20240210100000"))?;
+        let mut info = read_trash_info(Path::new(&broken.path))?;
+        info.deleted_at = "broken".to_string();
+        fs::write(Path::new(&broken.path).join(TRASH_INFO_FILE), serde_json::to_string(&info)?)?;
+        repo.trash_entry(&create_entry(&repo, "20240104100000", None, None), datetime("20240210100000"))?;
+        assert_eq!(repo.purge_trash(Some(datetime("20240301000000")))?, 1);
+        assert_eq!(repo.list_trash()?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_entries() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let repo = DataRepo::with_dir(tmp.path());
+        create_entry(&repo, "20240101100000", Some(60_000), Some("# Summary\n\n- foo\n"));
+        let encoded = create_entry(&repo, "20240102100000", Some(180_000), None);
+        create_entry(&repo, "20240103100000", None, None);
+        fs::write(repo.data_dir.join(".DS_Store"), "")?;
+        // Encoded in the different formats. The format in the metadata wins.
+        fs::write(encoded.encoded_audio_path(AudioFormat::Mp3), "")?;
+        fs::write(encoded.encoded_audio_path(AudioFormat::Opus), "")?;
+        encoded.update_meta(|meta| meta.audio_format = Some(AudioFormat::Opus))?;
+
+        let list = repo.list_entries(&EntryQuery::default())?;
+        assert_eq!(list.total, 3);
+        assert_eq!(basenames(&list), vec!["20240103100000", "20240102100000", "20240101100000"]);
+        let oldest = &list.entries[2];
+        assert!(oldest.has_md);
+        assert!(!oldest.has_vtt);
+        assert_eq!(oldest.duration_millis, Some(60_000));
+        assert_eq!(oldest.summary_excerpt.as_deref(), Some("Summary - foo"));
+        // Inferred from the files
+        assert_eq!(oldest.processing_stage, ProcessingStage::Summarized);
+        assert_eq!(list.entries[0].processing_stage, ProcessingStage::Recorded);
+        assert_eq!(oldest.audio_format, None);
+        assert_eq!(list.entries[1].audio_format, Some(AudioFormat::Opus));
+
+        let list = repo.list_entries(&EntryQuery {
+            offset: 1,
+            limit: Some(1),
+            sort: EntrySortOrder::Oldest,
+            ..Default::default()
+        })?;
+        assert_eq!(list.total, 3);
+        assert_eq!(basenames(&list), vec!["20240102100000"]);
+
+        let list = repo.list_entries(&EntryQuery { sort: EntrySortOrder::Longest, ..Default::default() })?;
+        assert_eq!(basenames(&list), vec!["20240102100000", "20240101100000", "20240103100000"]);
+
+        let list = repo.list_entries(&EntryQuery {
+            since: Some("2024-01-02".to_string()),
+            until: Some("2024-01-02".to_string()),
+            ..Default::default()
+        })?;
+        assert_eq!(list.total, 1);
+        assert_eq!(basenames(&list), vec!["20240102100000"]);
+
+        assert!(repo.list_entries(&EntryQuery { since: Some("yesterday".to_string()), ..Default::default() }).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_entries() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let repo = DataRepo::with_dir(tmp.path());
+        let first = EntryFixture {
+            vtt: Some("WEBVTT\n\n00:00:01.000 --> 00:00:02.000\na\n"),
+            meta: Some(EntryMeta { title: Some("Weekly".to_string()), tags: vec!["team".to_string()], ..Default::default() }),
+            mp3_bytes: 100,
+            ..Default::default()
+        }.create(repo.entry_dir("20240101100000"));
+        let second = EntryFixture {
+            vtt: Some("WEBVTT\n\n00:00:00.000 --> 00:00:01.000\nb\n"),
+            meta: Some(EntryMeta { tags: vec!["team".to_string(), "2nd".to_string()], ..Default::default() }),
+            ..Default::default()
+        }.create(repo.entry_dir("20240101100100"));
+
+        let merged = repo.merge_entries(&second, &first)?;
+        assert_eq!(merged.basename, "20240101100001");
+        let meta = merged.load_meta()?;
+        assert_eq!(meta.title.as_deref(), Some("Weekly"));
+        assert_eq!(meta.tags, vec!["team", "2nd"]);
+        assert_eq!(merged.read_captions()?.iter().map(|caption| caption.start_time.as_secs()).collect::<Vec<_>>(), vec![1, 60]);
+        assert!(merged.audio_files()?.is_empty());
+
+        // Merged again in the same second.
+        assert_eq!(repo.merge_entries(&first, &second)?.basename, "20240101100002");
+
+        let clipped = repo.clip_entry(&merged, Duration::from_secs(30), Duration::from_secs(90))?;
+        assert_eq!(clipped.basename, "20240101100031");
+        assert_eq!(clipped.read_captions()?.iter().map(|caption| caption.start_time.as_secs()).collect::<Vec<_>>(), vec![30]);
+        assert_eq!(clipped.load_meta()?.title.as_deref(), Some("Weekly"));
+        assert!(repo.clip_entry(&merged, Duration::from_secs(30), Duration::from_secs(30)).is_err());
+        Ok(())
+    }
+}
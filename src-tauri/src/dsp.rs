@@ -0,0 +1,585 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use anyhow::{anyhow, Context, Result};
+use hound::{SampleFormat, WavSpec, WavWriter};
+use crate::capture_format::CaptureFormat;
+
+// Taps on each side of the resampling kernel, at the lower sample rate.
+const HALF_TAPS: usize = 16;
+// Kernel table entries per tap.
+const TABLE_RESOLUTION: usize = 512;
+
+// The read error which ended the file early. The iterator can't return it.
+type ReadError = Rc<RefCell<Option<anyhow::Error>>>;
+
+/**
+ * Mono audio stream. The samples are -1.0 to 1.0.
+ * The samples are streamed, the recordings of the long meetings don't fit in the memory.
+ */
+pub struct AudioSource {
+    pub sample_rate: u32,
+    samples: Box<dyn Iterator<Item = f32>>,
+    // Of the files which this source reads.
+    errors: Vec<ReadError>,
+}
+
+impl AudioSource {
+    pub fn new(sample_rate: u32, samples: impl Iterator<Item = f32> + 'static) -> AudioSource {
+        AudioSource { sample_rate, samples: Box::new(samples), errors: Vec::new() }
+    }
+
+    fn with_errors(sample_rate: u32, samples: impl Iterator<Item = f32> + 'static, errors: Vec<ReadError>) -> AudioSource {
+        AudioSource { sample_rate, samples: Box::new(samples), errors }
+    }
+
+    pub fn gain(self, factor: f32) -> AudioSource {
+        let sample_rate = self.sample_rate;
+        let errors = self.errors.clone();
+        AudioSource::with_errors(sample_rate, self.map(move |sample| sample * factor), errors)
+    }
+
+    /**
+     * Fail if the file couldn't be read to the end. Call it after reading the samples, the stream
+     * just stops at the read error.
+     */
+    pub fn check(&self) -> Result<()> {
+        check_errors(&self.errors)
+    }
+}
+
+fn check_errors(errors: &[ReadError]) -> Result<()> {
+    match errors.iter().find_map(|error| error.borrow_mut().take()) {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+impl Iterator for AudioSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        self.samples.next()
+    }
+}
+
+/**
+ * Read the raw capture of the system audio. The channels are mixed down to mono.
+ */
+pub fn open_raw(path: &Path, format: &CaptureFormat) -> Result<AudioSource> {
+    let file = File::open(path).with_context(|| format!("Cannot open {:?}", path))?;
+    let error = ReadError::default();
+    let samples = RawSamples { reader: BufReader::new(file), path: path.to_path_buf(), format: *format, error: error.clone() };
+    let downmix = Downmix { inner: Box::new(samples), channels: format.channels.max(1) as usize };
+    Ok(AudioSource::with_errors(format.sample_rate, downmix, vec![error]))
+}
+
+struct RawSamples {
+    reader: BufReader<File>,
+    path: PathBuf,
+    format: CaptureFormat,
+    error: ReadError,
+}
+
+impl RawSamples {
+    fn decode(&self, bytes: &[u8]) -> f32 {
+        let format = &self.format;
+        match (format.float, format.bits_per_sample, format.big_endian) {
+            (true, _, false) => f32::from_le_bytes(bytes.try_into().unwrap()),
+            (true, _, true) => f32::from_be_bytes(bytes.try_into().unwrap()),
+            (false, 16, false) => i16::from_le_bytes(bytes.try_into().unwrap()) as f32 / 32768.0,
+            (false, 16, true) => i16::from_be_bytes(bytes.try_into().unwrap()) as f32 / 32768.0,
+            (false, _, false) => i32::from_le_bytes(bytes.try_into().unwrap()) as f32 / 2147483648.0,
+            (false, _, true) => i32::from_be_bytes(bytes.try_into().unwrap()) as f32 / 2147483648.0,
+        }
+    }
+}
+
+impl Iterator for RawSamples {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let mut buf = [0u8; 4];
+        let bytes = &mut buf[..self.format.bytes_per_sample()];
+        match self.reader.read_exact(bytes) {
+            Ok(_) => Some(self.decode(bytes)),
+            // The last partial sample is dropped, the capture may be stopped in the middle of the write.
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => None,
+            Err(err) => {
+                *self.error.borrow_mut() = Some(anyhow!("Cannot read {:?}: {:?}", self.path, err));
+                None
+            }
+        }
+    }
+}
+
+/**
+ * Read the WAV file. The channels are mixed down to mono.
+ */
+pub fn open_wav(path: &Path) -> Result<AudioSource> {
+    let (spec, samples, error) = open_wav_interleaved(path)?;
+    let downmix = Downmix { inner: samples, channels: spec.channels.max(1) as usize };
+    Ok(AudioSource::with_errors(spec.sample_rate, downmix, vec![error]))
+}
+
+fn open_wav_interleaved(path: &Path) -> Result<(WavSpec, Box<dyn Iterator<Item = f32>>, ReadError)> {
+    let reader = hound::WavReader::open(path)
+        .with_context(|| format!("Cannot open {:?}", path))?;
+    let spec = reader.spec();
+    let display_path = path.to_path_buf();
+    let error = ReadError::default();
+    let sample_error = error.clone();
+    // hound fails on the truncated file, which is shorter than the length in the header.
+    let samples: Box<dyn Iterator<Item = f32>> = match spec.sample_format {
+        SampleFormat::Float => Box::new(reader.into_samples::<f32>()
+            .map_while(move |sample| ok_or_record(sample, &display_path, &sample_error))),
+        SampleFormat::Int => {
+            let scale = 1.0 / (1i64 << (spec.bits_per_sample - 1)) as f32;
+            Box::new(reader.into_samples::<i32>()
+                .map_while(move |sample| ok_or_record(sample, &display_path, &sample_error))
+                .map(move |sample| sample as f32 * scale))
+        }
+    };
+    Ok((spec, samples, error))
+}
+
+fn ok_or_record<T>(sample: hound::Result<T>, path: &Path, error: &ReadError) -> Option<T> {
+    match sample {
+        Ok(sample) => Some(sample),
+        Err(err) => {
+            *error.borrow_mut() = Some(anyhow!("Cannot read {:?}: {:?}", path, err));
+            None
+        }
+    }
+}
+
+struct Downmix {
+    inner: Box<dyn Iterator<Item = f32>>,
+    channels: usize,
+}
+
+impl Iterator for Downmix {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let mut sum = 0.0;
+        for _ in 0..self.channels {
+            sum += self.inner.next()?;
+        }
+        Some(sum / self.channels as f32)
+    }
+}
+
+/**
+ * Convert the sample rate by the windowed sinc interpolation.
+ * The cutoff follows the lower rate, not to fold the high frequencies back when downsampling.
+ */
+pub fn resample(source: AudioSource, sample_rate: u32) -> AudioSource {
+    if source.sample_rate == sample_rate {
+        return source;
+    }
+    let cutoff = (sample_rate as f64 / source.sample_rate as f64).min(1.0);
+    let errors = source.errors.clone();
+    let resampler = Resampler {
+        input_rate: source.sample_rate as u64,
+        output_rate: sample_rate as u64,
+        cutoff,
+        half_width: (HALF_TAPS as f64 / cutoff).ceil() as i64,
+        table: kernel_table(),
+        inner: source.fuse(),
+        buffer: VecDeque::new(),
+        buffer_start: 0,
+        input_end: None,
+        index: 0,
+    };
+    AudioSource::with_errors(sample_rate, resampler, errors)
+}
+
+// sinc(x) * hann(x / HALF_TAPS), for x = 0..HALF_TAPS. The kernel is symmetric.
+fn kernel_table() -> Vec<f64> {
+    (0..=HALF_TAPS * TABLE_RESOLUTION)
+        .map(|i| {
+            let x = i as f64 / TABLE_RESOLUTION as f64;
+            let sinc = if i == 0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+            let window = 0.5 * (1.0 + (PI * x / HALF_TAPS as f64).cos());
+            sinc * window
+        })
+        .collect()
+}
+
+struct Resampler {
+    input_rate: u64,
+    output_rate: u64,
+    // Relative to the input Nyquist frequency.
+    cutoff: f64,
+    // Input samples on each side of the kernel.
+    half_width: i64,
+    table: Vec<f64>,
+    inner: std::iter::Fuse<AudioSource>,
+    buffer: VecDeque<f32>,
+    // Input index of the buffer[0].
+    buffer_start: i64,
+    input_end: Option<i64>,
+    // Output index
+    index: u64,
+}
+
+impl Resampler {
+    fn kernel(&self, x: f64) -> f64 {
+        let position = (x * self.cutoff).abs() * TABLE_RESOLUTION as f64;
+        let i = position as usize;
+        if i + 1 >= self.table.len() {
+            return 0.0;
+        }
+        let fraction = position - i as f64;
+        self.cutoff * (self.table[i] * (1.0 - fraction) + self.table[i + 1] * fraction)
+    }
+}
+
+impl Iterator for Resampler {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        // Input position of the output sample. Not accumulated, not to drift.
+        let position = (self.index * self.input_rate) as f64 / self.output_rate as f64;
+        let center = position.floor() as i64;
+
+        while self.input_end.is_none() && self.buffer_start + (self.buffer.len() as i64) <= center + self.half_width {
+            match self.inner.next() {
+                Some(sample) => self.buffer.push_back(sample),
+                None => self.input_end = Some(self.buffer_start + self.buffer.len() as i64),
+            }
+        }
+        if let Some(end) = self.input_end {
+            if position >= end as f64 {
+                return None;
+            }
+        }
+        while self.buffer_start < center - self.half_width && !self.buffer.is_empty() {
+            self.buffer.pop_front();
+            self.buffer_start += 1;
+        }
+
+        // The samples out of the stream are silence.
+        let first = (center - self.half_width + 1).max(self.buffer_start);
+        let last = (center + self.half_width).min(self.buffer_start + self.buffer.len() as i64 - 1);
+        let mut sum = 0.0;
+        for k in first..=last {
+            let sample = self.buffer[(k - self.buffer_start) as usize] as f64;
+            sum += sample * self.kernel(position - k as f64);
+        }
+        self.index += 1;
+        Some(sum as f32)
+    }
+}
+
+/**
+ * Scale the source to the peak, like `sox norm`. `open` is called twice, to find the peak and to scale.
+ */
+pub fn normalize(open: impl Fn() -> Result<AudioSource>, peak: f32) -> Result<AudioSource> {
+    let mut source = open()?;
+    let max = source.by_ref().fold(0.0f32, |max, sample| max.max(sample.abs()));
+    source.check()?;
+    let source = open()?;
+    if max == 0.0 {
+        // silence
+        return Ok(source);
+    }
+    Ok(source.gain(peak / max))
+}
+
+/**
+ * Mix the sources like `sox -m`. Each source is scaled by 1/n not to clip.
+ * The shorter sources are padded with silence.
+ */
+pub fn mix(sources: Vec<AudioSource>) -> Result<AudioSource> {
+    let sample_rate = common_sample_rate(&sources)?;
+    let scale = 1.0 / sources.len() as f32;
+    let errors = sources.iter().flat_map(|source| source.errors.clone()).collect();
+    let mut sources = sources.into_iter().map(|source| source.fuse()).collect::<Vec<_>>();
+    let samples = std::iter::from_fn(move || {
+        let mut sum = 0.0;
+        let mut remaining = false;
+        for source in sources.iter_mut() {
+            if let Some(sample) = source.next() {
+                sum += sample;
+                remaining = true;
+            }
+        }
+        remaining.then_some(sum * scale)
+    });
+    Ok(AudioSource::with_errors(sample_rate, samples, errors))
+}
+
+fn common_sample_rate(sources: &[AudioSource]) -> Result<u32> {
+    let sample_rate = sources.first()
+        .ok_or_else(|| anyhow!("No audio sources"))?
+        .sample_rate;
+    if let Some(source) = sources.iter().find(|source| source.sample_rate != sample_rate) {
+        return Err(anyhow!("Cannot combine the different sample rates: {} and {}", sample_rate, source.sample_rate));
+    }
+    Ok(sample_rate)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WavFormat {
+    Float32,
+    // whisper.cpp requires 16bit PCM.
+    Int16,
+    // flac doesn't read the float.
+    Int24,
+}
+
+/**
+ * Write the mono WAV file. Returns the number of the samples.
+ */
+pub fn write_wav(source: AudioSource, path: &Path, format: WavFormat) -> Result<u64> {
+    write_wav_channels(vec![source], path, format)
+}
+
+/**
+ * Write the sources to the channels, e.g. the mic on the left and the system audio on the right.
+ * The shorter sources are padded with silence. Returns the number of the frames.
+ */
+pub fn write_wav_channels(sources: Vec<AudioSource>, path: &Path, format: WavFormat) -> Result<u64> {
+    let sample_rate = common_sample_rate(&sources)?;
+    let mut writer = WavWriter::create(path, wav_spec(sources.len() as u16, sample_rate, format))
+        .with_context(|| format!("Cannot create {:?}", path))?;
+    let errors = sources.iter().flat_map(|source| source.errors.clone()).collect::<Vec<_>>();
+    let mut sources = sources.into_iter().map(|source| source.fuse()).collect::<Vec<_>>();
+    let mut count = 0;
+    loop {
+        let frame = sources.iter_mut().map(|source| source.next()).collect::<Vec<_>>();
+        if frame.iter().all(|sample| sample.is_none()) {
+            break;
+        }
+        for sample in frame {
+            write_sample(&mut writer, sample.unwrap_or(0.0), format)?;
+        }
+        count += 1;
+    }
+    writer.finalize()?;
+    check_errors(&errors)?;
+    Ok(count)
+}
+
+/**
+ * Convert the sample format of the WAV file. The channels are kept.
+ */
+pub fn convert_wav(input: &Path, output: &Path, format: WavFormat) -> Result<()> {
+    let (spec, samples, error) = open_wav_interleaved(input)?;
+    let mut writer = WavWriter::create(output, wav_spec(spec.channels, spec.sample_rate, format))
+        .with_context(|| format!("Cannot create {:?}", output))?;
+    for sample in samples {
+        write_sample(&mut writer, sample, format)?;
+    }
+    writer.finalize()?;
+    check_errors(&[error])?;
+    Ok(())
+}
+
+fn wav_spec(channels: u16, sample_rate: u32, format: WavFormat) -> WavSpec {
+    WavSpec {
+        channels,
+        sample_rate,
+        bits_per_sample: match format {
+            WavFormat::Float32 => 32,
+            WavFormat::Int16 => 16,
+            WavFormat::Int24 => 24,
+        },
+        sample_format: match format {
+            WavFormat::Float32 => SampleFormat::Float,
+            WavFormat::Int16 | WavFormat::Int24 => SampleFormat::Int,
+        },
+    }
+}
+
+fn write_sample<W: Write + Seek>(writer: &mut WavWriter<W>, sample: f32, format: WavFormat) -> Result<()> {
+    match format {
+        WavFormat::Float32 => writer.write_sample(sample)?,
+        WavFormat::Int16 => writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16)?,
+        WavFormat::Int24 => writer.write_sample((sample.clamp(-1.0, 1.0) * 8388607.0).round() as i32)?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn sine(sample_rate: u32, frequency: f32, seconds: f32, amplitude: f32) -> Vec<f32> {
+        let len = (sample_rate as f32 * seconds) as usize;
+        (0..len)
+            .map(|i| amplitude * (2.0 * std::f32::consts::PI * frequency * i as f32 / sample_rate as f32).sin())
+            .collect()
+    }
+
+    fn source(sample_rate: u32, samples: Vec<f32>) -> AudioSource {
+        AudioSource::new(sample_rate, samples.into_iter())
+    }
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples.iter().fold(0.0f32, |max, sample| max.max(sample.abs()))
+    }
+
+    fn zero_crossings(samples: &[f32]) -> usize {
+        samples.windows(2).filter(|w| (w[0] < 0.0) != (w[1] < 0.0)).count()
+    }
+
+    #[test]
+    fn test_open_raw() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("a.raw");
+        let mut bytes = [0.5f32, -0.25, 1.0].iter().flat_map(|s| s.to_le_bytes()).collect::<Vec<_>>();
+        // partial sample at the end
+        bytes.extend_from_slice(&[0, 0]);
+        fs::write(&path, bytes)?;
+
+        let source = open_raw(&path, &CaptureFormat::legacy())?;
+        assert_eq!(source.sample_rate, 48000);
+        assert_eq!(source.collect::<Vec<_>>(), vec![0.5, -0.25, 1.0]);
+
+        // 16bit big endian stereo
+        let path = tmp.path().join("b.raw");
+        fs::write(&path, [16384i16, 0, -32768, -32768].iter().flat_map(|s| s.to_be_bytes()).collect::<Vec<_>>())?;
+        let format = CaptureFormat { sample_rate: 44100, channels: 2, bits_per_sample: 16, float: false, big_endian: true };
+        let source = open_raw(&path, &format)?;
+        assert_eq!(source.sample_rate, 44100);
+        assert_eq!(source.collect::<Vec<_>>(), vec![0.25, -1.0]);
+
+        assert!(open_raw(&tmp.path().join("missing.raw"), &format).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_wav() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("stereo.wav");
+        let spec = WavSpec { channels: 2, sample_rate: 44100, bits_per_sample: 16, sample_format: SampleFormat::Int };
+        let mut writer = WavWriter::create(&path, spec)?;
+        for (left, right) in [(16384i16, 0i16), (-32768, -32768)] {
+            writer.write_sample(left)?;
+            writer.write_sample(right)?;
+        }
+        writer.finalize()?;
+
+        let mut source = open_wav(&path)?;
+        assert_eq!(source.sample_rate, 44100);
+        assert_eq!(source.by_ref().collect::<Vec<_>>(), vec![0.25, -1.0]);
+        assert!(source.check().is_ok());
+
+        // Shorter than the header says.
+        let bytes = fs::read(&path)?;
+        fs::write(&path, &bytes[..bytes.len() - 2])?;
+        let mut source = open_wav(&path)?;
+        assert_eq!(source.by_ref().collect::<Vec<_>>(), vec![0.25]);
+        assert!(source.check().is_err());
+        assert!(write_wav(open_wav(&path)?, &tmp.path().join("out.wav"), WavFormat::Float32).is_err());
+        assert!(convert_wav(&path, &tmp.path().join("out.wav"), WavFormat::Int24).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_wav() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let samples = vec![0.0, 0.5, -1.0, 2.0];
+        for (format, expected) in [
+            (WavFormat::Float32, vec![0.0, 0.5, -1.0, 2.0]),
+            // clipped, and rounded to 16bit
+            (WavFormat::Int16, vec![0.0, 16384.0 / 32768.0, -32767.0 / 32768.0, 32767.0 / 32768.0]),
+            (WavFormat::Int24, vec![0.0, 4194304.0 / 8388608.0, -8388607.0 / 8388608.0, 8388607.0 / 8388608.0]),
+        ] {
+            let path = tmp.path().join(format!("{:?}.wav", format));
+            assert_eq!(write_wav(source(16000, samples.clone()), &path, format)?, 4);
+            let read = open_wav(&path)?;
+            assert_eq!(read.sample_rate, 16000);
+            assert_eq!(read.collect::<Vec<_>>(), expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_wav_channels() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("stereo.wav");
+        let frames = write_wav_channels(vec![
+            source(48000, vec![0.5, 0.5, 0.5]),
+            source(48000, vec![-0.5]),
+        ], &path, WavFormat::Float32)?;
+        assert_eq!(frames, 3);
+
+        let reader = hound::WavReader::open(&path)?;
+        assert_eq!(reader.spec().channels, 2);
+        assert_eq!(reader.duration(), 3);
+        let samples = reader.into_samples::<f32>().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(samples, vec![0.5, -0.5, 0.5, 0.0, 0.5, 0.0]);
+
+        // The channels are kept.
+        let converted = tmp.path().join("converted.wav");
+        convert_wav(&path, &converted, WavFormat::Int24)?;
+        let reader = hound::WavReader::open(&converted)?;
+        assert_eq!(reader.spec(), wav_spec(2, 48000, WavFormat::Int24));
+        let samples = reader.into_samples::<i32>().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(samples, vec![4194304, -4194304, 4194304, 0, 4194304, 0]);
+
+        assert!(write_wav_channels(vec![source(48000, vec![]), source(16000, vec![])], &path, WavFormat::Int16).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_resample() {
+        // 1kHz at 48kHz to 16kHz
+        let samples = resample(source(48000, sine(48000, 1000.0, 1.0, 0.5)), 16000).collect::<Vec<_>>();
+        assert_eq!(samples.len(), 16000);
+        assert!((1998..=2001).contains(&zero_crossings(&samples)), "{}", zero_crossings(&samples));
+        // away from the edges
+        assert!((peak(&samples[100..15900]) - 0.5).abs() < 0.01, "{}", peak(&samples[100..15900]));
+
+        // 44.1kHz to 48kHz
+        let samples = resample(source(44100, sine(44100, 440.0, 1.0, 0.5)), 48000).collect::<Vec<_>>();
+        assert_eq!(samples.len(), 48000);
+        assert!((879..=881).contains(&zero_crossings(&samples)), "{}", zero_crossings(&samples));
+        assert!((peak(&samples[100..47900]) - 0.5).abs() < 0.01, "{}", peak(&samples[100..47900]));
+
+        // same rate
+        assert_eq!(resample(source(16000, vec![0.1, 0.2]), 16000).collect::<Vec<_>>(), vec![0.1, 0.2]);
+    }
+
+    #[test]
+    fn test_resample_aliasing() {
+        // 10kHz is over the Nyquist frequency of 16kHz. It must be filtered, not folded back to 6kHz.
+        let samples = resample(source(48000, sine(48000, 10000.0, 1.0, 0.5)), 16000).collect::<Vec<_>>();
+        assert!(peak(&samples[100..15900]) < 0.05, "{}", peak(&samples[100..15900]));
+    }
+
+    #[test]
+    fn test_normalize() -> Result<()> {
+        let samples = normalize(|| Ok(source(48000, sine(48000, 1000.0, 0.1, 0.25))), 1.0)?
+            .collect::<Vec<_>>();
+        assert!((peak(&samples) - 1.0).abs() < 1e-6);
+
+        // silence stays silent
+        let samples = normalize(|| Ok(source(48000, vec![0.0; 10])), 1.0)?.collect::<Vec<_>>();
+        assert_eq!(samples, vec![0.0; 10]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_mix() -> Result<()> {
+        let mixed = mix(vec![
+            source(48000, vec![1.0, 1.0, 1.0]),
+            source(48000, vec![-0.5, 0.5]),
+        ])?;
+        assert_eq!(mixed.sample_rate, 48000);
+        assert_eq!(mixed.collect::<Vec<_>>(), vec![0.25, 0.75, 0.5]);
+
+        assert!(mix(vec![source(48000, vec![]), source(44100, vec![])]).is_err());
+        assert!(mix(vec![]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,260 @@
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::thread::JoinHandle;
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use crate::dsp;
+use crate::dsp::WavFormat;
+use crate::postprocess::MissingCommand;
+
+/**
+ * Format of the encoded recording, which is kept after the cleanup.
+ */
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AudioFormat {
+    #[default]
+    Mp3,
+    // Opus in Ogg. Much smaller than MP3 for the speech at the same quality.
+    Opus,
+    // Lossless
+    Flac,
+    // AAC in MP4, by afconvert of macOS.
+    Aac,
+}
+
+impl AudioFormat {
+    pub const ALL: [AudioFormat; 4] = [AudioFormat::Mp3, AudioFormat::Opus, AudioFormat::Flac, AudioFormat::Aac];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            AudioFormat::Mp3 => "mp3",
+            AudioFormat::Opus => "opus",
+            AudioFormat::Flac => "flac",
+            AudioFormat::Aac => "m4a",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChannelMode {
+    #[default]
+    Mono,
+    // The mic on the left, the system audio on the right. They can be split again, e.g. to
+    // transcribe each speaker.
+    Stereo,
+}
+
+pub trait Encoder {
+    fn encode(&self, wav_file: &str, out_file: &str, on_progress: &dyn Fn(f32)) -> Result<()>;
+}
+
+pub struct Mp3Encoder {
+    pub bitrate_kbps: u32,
+    pub channel_mode: ChannelMode,
+}
+
+impl Encoder for Mp3Encoder {
+    fn encode(&self, wav_file: &str, out_file: &str, on_progress: &dyn Fn(f32)) -> Result<()> {
+        let mut command = Command::new("lame");
+        command
+            .arg("--verbose")
+            .arg("-v")
+            .arg("--abr")
+            .arg(self.bitrate_kbps.to_string())
+            .arg("-m")
+            .arg(match self.channel_mode {
+                ChannelMode::Mono => "m",
+                // Not joint stereo, the channels are the different speakers.
+                ChannelMode::Stereo => "s",
+            })
+            .arg("-")
+            .arg(out_file);
+        feed_command(command, wav_file, on_progress)
+    }
+}
+
+pub struct OpusEncoder {
+    pub bitrate_kbps: u32,
+    pub channel_mode: ChannelMode,
+}
+
+impl Encoder for OpusEncoder {
+    fn encode(&self, wav_file: &str, out_file: &str, on_progress: &dyn Fn(f32)) -> Result<()> {
+        let mut command = Command::new("opusenc");
+        command
+            .arg("--bitrate")
+            .arg(self.bitrate_kbps.to_string());
+        if self.channel_mode == ChannelMode::Mono {
+            command.arg("--downmix-mono");
+        }
+        command.arg("-").arg(out_file);
+        feed_command(command, wav_file, on_progress)
+    }
+}
+
+/**
+ * The bitrate is not configurable, it's lossless.
+ */
+pub struct FlacEncoder {
+    pub channel_mode: ChannelMode,
+}
+
+impl Encoder for FlacEncoder {
+    fn encode(&self, wav_file: &str, out_file: &str, on_progress: &dyn Fn(f32)) -> Result<()> {
+        // flac doesn't read the float wave files.
+        let pcm_tmp = tempfile::Builder::new()
+            .suffix(".wav")
+            .rand_bytes(5)
+            .tempfile()?;
+        if self.channel_mode == ChannelMode::Mono {
+            dsp::write_wav(dsp::open_wav(Path::new(wav_file))?, pcm_tmp.path(), WavFormat::Int24)?;
+        } else {
+            dsp::convert_wav(Path::new(wav_file), pcm_tmp.path(), WavFormat::Int24)?;
+        }
+
+        let mut command = Command::new("flac");
+        command
+            .arg("--best")
+            .arg("--force")
+            .arg("-o")
+            .arg(out_file)
+            .arg("-");
+        feed_command(command, pcm_tmp.path().to_str().unwrap(), on_progress)
+    }
+}
+
+pub struct AacEncoder {
+    pub bitrate_kbps: u32,
+    pub channel_mode: ChannelMode,
+}
+
+impl Encoder for AacEncoder {
+    fn encode(&self, wav_file: &str, out_file: &str, on_progress: &dyn Fn(f32)) -> Result<()> {
+        // afconvert doesn't read the stdin. No progress until it finishes.
+        let mut command = Command::new("afconvert");
+        command
+            .arg("-f").arg("m4af")
+            .arg("-d").arg("aac")
+            .arg("-b").arg((self.bitrate_kbps * 1000).to_string());
+        if self.channel_mode == ChannelMode::Mono {
+            command.arg("-c").arg("1");
+        }
+        command.arg(wav_file).arg(out_file);
+
+        let output = command.output().map_err(|err| missing_command(&command, err))?;
+        if !output.status.success() {
+            return Err(anyhow!("Failed to convert to aac: {}, {}",
+                String::from_utf8_lossy(&output.stderr),
+                String::from_utf8_lossy(&output.stdout),
+            ));
+        }
+        on_progress(100.0);
+        log::info!("Converted {} to {}", wav_file, out_file);
+        Ok(())
+    }
+}
+
+fn missing_command(command: &Command, err: std::io::Error) -> anyhow::Error {
+    match err.kind() {
+        std::io::ErrorKind::NotFound => anyhow::Error::new(MissingCommand(command.get_program().to_string_lossy().to_string())),
+        _ => err.into(),
+    }
+}
+
+/**
+ * Feed the wave file via stdin, to know how much the encoder has processed.
+ */
+fn feed_command(mut command: Command, wav_file: &str, on_progress: &dyn Fn(f32)) -> Result<()> {
+    let mut input = File::open(wav_file)
+        .map_err(|err| anyhow!("Cannot open {}: {:?}", wav_file, err))?;
+    let total = input.metadata()?.len().max(1);
+
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| missing_command(&command, err))?;
+
+    // Drain the outputs, the encoder blocks if the pipes are full.
+    let stdout_reader = read_in_background(child.stdout.take().unwrap());
+    let stderr_reader = read_in_background(child.stderr.take().unwrap());
+
+    let mut stdin = child.stdin.take().unwrap();
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut processed = 0u64;
+    let write_result = loop {
+        let n = match input.read(&mut buf) {
+            Ok(n) => n,
+            Err(err) => {
+                // Don't leave the encoder waiting for the rest of the input.
+                if let Err(err) = child.kill() {
+                    log::error!("Cannot kill {:?}: {:?}", command.get_program(), err);
+                }
+                if let Err(err) = child.wait() {
+                    log::error!("Cannot wait {:?}: {:?}", command.get_program(), err);
+                }
+                return Err(anyhow!("Cannot read {}: {:?}", wav_file, err));
+            }
+        };
+        if n == 0 {
+            break Ok(());
+        }
+        // The encoder exits on the broken input. The reason is in the stderr.
+        if let Err(err) = stdin.write_all(&buf[..n]) {
+            break Err(err);
+        }
+        processed += n as u64;
+        on_progress(processed as f32 * 100.0 / total as f32);
+    };
+    drop(stdin);
+
+    let status = child.wait()?;
+    let stdout = stdout_reader.join().unwrap_or_default();
+    let stderr = stderr_reader.join().unwrap_or_default();
+    if !status.success() || write_result.is_err() {
+        return Err(anyhow!("Failed to encode by {:?}: {}, {}, {:?}",
+            command.get_program(),
+            String::from_utf8_lossy(&stderr),
+            String::from_utf8_lossy(&stdout),
+            write_result
+        ));
+    }
+
+    log::info!("Converted {} by {:?}", wav_file, command);
+    Ok(())
+}
+
+fn read_in_background<R: Read + Send + 'static>(mut reader: R) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = reader.read_to_end(&mut buf);
+        buf
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audio_format() {
+        assert_eq!(serde_json::to_string(&AudioFormat::Opus).unwrap(), "\"Opus\"");
+        let extensions = AudioFormat::ALL.iter().map(|format| format.extension()).collect::<Vec<_>>();
+        assert_eq!(extensions, vec!["mp3", "opus", "flac", "m4a"]);
+    }
+
+    #[test]
+    fn test_missing_command() {
+        let tmp = tempfile::tempdir().unwrap();
+        let wav_file = tmp.path().join("in.wav");
+        std::fs::write(&wav_file, b"RIFF").unwrap();
+        let mut command = Command::new("meetnote-missing-encoder");
+        command.arg("-");
+        let err = feed_command(command, wav_file.to_str().unwrap(), &|_| {}).unwrap_err();
+        assert_eq!(err.downcast_ref::<MissingCommand>().unwrap().0, "meetnote-missing-encoder");
+    }
+}
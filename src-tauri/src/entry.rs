@@ -1,6 +1,28 @@
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use anyhow::anyhow;
+use chrono::NaiveDateTime;
 use glob::Paths;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use crate::encoder::AudioFormat;
+use crate::entry_meta::EntryMeta;
+use crate::processing_state::{ProcessingStage, ProcessingState};
+use crate::transcript_export::ExportFormat;
+use crate::webvtt::{Caption, parse_webvtt, validate_captions, write_webvtt};
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct EncodedAudio {
+    pub format: AudioFormat,
+    pub path: PathBuf,
+}
+
+lazy_static! {
+    // The postprocess steps and the UI update the metadata of the same entry concurrently.
+    static ref META_LOCKS: Mutex<HashMap<PathBuf, Arc<Mutex<()>>>> = Mutex::new(HashMap::new());
+}
 
 #[derive(Debug)]
 pub struct Entry {
@@ -14,6 +36,20 @@ impl Entry {
         Entry { dir, basename }
     }
 
+    /**
+     * The time when the recording started. It's encoded in the basename.
+     */
+    pub fn started_at(&self) -> Option<NaiveDateTime> {
+        NaiveDateTime::parse_from_str(self.basename.as_str(), "%Y%m%d%H%M%S").ok()
+    }
+
+    /**
+     * Name of the per-day directory which contains this entry, e.g. "20240101".
+     */
+    pub fn shard_name(&self) -> Option<String> {
+        self.started_at().map(|started_at| started_at.format("%Y%m%d").to_string())
+    }
+
     pub fn mic_wav_path(&self) -> PathBuf {
         self.path("mic.wav")
     }
@@ -34,6 +70,22 @@ impl Entry {
         Ok(paths)
     }
 
+    /**
+     * The audio files which exist. The raw captures, the WAV files and the encoded files.
+     */
+    pub fn audio_files(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let mut files: Vec<PathBuf> = self.list_raw_files()?
+            .filter_map(|path| path.ok())
+            .collect();
+        let encoded = AudioFormat::ALL.iter().map(|format| self.encoded_audio_path(*format));
+        for path in [self.mic_wav_path(), self.path("wav")].into_iter().chain(encoded) {
+            if path.exists() {
+                files.push(path);
+            }
+        }
+        Ok(files)
+    }
+
     pub fn raw_prefix_path_string(&self) -> String {
         return self.dir.join(&self.basename).to_str().unwrap().to_string();
     }
@@ -42,8 +94,140 @@ impl Entry {
         self.path("vtt").to_str().unwrap().to_string()
     }
 
+    // The transcript before the cleanup.
+    pub fn original_webvtt_path_string(&self) -> String {
+        self.path("orig.vtt").to_str().unwrap().to_string()
+    }
+
+    pub fn read_captions(&self) -> anyhow::Result<Vec<Caption>> {
+        let vtt_file = self.webvtt_path_string();
+        let content = fs::read_to_string(&vtt_file)
+            .map_err(|err| anyhow!("Cannot read VTT file({}): {:?}", vtt_file, err))?;
+        let captions = parse_webvtt(content.as_str())
+            .map_err(|err| anyhow!("Cannot parse VTT file({}): {}", vtt_file, err))?;
+        Ok(captions)
+    }
+
+    /**
+     * Validate the captions and write them to the VTT file atomically.
+     */
+    pub fn save_captions(&self, captions: &[Caption]) -> anyhow::Result<()> {
+        validate_captions(captions)?;
+        write_atomically(&self.path("vtt"), write_webvtt(captions).as_str())?;
+        log::info!("Saved {} captions to {:?}", captions.len(), self.webvtt_path_string());
+        Ok(())
+    }
+
+    pub fn transcript_export_path(&self, format: ExportFormat) -> PathBuf {
+        self.path(format.extension())
+    }
+
+    pub fn meta_path(&self) -> PathBuf {
+        self.dir.join("meta.json")
+    }
+
+    /**
+     * Load `meta.json`. Returns the empty metadata if the file doesn't exist yet.
+     */
+    pub fn load_meta(&self) -> anyhow::Result<EntryMeta> {
+        let meta_path = self.meta_path();
+        if !meta_path.exists() {
+            return Ok(EntryMeta::default());
+        }
+
+        let content = fs::read_to_string(&meta_path)
+            .map_err(|err| anyhow!("Cannot read metadata({:?}): {:?}", meta_path, err))?;
+        let meta: EntryMeta = serde_json::from_str(content.as_str())
+            .map_err(|err| anyhow!("Cannot parse metadata({:?}): {:?}", meta_path, err))?;
+        Ok(meta)
+    }
+
+    pub fn save_meta(&self, meta: &EntryMeta) -> anyhow::Result<()> {
+        log::info!("Saving metadata: {:?} to {:?}", meta, self.meta_path());
+        let content = serde_json::to_string_pretty(meta)?;
+        write_atomically(&self.meta_path(), content.as_str())
+    }
+
+    /**
+     * Load, modify and save the metadata. The updates of the same entry are serialized, not to
+     * lose the fields written by the others.
+     */
+    pub fn update_meta<F: FnOnce(&mut EntryMeta)>(&self, f: F) -> anyhow::Result<()> {
+        let lock = META_LOCKS.lock().unwrap()
+            .entry(self.dir.clone())
+            .or_default()
+            .clone();
+        let _guard = lock.lock().unwrap();
+        let mut meta = self.load_meta()?;
+        f(&mut meta);
+        self.save_meta(&meta)
+    }
+
+    pub fn processing_state_path(&self) -> PathBuf {
+        self.dir.join("processing.json")
+    }
+
+    /**
+     * Load `processing.json`. The entries processed before it was introduced don't have it,
+     * the stage is guessed from the files.
+     */
+    pub fn load_processing_state(&self) -> anyhow::Result<ProcessingState> {
+        let path = self.processing_state_path();
+        if !path.exists() {
+            let stage = if Path::new(&self.md_path()).exists() {
+                ProcessingStage::Summarized
+            } else if Path::new(&self.webvtt_path_string()).exists() {
+                ProcessingStage::Transcribed
+            } else if self.find_encoded_audio().is_some() {
+                ProcessingStage::Encoded
+            } else if Path::new(&self.merged_wav_path_string()).exists() {
+                ProcessingStage::Merged
+            } else {
+                ProcessingStage::Recorded
+            };
+            return Ok(ProcessingState { stage, ..Default::default() });
+        }
+
+        let content = fs::read_to_string(&path)
+            .map_err(|err| anyhow!("Cannot read processing state({:?}): {:?}", path, err))?;
+        let state: ProcessingState = serde_json::from_str(content.as_str())
+            .map_err(|err| anyhow!("Cannot parse processing state({:?}): {:?}", path, err))?;
+        Ok(state)
+    }
+
+    pub fn save_processing_state(&self, state: &ProcessingState) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(state)?;
+        write_atomically(&self.processing_state_path(), content.as_str())
+    }
+
+    pub fn update_processing_state<F: FnOnce(&mut ProcessingState)>(&self, f: F) -> anyhow::Result<()> {
+        let mut state = self.load_processing_state()?;
+        f(&mut state);
+        self.save_processing_state(&state)
+    }
+
     pub fn mp3_path_string(&self) -> String {
-        self.path("mp3").to_str().unwrap().to_string()
+        self.encoded_audio_path_string(AudioFormat::Mp3)
+    }
+
+    pub fn encoded_audio_path(&self, format: AudioFormat) -> PathBuf {
+        self.path(format.extension())
+    }
+
+    pub fn encoded_audio_path_string(&self, format: AudioFormat) -> String {
+        self.encoded_audio_path(format).to_str().unwrap().to_string()
+    }
+
+    /**
+     * The encoded recording which exists. The format in the metadata is preferred, the format may be
+     * changed after the entry was encoded.
+     */
+    pub fn find_encoded_audio(&self) -> Option<EncodedAudio> {
+        let recorded = self.load_meta().ok().and_then(|meta| meta.audio_format);
+        recorded.into_iter()
+            .chain(AudioFormat::ALL)
+            .map(|format| EncodedAudio { format, path: self.encoded_audio_path(format) })
+            .find(|encoded| encoded.path.exists())
     }
 
     pub fn md_path(&self) -> String {
@@ -55,3 +239,97 @@ impl Entry {
         self.dir.join(filename)
     }
 }
+
+pub(crate) fn write_atomically(path: &Path, content: &str) -> anyhow::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+
+    // Write the data to the temp file
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(&tmp_path, content)?;
+
+    // Atomically replace the old file with the temp file
+    fs::rename(tmp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::*;
+    use crate::webvtt::interleave_captions;
+
+    #[test]
+    fn test_save_interleaved_captions() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let entry = Entry::new(tmp.path().join("20240101100000"));
+        let ms = Duration::from_millis;
+        // Both speakers talk at once.
+        let captions = interleave_captions(vec![
+            vec![Caption::new(ms(0), ms(3000), "a".to_string()).with_speaker("Me")],
+            vec![Caption::new(ms(1000), ms(2000), "b".to_string()).with_speaker("Others")],
+        ]);
+
+        entry.save_captions(&captions)?;
+        assert_eq!(entry.read_captions()?, captions);
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_meta_concurrently() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let dir = tmp.path().join("20240101100000");
+        fs::create_dir_all(&dir)?;
+
+        std::thread::scope(|scope| {
+            for i in 0..8 {
+                let entry = Entry::new(dir.clone());
+                scope.spawn(move || entry.update_meta(|meta| meta.tags.push(i.to_string())).unwrap());
+            }
+        });
+
+        let mut tags = Entry::new(dir).load_meta()?.tags;
+        tags.sort();
+        assert_eq!(tags, (0..8).map(|i| i.to_string()).collect::<Vec<_>>());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod test_support {
+    use std::fs;
+    use std::path::PathBuf;
+    use crate::entry::Entry;
+    use crate::entry_meta::EntryMeta;
+
+    /**
+     * Files of the entry for the tests. Only the given files are written.
+     */
+    #[derive(Default)]
+    pub struct EntryFixture<'a> {
+        pub vtt: Option<&'a str>,
+        pub summary: Option<&'a str>,
+        pub mp3_bytes: usize,
+        pub meta: Option<EntryMeta>,
+    }
+
+    impl EntryFixture<'_> {
+        pub fn create(&self, dir: PathBuf) -> Entry {
+            let entry = Entry::new(dir);
+            fs::create_dir_all(&entry.dir).unwrap();
+            if let Some(vtt) = self.vtt {
+                fs::write(entry.webvtt_path_string(), vtt).unwrap();
+            }
+            if let Some(summary) = self.summary {
+                fs::write(entry.md_path(), summary).unwrap();
+            }
+            if self.mp3_bytes > 0 {
+                fs::write(entry.mp3_path_string(), vec![1u8; self.mp3_bytes]).unwrap();
+            }
+            if let Some(meta) = &self.meta {
+                entry.save_meta(meta).unwrap();
+            }
+            entry
+        }
+    }
+}
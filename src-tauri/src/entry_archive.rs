@@ -0,0 +1,350 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use anyhow::anyhow;
+use chrono::NaiveDateTime;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use crate::data_repo::DataRepo;
+use crate::entry::Entry;
+
+const MANIFEST_FILE: &str = "manifest.json";
+const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum ArchiveFormat {
+    Zip,
+    TarGz,
+}
+
+impl ArchiveFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::TarGz => "tar.gz",
+        }
+    }
+
+    fn from_path(path: &Path) -> anyhow::Result<ArchiveFormat> {
+        let name = path.file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("");
+        if name.ends_with(".zip") {
+            Ok(ArchiveFormat::Zip)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Ok(ArchiveFormat::TarGz)
+        } else {
+            Err(anyhow!("Unknown archive format: {:?}", path))
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ManifestFile {
+    pub name: String,
+    pub size: u64,
+}
+
+/**
+ * `manifest.json` in the archive. The archive contains `{basename}/manifest.json` and
+ * `{basename}/{files}`.
+ */
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ArchiveManifest {
+    pub version: u32,
+    pub basename: String,
+    pub app_version: String,
+    pub exported_at: String,
+    pub files: Vec<ManifestFile>,
+}
+
+/**
+ * Pack the entry directory into `{output_dir}/{basename}.{zip,tar.gz}`.
+ */
+pub fn export_entry(entry: &Entry, format: ArchiveFormat, output_dir: &Path) -> anyhow::Result<PathBuf> {
+    let mut files = Vec::new();
+    for dir_entry in fs::read_dir(&entry.dir)? {
+        let path = dir_entry?.path();
+        let name = path.file_name().unwrap().to_str().unwrap().to_string();
+        // Skip the half-written files.
+        if !path.is_file() || name.ends_with(".tmp") {
+            continue;
+        }
+        files.push(ManifestFile { name, size: fs::metadata(&path)?.len() });
+    }
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let manifest = ArchiveManifest {
+        version: MANIFEST_VERSION,
+        basename: entry.basename.clone(),
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        exported_at: chrono::Local::now().to_rfc3339(),
+        files,
+    };
+    let manifest_json = serde_json::to_string_pretty(&manifest)?;
+
+    fs::create_dir_all(output_dir)?;
+    let output = output_dir.join(format!("{}.{}", entry.basename, format.extension()));
+    let file = File::create(&output)
+        .map_err(|err| anyhow!("Cannot create archive({:?}): {:?}", output, err))?;
+    match format {
+        ArchiveFormat::Zip => {
+            let mut zip = zip::ZipWriter::new(file);
+            let options = zip::write::SimpleFileOptions::default()
+                .compression_method(zip::CompressionMethod::Deflated);
+            zip.start_file(format!("{}/{}", entry.basename, MANIFEST_FILE), options)?;
+            io::Write::write_all(&mut zip, manifest_json.as_bytes())?;
+            for file in &manifest.files {
+                zip.start_file(format!("{}/{}", entry.basename, file.name), options)?;
+                io::copy(&mut File::open(entry.dir.join(&file.name))?, &mut zip)?;
+            }
+            zip.finish()?;
+        }
+        ArchiveFormat::TarGz => {
+            let mut tar = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+            let mut header = tar::Header::new_gnu();
+            header.set_size(manifest_json.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(chrono::Local::now().timestamp() as u64);
+            header.set_cksum();
+            tar.append_data(&mut header, format!("{}/{}", entry.basename, MANIFEST_FILE), manifest_json.as_bytes())?;
+            for file in &manifest.files {
+                tar.append_path_with_name(entry.dir.join(&file.name), format!("{}/{}", entry.basename, file.name))?;
+            }
+            tar.into_inner()?.finish()?;
+        }
+    }
+
+    log::info!("Exported {:?} to {:?}", entry.dir, output);
+    Ok(output)
+}
+
+/**
+ * Unpack the archive into the data repository as the new entry.
+ *
+ * If the entry with the same basename exists already, the imported entry is renamed to the next
+ * free timestamp. The existing entry is never overwritten.
+ */
+pub fn import_entry(data_repo: &DataRepo, archive: &Path) -> anyhow::Result<Entry> {
+    let format = ArchiveFormat::from_path(archive)?;
+
+    fs::create_dir_all(&data_repo.data_dir)?;
+    // In the data directory to rename it into the place. DataRepo ignores the hidden directories.
+    let staging = tempfile::Builder::new()
+        .prefix(".import-")
+        .tempdir_in(&data_repo.data_dir)?;
+
+    // The directory of the files in the archive. It must be the basename in the manifest.
+    let mut archived_dirs: Vec<String> = Vec::new();
+    let file = File::open(archive)
+        .map_err(|err| anyhow!("Cannot open archive({:?}): {:?}", archive, err))?;
+    match format {
+        ArchiveFormat::Zip => {
+            let mut zip = zip::ZipArchive::new(file)?;
+            for i in 0..zip.len() {
+                let mut zip_file = zip.by_index(i)?;
+                if zip_file.is_dir() {
+                    continue;
+                }
+                let (dir, name) = archived_file_name(zip_file.name())?;
+                if !archived_dirs.iter().any(|archived_dir| archived_dir == dir) {
+                    archived_dirs.push(dir.to_string());
+                }
+                let path = staging.path().join(name);
+                io::copy(&mut zip_file, &mut File::create(path)?)?;
+            }
+        }
+        ArchiveFormat::TarGz => {
+            let mut tar = tar::Archive::new(GzDecoder::new(file));
+            for tar_entry in tar.entries()? {
+                let mut tar_entry = tar_entry?;
+                if !tar_entry.header().entry_type().is_file() {
+                    continue;
+                }
+                let path = tar_entry.path()?.to_str()
+                    .ok_or_else(|| anyhow!("Invalid file name in the archive"))?
+                    .to_string();
+                let (dir, name) = archived_file_name(path.as_str())?;
+                if !archived_dirs.iter().any(|archived_dir| archived_dir == dir) {
+                    archived_dirs.push(dir.to_string());
+                }
+                io::copy(&mut tar_entry, &mut File::create(staging.path().join(name))?)?;
+            }
+        }
+    }
+
+    let manifest_path = staging.path().join(MANIFEST_FILE);
+    let manifest: ArchiveManifest = serde_json::from_str(fs::read_to_string(&manifest_path)
+        .map_err(|err| anyhow!("{:?} has no manifest: {:?}", archive, err))?.as_str())?;
+    if manifest.version > MANIFEST_VERSION {
+        return Err(anyhow!("Unsupported archive version: {}", manifest.version));
+    }
+    // The basename becomes the directory name in the data repository.
+    if !is_valid_basename(manifest.basename.as_str()) {
+        return Err(anyhow!("Invalid basename in the manifest: {:?}", manifest.basename));
+    }
+    if archived_dirs != [manifest.basename.as_str()] {
+        return Err(anyhow!("The files in the archive are not in {}/: {:?}", manifest.basename, archived_dirs));
+    }
+    for file in &manifest.files {
+        let path = staging.path().join(&file.name);
+        let size = fs::metadata(&path)
+            .map_err(|err| anyhow!("{} is missing in the archive: {:?}", file.name, err))?
+            .len();
+        if size != file.size {
+            return Err(anyhow!("{} is broken. Expected {} bytes, but got {} bytes", file.name, file.size, size));
+        }
+    }
+    fs::remove_file(&manifest_path)?;
+
+    let basename = free_basename(data_repo, manifest.basename.as_str())?;
+    if basename != manifest.basename {
+        log::info!("{} exists already. Importing as {}", manifest.basename, basename);
+        let prefix = format!("{}.", manifest.basename);
+        for file in &manifest.files {
+            if let Some(suffix) = file.name.strip_prefix(prefix.as_str()) {
+                fs::rename(staging.path().join(&file.name), staging.path().join(format!("{}.{}", basename, suffix)))?;
+            }
+        }
+    }
+
+    let dest = data_repo.entry_dir(basename.as_str());
+    fs::create_dir_all(dest.parent().unwrap())?;
+    // TempDir ignores the failure of the cleanup, after it's moved.
+    fs::rename(staging.path(), &dest)?;
+    log::info!("Imported {:?} to {:?}", archive, dest);
+    Ok(Entry::new(dest))
+}
+
+// The archive contains `{basename}/{name}` only. Reject anything else, e.g. "../../etc/passwd".
+fn archived_file_name(path: &str) -> anyhow::Result<(&str, &str)> {
+    let (dir, name) = path.split_once('/')
+        .ok_or_else(|| anyhow!("Unexpected file in the archive: {:?}", path))?;
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        return Err(anyhow!("Unexpected file in the archive: {:?}", path));
+    }
+    Ok((dir, name))
+}
+
+// The recording start time, e.g. "20240101100000".
+fn is_valid_basename(basename: &str) -> bool {
+    basename.len() == 14
+        && basename.chars().all(|c| c.is_ascii_digit())
+        && NaiveDateTime::parse_from_str(basename, "%Y%m%d%H%M%S").is_ok()
+}
+
+fn free_basename(data_repo: &DataRepo, basename: &str) -> anyhow::Result<String> {
+    let exists = |basename: &str| data_repo.has_entry(basename);
+    if !exists(basename) {
+        return Ok(basename.to_string());
+    }
+
+    // Keep the basename as the timestamp. It's the recording start time and the sort key.
+    let started_at = NaiveDateTime::parse_from_str(basename, "%Y%m%d%H%M%S")?;
+    for seconds in 1..3600 {
+        let candidate = (started_at + chrono::Duration::seconds(seconds)).format("%Y%m%d%H%M%S").to_string();
+        if !exists(candidate.as_str()) {
+            return Ok(candidate);
+        }
+    }
+    Err(anyhow!("Cannot find the free name for {}", basename))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::test_support::EntryFixture;
+    use crate::entry_meta::EntryMeta;
+
+    fn round_trip(format: ArchiveFormat) -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let repo = DataRepo::with_dir(tmp.path());
+        let entry = EntryFixture {
+            vtt: Some("WEBVTT\n"),
+            summary: Some("# summary"),
+            mp3_bytes: 1000,
+            meta: Some(EntryMeta::default()),
+        }.create(repo.entry_dir("20240101100000"));
+
+        let archive = export_entry(&entry, format, tmp.path())?;
+        assert_eq!(archive.file_name().unwrap().to_str().unwrap(), format!("20240101100000.{}", format.extension()));
+
+        // Same basename exists.
+        let imported = import_entry(&repo, &archive)?;
+        assert_eq!(imported.basename, "20240101100001");
+        assert_eq!(fs::read_to_string(imported.md_path())?, "# summary");
+        assert_eq!(fs::read(imported.mp3_path_string())?.len(), 1000);
+        assert!(imported.meta_path().exists());
+        assert!(!imported.dir.join(MANIFEST_FILE).exists());
+        // The original one is untouched.
+        assert_eq!(fs::read_to_string(entry.md_path())?, "# summary");
+
+        let imported = import_entry(&repo, &archive)?;
+        assert_eq!(imported.basename, "20240101100002");
+
+        assert_eq!(repo.entries()?.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_zip() -> anyhow::Result<()> {
+        round_trip(ArchiveFormat::Zip)
+    }
+
+    #[test]
+    fn test_tar_gz() -> anyhow::Result<()> {
+        round_trip(ArchiveFormat::TarGz)
+    }
+
+    #[test]
+    fn test_archived_file_name() {
+        assert_eq!(archived_file_name("20240101100000/20240101100000.vtt").unwrap(), ("20240101100000", "20240101100000.vtt"));
+        assert!(archived_file_name("20240101100000/../../etc/passwd").is_err());
+        assert!(archived_file_name("20240101100000/").is_err());
+        assert!(archived_file_name("20240101100000.vtt").is_err());
+    }
+
+    fn write_zip(path: &Path, dir: &str, manifest_basename: &str) -> anyhow::Result<()> {
+        let manifest = ArchiveManifest {
+            version: MANIFEST_VERSION,
+            basename: manifest_basename.to_string(),
+            app_version: "0.0.0".to_string(),
+            exported_at: "2024-01-01T10:00:00+09:00".to_string(),
+            files: vec![ManifestFile { name: "20240101100000.md".to_string(), size: 9 }],
+        };
+        let mut zip = zip::ZipWriter::new(File::create(path)?);
+        let options = zip::write::SimpleFileOptions::default();
+        zip.start_file(format!("{}/{}", dir, MANIFEST_FILE), options)?;
+        io::Write::write_all(&mut zip, serde_json::to_string(&manifest)?.as_bytes())?;
+        zip.start_file(format!("{}/20240101100000.md", dir), options)?;
+        io::Write::write_all(&mut zip, b"# summary")?;
+        zip.finish()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_invalid_basename() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let repo = DataRepo::with_dir(tmp.path());
+        let archive = tmp.path().join("archive.zip");
+
+        for basename in ["../../x", "/tmp/x", "..", "2024010110000x", "20241301100000"] {
+            write_zip(&archive, "20240101100000", basename)?;
+            assert!(import_entry(&repo, &archive).is_err(), "{}", basename);
+        }
+        // The manifest doesn't match the directory in the archive.
+        write_zip(&archive, "20240101100000", "20240102100000")?;
+        assert!(import_entry(&repo, &archive).is_err());
+
+        // Nothing is left, neither outside the data directory nor in it.
+        assert_eq!(fs::read_dir(tmp.path())?.count(), 2);
+        assert_eq!(fs::read_dir(&repo.data_dir)?.count(), 0);
+
+        write_zip(&archive, "20240101100000", "20240101100000")?;
+        assert_eq!(import_entry(&repo, &archive)?.basename, "20240101100000");
+        Ok(())
+    }
+}
@@ -0,0 +1,41 @@
+use serde::{Deserialize, Serialize};
+use crate::encoder::{AudioFormat, ChannelMode};
+use crate::window::WindowPattern;
+
+/**
+ * Metadata of the entry, stored in `meta.json` next to the audio files.
+ */
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct EntryMeta {
+    // Meeting name. The UI falls back to the timestamp if it's missing.
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub participants: Vec<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    // The window pattern which started the recording
+    #[serde(default)]
+    pub window_pattern: Option<WindowPattern>,
+    // Length of the recording in milliseconds
+    #[serde(default)]
+    pub duration_millis: Option<u64>,
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub transcriber: Option<String>,
+    #[serde(default)]
+    pub transcriber_model: Option<String>,
+    #[serde(default)]
+    pub summarizer: Option<String>,
+    #[serde(default)]
+    pub summarizer_model: Option<String>,
+    // The encoded recording. It's MP3 if it's missing, the entries before the format was configurable.
+    #[serde(default)]
+    pub audio_format: Option<AudioFormat>,
+    // Stereo: the mic on the left, the system audio on the right.
+    #[serde(default)]
+    pub audio_channel_mode: Option<ChannelMode>,
+}
@@ -0,0 +1,461 @@
+use std::fs;
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use crate::config::{MeetNoteConfig, TranscriberType};
+use crate::lindera_tokenizer::LinderaTokenizer;
+use crate::webvtt::parse_webvtt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditOp {
+    Match(String),
+    // (reference, hypothesis)
+    Substitute(String, String),
+    Insert(String),
+    Delete(String),
+}
+
+// Up to 512KB of the distance matrix. The larger inputs are split by Hirschberg's algorithm.
+const MAX_MATRIX_CELLS: usize = 1 << 16;
+
+/**
+ * Align the hypothesis to the reference with the minimum edit distance, in the linear space.
+ * The transcript of the hour long meeting has tens of thousands of characters.
+ */
+pub fn align(reference: &[String], hypothesis: &[String]) -> Vec<EditOp> {
+    let mut ops = Vec::new();
+    align_into(reference, hypothesis, &mut ops);
+    ops
+}
+
+fn align_into(reference: &[String], hypothesis: &[String], ops: &mut Vec<EditOp>) {
+    let n = reference.len();
+    let m = hypothesis.len();
+    if n <= 1 || m == 0 || n * m <= MAX_MATRIX_CELLS {
+        ops.extend(align_matrix(reference, hypothesis));
+        return;
+    }
+
+    // Split the reference in half, and find where the optimal path crosses the middle.
+    let mid = n / 2;
+    let upper = last_row(&reference[..mid], hypothesis);
+    let lower = last_row(
+        &reference[mid..].iter().rev().collect::<Vec<_>>(),
+        &hypothesis.iter().rev().collect::<Vec<_>>(),
+    );
+    let split = (0..=m)
+        .min_by_key(|&j| upper[j] + lower[m - j])
+        .unwrap();
+    align_into(&reference[..mid], &hypothesis[..split], ops);
+    align_into(&reference[mid..], &hypothesis[split..], ops);
+}
+
+/**
+ * Edit distances between the whole `a` and every prefix of `b`.
+ */
+fn last_row<T: PartialEq>(a: &[T], b: &[T]) -> Vec<usize> {
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    let mut current = vec![0; b.len() + 1];
+    for (i, a_item) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, b_item) in b.iter().enumerate() {
+            let cost = if a_item == b_item { 0 } else { 1 };
+            current[j + 1] = (previous[j] + cost)
+                .min(previous[j + 1] + 1)
+                .min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous
+}
+
+fn align_matrix(reference: &[String], hypothesis: &[String]) -> Vec<EditOp> {
+    let n = reference.len();
+    let m = hypothesis.len();
+
+    // distance[i][j]: edit distance between reference[..i] and hypothesis[..j]
+    let mut distance = vec![vec![0usize; m + 1]; n + 1];
+    for (i, row) in distance.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in distance[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=n {
+        for j in 1..=m {
+            let cost = if reference[i - 1] == hypothesis[j - 1] { 0 } else { 1 };
+            distance[i][j] = (distance[i - 1][j - 1] + cost)
+                .min(distance[i - 1][j] + 1)
+                .min(distance[i][j - 1] + 1);
+        }
+    }
+
+    // backtrace. Prefer the match, then the deletion and the insertion over the substitution,
+    // it makes the diff readable.
+    let mut ops = Vec::new();
+    let (mut i, mut j) = (n, m);
+    while i > 0 || j > 0 {
+        if i > 0 && j > 0 && reference[i - 1] == hypothesis[j - 1] && distance[i][j] == distance[i - 1][j - 1] {
+            ops.push(EditOp::Match(reference[i - 1].clone()));
+            i -= 1;
+            j -= 1;
+        } else if i > 0 && distance[i][j] == distance[i - 1][j] + 1 {
+            ops.push(EditOp::Delete(reference[i - 1].clone()));
+            i -= 1;
+        } else if j > 0 && distance[i][j] == distance[i][j - 1] + 1 {
+            ops.push(EditOp::Insert(hypothesis[j - 1].clone()));
+            j -= 1;
+        } else {
+            ops.push(EditOp::Substitute(reference[i - 1].clone(), hypothesis[j - 1].clone()));
+            i -= 1;
+            j -= 1;
+        }
+    }
+    ops.reverse();
+    ops
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorRate {
+    pub rate: f64,
+    pub substitutions: usize,
+    pub deletions: usize,
+    pub insertions: usize,
+    pub reference_length: usize,
+}
+
+impl ErrorRate {
+    fn new(substitutions: usize, deletions: usize, insertions: usize, reference_length: usize) -> ErrorRate {
+        let errors = substitutions + deletions + insertions;
+        let rate = if reference_length == 0 {
+            if errors == 0 { 0.0 } else { 1.0 }
+        } else {
+            errors as f64 / reference_length as f64
+        };
+        ErrorRate { rate, substitutions, deletions, insertions, reference_length }
+    }
+
+    pub fn from_ops(ops: &[EditOp]) -> ErrorRate {
+        let mut substitutions = 0;
+        let mut deletions = 0;
+        let mut insertions = 0;
+        let mut matches = 0;
+        for op in ops {
+            match op {
+                EditOp::Match(_) => matches += 1,
+                EditOp::Substitute(_, _) => substitutions += 1,
+                EditOp::Insert(_) => insertions += 1,
+                EditOp::Delete(_) => deletions += 1,
+            }
+        }
+
+        ErrorRate::new(substitutions, deletions, insertions, matches + substitutions + deletions)
+    }
+
+    /**
+     * Count the errors without the alignment, in two rows of the distance matrix.
+     */
+    pub fn compute(reference: &[String], hypothesis: &[String]) -> ErrorRate {
+        // (distance, substitutions, deletions, insertions)
+        let mut previous = (0..=hypothesis.len()).map(|j| (j, 0, 0, j)).collect::<Vec<_>>();
+        let mut current = vec![(0, 0, 0, 0); hypothesis.len() + 1];
+        for (i, reference_item) in reference.iter().enumerate() {
+            current[0] = (i + 1, 0, i + 1, 0);
+            for (j, hypothesis_item) in hypothesis.iter().enumerate() {
+                // Prefer the match. Otherwise prefer fewer substitutions at the same distance, the
+                // alignment prefers the deletion and the insertion too.
+                let matched = reference_item == hypothesis_item;
+                let (distance, s, d, ins) = previous[j];
+                let mut best = if matched {
+                    (distance, s, d, ins)
+                } else {
+                    (distance + 1, s + 1, d, ins)
+                };
+                let (distance, s, d, ins) = previous[j + 1];
+                if distance + 1 < best.0 || (!matched && distance + 1 == best.0 && best.1 > s) {
+                    best = (distance + 1, s, d + 1, ins);
+                }
+                let (distance, s, d, ins) = current[j];
+                if distance + 1 < best.0 || (!matched && distance + 1 == best.0 && best.1 > s) {
+                    best = (distance + 1, s, d, ins + 1);
+                }
+                current[j + 1] = best;
+            }
+            std::mem::swap(&mut previous, &mut current);
+        }
+        let (_, substitutions, deletions, insertions) = previous[hypothesis.len()];
+        ErrorRate::new(substitutions, deletions, insertions, reference.len())
+    }
+}
+
+/**
+ * Render the alignment in wdiff style: `[-deleted-]`, `{+inserted+}`, and `[-ref-]{+hyp+}` for
+ * the substitution.
+ */
+pub fn format_diff(ops: &[EditOp], separator: &str) -> String {
+    ops.iter()
+        .map(|op| match op {
+            EditOp::Match(word) => word.clone(),
+            EditOp::Substitute(reference, hypothesis) => format!("[-{}-]{{+{}+}}", reference, hypothesis),
+            EditOp::Insert(word) => format!("{{+{}+}}", word),
+            EditOp::Delete(word) => format!("[-{}-]", word),
+        })
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvaluationReport {
+    pub label: String,
+    pub wer: ErrorRate,
+    pub cer: ErrorRate,
+    // aligned diff in words
+    pub diff: String,
+}
+
+/**
+ * The transcriber to evaluate.
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriberCandidate {
+    pub transcriber_type: TranscriberType,
+    // Used by the whisper.cpp transcriber
+    pub whisper_model: Option<String>,
+}
+
+impl TranscriberCandidate {
+    fn label(&self) -> String {
+        match (&self.transcriber_type, &self.whisper_model) {
+            (TranscriberType::WhisperCppTranscriberType, Some(model)) => format!("whisper.cpp({})", model),
+            (TranscriberType::WhisperCppTranscriberType, None) => "whisper.cpp".to_string(),
+            (TranscriberType::OpenAITranscriberType, _) => "OpenAI".to_string(),
+        }
+    }
+}
+
+pub struct Evaluator {
+    // None for the space separated languages.
+    segmenter: Option<LinderaTokenizer>,
+}
+
+impl Evaluator {
+    pub fn new(language: &str) -> anyhow::Result<Evaluator> {
+        let segmenter = if language == "ja" {
+            Some(LinderaTokenizer::segmenter()?)
+        } else {
+            None
+        };
+        Ok(Evaluator { segmenter })
+    }
+
+    fn words(&self, text: &str) -> anyhow::Result<Vec<String>> {
+        let words = match &self.segmenter {
+            Some(segmenter) => segmenter.segment(text)?,
+            None => text.split_whitespace().map(|word| word.to_string()).collect(),
+        };
+        Ok(words.iter()
+            .map(|word| normalize(word))
+            .filter(|word| !word.is_empty())
+            .collect())
+    }
+
+    fn chars(&self, text: &str) -> Vec<String> {
+        normalize(text).chars()
+            .map(|c| c.to_string())
+            .collect()
+    }
+
+    pub fn evaluate(&self, label: &str, reference: &str, hypothesis: &str) -> anyhow::Result<EvaluationReport> {
+        let word_ops = align(&self.words(reference)?, &self.words(hypothesis)?);
+
+        let separator = if self.segmenter.is_some() { "" } else { " " };
+        let report = EvaluationReport {
+            label: label.to_string(),
+            wer: ErrorRate::from_ops(&word_ops),
+            cer: ErrorRate::compute(&self.chars(reference), &self.chars(hypothesis)),
+            diff: format_diff(&word_ops, separator),
+        };
+        log::info!("[{}] WER={:.3} CER={:.3}\n{}", report.label, report.wer.rate, report.cer.rate, report.diff);
+        Ok(report)
+    }
+}
+
+// Lower case, and remove the punctuations and the spaces.
+fn normalize(src: &str) -> String {
+    src.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+/**
+ * Read the transcript file. VTT files are reduced to the cue text, anything else is read as the
+ * plain text.
+ */
+pub fn read_transcript_text(path: &str) -> anyhow::Result<String> {
+    let content = fs::read_to_string(path)
+        .map_err(|err| anyhow!("Cannot read transcript({}): {:?}", path, err))?;
+    if !content.trim_start_matches('\u{FEFF}').trim_start().starts_with("WEBVTT") {
+        return Ok(content);
+    }
+
+    let captions = parse_webvtt(content.as_str())
+        .map_err(|err| anyhow!("Cannot parse VTT file({}): {}", path, err))?;
+    Ok(captions.iter()
+        .map(|caption| caption.text.as_str())
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/**
+ * Compare the hypothesis VTT to the reference transcript.
+ */
+pub fn evaluate_files(reference_file: &str, hypothesis_file: &str, language: &str) -> anyhow::Result<EvaluationReport> {
+    let reference = read_transcript_text(reference_file)?;
+    let hypothesis = read_transcript_text(hypothesis_file)?;
+    Evaluator::new(language)?.evaluate(hypothesis_file, reference.as_str(), hypothesis.as_str())
+}
+
+/**
+ * Run the audio file through the every candidate, and score them against the reference.
+ */
+pub fn evaluate_transcribers(config: &MeetNoteConfig, wav_file: &str, reference_file: &str,
+                             candidates: &[TranscriberCandidate]) -> anyhow::Result<Vec<EvaluationReport>> {
+    let reference = read_transcript_text(reference_file)?;
+    let evaluator = Evaluator::new(config.language.as_str())?;
+
+    let mut reports = Vec::new();
+    for candidate in candidates {
+        let mut candidate_config = config.clone();
+        candidate_config.transcriber_type = candidate.transcriber_type.clone();
+        if let Some(model) = &candidate.whisper_model {
+            candidate_config.whisper_model = model.clone();
+        }
+
+        let vtt_tmp = tempfile::Builder::new()
+            .suffix(".vtt")
+            .rand_bytes(5)
+            .tempfile()?;
+        let vtt_file = vtt_tmp.path().to_str().unwrap();
+        candidate_config.build_transcriber()?
+            .transcribe(wav_file, vtt_file, &|_| {})
+            .map_err(|err| anyhow!("Cannot transcribe with {}: {:?}", candidate.label(), err))?;
+
+        let hypothesis = read_transcript_text(vtt_file)?;
+        reports.push(evaluator.evaluate(candidate.label().as_str(), reference.as_str(), hypothesis.as_str())?);
+    }
+
+    log::info!("Evaluation result of {}:\n{}", wav_file, format_reports(&reports));
+    Ok(reports)
+}
+
+/**
+ * Render the scores side by side, as the Markdown table.
+ */
+pub fn format_reports(reports: &[EvaluationReport]) -> String {
+    let mut buffer = String::from("| transcriber | WER | CER | S | D | I |\n|---|---|---|---|---|---|\n");
+    for report in reports {
+        buffer.push_str(format!("| {} | {:.3} | {:.3} | {} | {} | {} |\n",
+                                report.label, report.wer.rate, report.cer.rate,
+                                report.wer.substitutions, report.wer.deletions, report.wer.insertions).as_str());
+    }
+    buffer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(src: &str) -> Vec<String> {
+        src.split_whitespace().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn test_align() {
+        let ops = align(&words("the cat sat on the mat"), &words("the cat sit on mat today"));
+        assert_eq!(ops, vec![
+            EditOp::Match("the".to_string()),
+            EditOp::Match("cat".to_string()),
+            EditOp::Substitute("sat".to_string(), "sit".to_string()),
+            EditOp::Match("on".to_string()),
+            EditOp::Delete("the".to_string()),
+            EditOp::Match("mat".to_string()),
+            EditOp::Insert("today".to_string()),
+        ]);
+
+        let rate = ErrorRate::from_ops(&ops);
+        assert_eq!(rate, ErrorRate {
+            rate: 0.5,
+            substitutions: 1,
+            deletions: 1,
+            insertions: 1,
+            reference_length: 6,
+        });
+        assert_eq!(format_diff(&ops, " "), "the cat [-sat-]{+sit+} on [-the-] mat {+today+}");
+    }
+
+    #[test]
+    fn test_align_long() {
+        // Larger than MAX_MATRIX_CELLS
+        let mut seed = 1u32;
+        let mut random_words = |len: usize| (0..len).map(|_| {
+            seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            ((seed >> 16) % 4).to_string()
+        }).collect::<Vec<_>>();
+        let reference = random_words(700);
+        let hypothesis = random_words(500);
+
+        let ops = align(&reference, &hypothesis);
+        let aligned_reference = ops.iter().filter_map(|op| match op {
+            EditOp::Match(word) | EditOp::Substitute(word, _) | EditOp::Delete(word) => Some(word.clone()),
+            EditOp::Insert(_) => None,
+        }).collect::<Vec<_>>();
+        let aligned_hypothesis = ops.iter().filter_map(|op| match op {
+            EditOp::Match(word) | EditOp::Substitute(_, word) | EditOp::Insert(word) => Some(word.clone()),
+            EditOp::Delete(_) => None,
+        }).collect::<Vec<_>>();
+        assert_eq!(aligned_reference, reference);
+        assert_eq!(aligned_hypothesis, hypothesis);
+
+        let rate = ErrorRate::from_ops(&ops);
+        let distance = *last_row(&reference, &hypothesis).last().unwrap();
+        assert_eq!(rate.substitutions + rate.deletions + rate.insertions, distance);
+        let computed = ErrorRate::compute(&reference, &hypothesis);
+        assert_eq!(computed.substitutions + computed.deletions + computed.insertions, distance);
+        assert_eq!(computed.reference_length, 700);
+    }
+
+    #[test]
+    fn test_compute() {
+        assert_eq!(ErrorRate::compute(&words("the cat sat on the mat"), &words("the cat sit on mat today")),
+                   ErrorRate::from_ops(&align(&words("the cat sat on the mat"), &words("the cat sit on mat today"))));
+        assert_eq!(ErrorRate::compute(&[], &[]).rate, 0.0);
+        assert_eq!(ErrorRate::compute(&[], &words("hello")).rate, 1.0);
+        assert_eq!(ErrorRate::compute(&words("hello"), &[]).rate, 1.0);
+    }
+
+    #[test]
+    fn test_empty() {
+        assert_eq!(ErrorRate::from_ops(&align(&[], &[])).rate, 0.0);
+        assert_eq!(ErrorRate::from_ops(&align(&[], &words("hello"))).rate, 1.0);
+        assert_eq!(ErrorRate::from_ops(&align(&words("hello"), &[])).rate, 1.0);
+    }
+
+    #[test]
+    fn test_evaluate_en() {
+        let evaluator = Evaluator::new("en").unwrap();
+        let report = evaluator.evaluate("test", "Hello, World! How are you?", "hello world how r you").unwrap();
+        assert_eq!(report.wer.substitutions, 1);
+        assert_eq!(report.wer.reference_length, 5);
+        assert_eq!(report.wer.rate, 0.2);
+        assert_eq!(report.diff, "hello world how [-are-]{+r+} you");
+    }
+
+    #[test]
+    fn test_evaluate_ja() {
+        let evaluator = Evaluator::new("ja").unwrap();
+        let report = evaluator.evaluate("test", "今日は会議です。", "今日は会合です").unwrap();
+        assert_eq!(report.cer.reference_length, 7);
+        assert_eq!(report.cer.substitutions, 1);
+        assert!(report.wer.rate > 0.0);
+    }
+}
@@ -0,0 +1,133 @@
+use std::process::Command;
+use std::time::Instant;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use crate::entry::Entry;
+use crate::processing_state::{HookRun, ProcessingStep};
+
+// Keep the tail of the output in the processing log.
+const OUTPUT_LIMIT: usize = 1000;
+
+/**
+ * User command which runs after the step, e.g. copy the summary into the Obsidian vault.
+ * The command runs by `sh -c` in the entry directory. The paths are in the environment variables.
+ */
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PostprocessHook {
+    pub after: ProcessingStep,
+    pub command: String,
+    // Stop the postprocessing if the command fails. Otherwise, the failure is just recorded.
+    #[serde(default)]
+    pub required: bool,
+}
+
+/**
+ * Environment variables for the hook commands.
+ */
+pub fn hook_env(entry: &Entry, step: ProcessingStep) -> Vec<(String, String)> {
+    let audio_format = entry.find_encoded_audio()
+        .map(|encoded| encoded.format)
+        .unwrap_or_default();
+    vec![
+        ("MEETNOTE_STEP".to_string(), format!("{:?}", step)),
+        ("MEETNOTE_ENTRY_DIR".to_string(), entry.dir.to_str().unwrap().to_string()),
+        ("MEETNOTE_BASENAME".to_string(), entry.basename.clone()),
+        ("MEETNOTE_MIC_WAV".to_string(), entry.mic_wav_path_string()),
+        ("MEETNOTE_WAV".to_string(), entry.merged_wav_path_string()),
+        ("MEETNOTE_MP3".to_string(), entry.mp3_path_string()),
+        ("MEETNOTE_AUDIO".to_string(), entry.encoded_audio_path_string(audio_format)),
+        ("MEETNOTE_VTT".to_string(), entry.webvtt_path_string()),
+        ("MEETNOTE_ORIGINAL_VTT".to_string(), entry.original_webvtt_path_string()),
+        ("MEETNOTE_SUMMARY".to_string(), entry.md_path()),
+        ("MEETNOTE_META".to_string(), entry.meta_path().to_str().unwrap().to_string()),
+    ]
+}
+
+/**
+ * Run the hook, and describe the result for the processing log.
+ */
+pub fn run_hook(hook: &PostprocessHook, entry: &Entry, now: NaiveDateTime) -> HookRun {
+    log::info!("Running hook after {:?}: {}", hook.after, hook.command);
+    let start = Instant::now();
+    let result = Command::new("sh")
+        .arg("-c")
+        .arg(&hook.command)
+        .current_dir(&entry.dir)
+        .envs(hook_env(entry, hook.after))
+        .output();
+
+    let mut run = HookRun {
+        after: hook.after,
+        command: hook.command.clone(),
+        started_at: now.format("%Y-%m-%dT%H:%M:%S").to_string(),
+        duration_millis: 0,
+        exit_code: None,
+        success: false,
+        output: String::new(),
+    };
+    match result {
+        Ok(output) => {
+            run.exit_code = output.status.code();
+            run.success = output.status.success();
+            let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+            text.push_str(String::from_utf8_lossy(&output.stderr).as_ref());
+            run.output = tail(text.as_str(), OUTPUT_LIMIT);
+        }
+        Err(err) => {
+            run.output = format!("Cannot run hook: {:?}", err);
+        }
+    }
+    run.duration_millis = start.elapsed().as_millis() as u64;
+    if !run.success {
+        log::error!("Hook failed: {:?}", run);
+    }
+    run
+}
+
+fn tail(text: &str, limit: usize) -> String {
+    let chars = text.chars().count();
+    text.chars().skip(chars.saturating_sub(limit)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_run_hook() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let entry = Entry::new(tmp.path().join("20240101100000"));
+        fs::create_dir_all(&entry.dir)?;
+        fs::write(entry.md_path(), "# summary")?;
+        let now = NaiveDateTime::parse_from_str("20240101110000", "%Y%m%d%H%M%S")?;
+
+        let hook = PostprocessHook {
+            after: ProcessingStep::Summarize,
+            command: "cp \"$MEETNOTE_SUMMARY\" copied.md && echo \"$MEETNOTE_BASENAME $MEETNOTE_STEP\"".to_string(),
+            required: false,
+        };
+        let run = run_hook(&hook, &entry, now);
+        assert!(run.success);
+        assert_eq!(run.exit_code, Some(0));
+        assert_eq!(run.output, "20240101100000 Summarize\n");
+        assert_eq!(fs::read_to_string(entry.dir.join("copied.md"))?, "# summary");
+
+        let hook = PostprocessHook {
+            after: ProcessingStep::Transcribe,
+            command: "echo oops >&2; exit 3".to_string(),
+            required: true,
+        };
+        let run = run_hook(&hook, &entry, now);
+        assert!(!run.success);
+        assert_eq!(run.exit_code, Some(3));
+        assert_eq!(run.output, "oops\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_tail() {
+        assert_eq!(tail("abcdef", 3), "def");
+        assert_eq!(tail("あいう", 5), "あいう");
+    }
+}
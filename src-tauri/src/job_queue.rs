@@ -0,0 +1,418 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+use anyhow::anyhow;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use crate::entry::write_atomically;
+use crate::openai;
+use crate::postprocess::MissingCommand;
+
+const TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+// Give up the transient failures after this.
+const MAX_ATTEMPTS: u32 = 5;
+const BACKOFF_BASE_SECS: i64 = 30;
+const BACKOFF_MAX_SECS: i64 = 60 * 60;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum JobCommand {
+    // Run the whole postprocessing of the recorded entry.
+    All,
+    // Resume from the failed(or the next) step.
+    Retry,
+    RegenerateSummary,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Job {
+    pub id: u64,
+    pub command: JobCommand,
+    // Entry directory
+    pub path: String,
+    // Higher runs first. The same priority runs in the queued order.
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default)]
+    pub attempts: u32,
+    pub enqueued_at: String,
+    // Waiting for the backoff until this time.
+    #[serde(default)]
+    pub not_before: Option<String>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+    #[serde(default)]
+    pub running: bool,
+}
+
+impl Job {
+    fn is_ready(&self, now: NaiveDateTime) -> bool {
+        !self.running && !matches!(self.not_before_time(), Some(not_before) if not_before > now)
+    }
+
+    fn not_before_time(&self) -> Option<NaiveDateTime> {
+        self.not_before.as_ref()
+            .and_then(|it| NaiveDateTime::parse_from_str(it.as_str(), TIME_FORMAT).ok())
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct QueueData {
+    next_id: u64,
+    jobs: Vec<Job>,
+}
+
+/**
+ * The queue of the postprocessing jobs, stored in `job_queue.json`.
+ * The queued jobs survive the restart. The job is removed after it's finished.
+ */
+pub struct JobQueue {
+    path: PathBuf,
+    data: Mutex<QueueData>,
+    changed: Condvar,
+}
+
+impl JobQueue {
+    pub fn open(path: PathBuf) -> anyhow::Result<JobQueue> {
+        let mut data: QueueData = if path.exists() {
+            let src = fs::read_to_string(&path)?;
+            serde_json::from_str(src.as_str()).unwrap_or_else(|err| {
+                log::error!("Broken job queue({:?}), starting with the empty queue: {:?}", path, err);
+                Default::default()
+            })
+        } else {
+            Default::default()
+        };
+        // The app was quit while running them.
+        for job in data.jobs.iter_mut().filter(|job| job.running) {
+            log::info!("Requeue the interrupted job: {:?}", job);
+            job.running = false;
+            if job.command == JobCommand::All {
+                job.command = JobCommand::Retry;
+            }
+        }
+
+        let queue = JobQueue { path, data: Mutex::new(data), changed: Condvar::new() };
+        queue.save(&queue.data.lock().unwrap())?;
+        Ok(queue)
+    }
+
+    fn save(&self, data: &QueueData) -> anyhow::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_atomically(&self.path, serde_json::to_string_pretty(data)?.as_str())
+    }
+
+    fn update<T, F: FnOnce(&mut QueueData) -> anyhow::Result<T>>(&self, f: F) -> anyhow::Result<T> {
+        let mut data = self.data.lock().unwrap();
+        let result = f(&mut data)?;
+        self.save(&data)?;
+        self.changed.notify_all();
+        Ok(result)
+    }
+
+    /**
+     * Queue the job. If the same entry is waiting in the queue already, it's returned instead.
+     */
+    pub fn push(&self, command: JobCommand, path: &str, now: NaiveDateTime) -> anyhow::Result<Job> {
+        self.update(|data| {
+            if let Some(job) = data.jobs.iter().find(|job| job.path == path && job.command == command && !job.running) {
+                return Ok(job.clone());
+            }
+
+            data.next_id += 1;
+            let job = Job {
+                id: data.next_id,
+                command,
+                path: path.to_string(),
+                priority: 0,
+                attempts: 0,
+                enqueued_at: now.format(TIME_FORMAT).to_string(),
+                not_before: None,
+                last_error: None,
+                running: false,
+            };
+            data.jobs.push(job.clone());
+            Ok(job)
+        })
+    }
+
+    pub fn list(&self) -> Vec<Job> {
+        let mut jobs = self.data.lock().unwrap().jobs.clone();
+        // In the running order
+        jobs.sort_by(|a, b| b.running.cmp(&a.running)
+            .then_with(|| b.priority.cmp(&a.priority))
+            .then_with(|| a.id.cmp(&b.id)));
+        jobs
+    }
+
+    /**
+     * Whether the entry is waiting or running in the queue.
+     */
+    pub fn has_job(&self, path: &str) -> bool {
+        self.data.lock().unwrap().jobs.iter().any(|job| job.path == path)
+    }
+
+    pub fn cancel(&self, id: u64) -> anyhow::Result<()> {
+        self.update(|data| {
+            let index = find_waiting(data, id)?;
+            let job = data.jobs.remove(index);
+            log::info!("Cancelled job: {:?}", job);
+            Ok(())
+        })
+    }
+
+    pub fn set_priority(&self, id: u64, priority: i32) -> anyhow::Result<()> {
+        self.update(|data| {
+            let index = find_waiting(data, id)?;
+            data.jobs[index].priority = priority;
+            Ok(())
+        })
+    }
+
+    /**
+     * Rewrite the entry paths of the jobs, e.g. after the entries are moved. `f` returns the new path,
+     * or None to keep it.
+     */
+    pub fn remap_paths<F: Fn(&str) -> Option<String>>(&self, f: F) -> anyhow::Result<()> {
+        self.update(|data| {
+            for job in data.jobs.iter_mut() {
+                if let Some(path) = f(job.path.as_str()) {
+                    log::info!("Moved job {} from {} to {}", job.id, job.path, path);
+                    job.path = path;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /**
+     * Take the next job to run, if it's ready. The job stays in the queue until it's finished.
+     */
+    #[cfg(test)]
+    pub fn start_next(&self, now: NaiveDateTime) -> anyhow::Result<Option<Job>> {
+        self.update(|data| Ok(take_next(data, now)))
+    }
+
+    /**
+     * Block until the next job is ready.
+     */
+    pub fn wait_next(&self) -> Job {
+        let mut data = self.data.lock().unwrap();
+        loop {
+            let now = chrono::Local::now().naive_local();
+            if let Some(job) = take_next(&mut data, now) {
+                if let Err(err) = self.save(&data) {
+                    log::error!("Cannot save the job queue: {:?}", err);
+                }
+                return job;
+            }
+
+            // Wake up at the end of the nearest backoff, or when the queue is changed.
+            let timeout = data.jobs.iter()
+                .filter(|job| !job.running)
+                .filter_map(|job| job.not_before_time())
+                .min()
+                .and_then(|not_before| (not_before - now).to_std().ok())
+                .unwrap_or(Duration::from_secs(60))
+                .max(Duration::from_millis(100));
+            data = self.changed.wait_timeout(data, timeout).unwrap().0;
+        }
+    }
+
+    pub fn complete(&self, id: u64) -> anyhow::Result<()> {
+        self.update(|data| {
+            data.jobs.retain(|job| job.id != id);
+            Ok(())
+        })
+    }
+
+    /**
+     * Schedule the retry with the exponential backoff if the failure is transient.
+     * Otherwise, the job is removed. The failure is in the processing state of the entry.
+     */
+    pub fn fail(&self, id: u64, err: &anyhow::Error, now: NaiveDateTime) -> anyhow::Result<()> {
+        let transient = is_transient(err);
+        self.update(|data| {
+            let Some(index) = data.jobs.iter().position(|job| job.id == id) else {
+                return Ok(());
+            };
+            let job = &mut data.jobs[index];
+            if !transient || job.attempts >= MAX_ATTEMPTS {
+                log::info!("Giving up job {} after {} attempts(transient={})", id, job.attempts, transient);
+                data.jobs.remove(index);
+                return Ok(());
+            }
+
+            let delay = chrono::Duration::seconds(backoff_secs(job.attempts));
+            job.running = false;
+            job.last_error = Some(format!("{:?}", err));
+            job.not_before = Some((now + delay).format(TIME_FORMAT).to_string());
+            // The finished steps are not needed to run again.
+            if job.command == JobCommand::All {
+                job.command = JobCommand::Retry;
+            }
+            log::info!("Retrying job {} at {:?}", id, job.not_before);
+            Ok(())
+        })
+    }
+}
+
+fn take_next(data: &mut QueueData, now: NaiveDateTime) -> Option<Job> {
+    // Don't process the same entry in the multiple workers.
+    let running_paths = data.jobs.iter()
+        .filter(|job| job.running)
+        .map(|job| job.path.clone())
+        .collect::<HashSet<_>>();
+    let next = data.jobs.iter_mut()
+        .filter(|job| job.is_ready(now) && !running_paths.contains(&job.path))
+        .max_by(|a, b| a.priority.cmp(&b.priority).then_with(|| b.id.cmp(&a.id)))?;
+    next.running = true;
+    next.attempts += 1;
+    Some(next.clone())
+}
+
+fn find_waiting(data: &QueueData, id: u64) -> anyhow::Result<usize> {
+    let index = data.jobs.iter().position(|job| job.id == id)
+        .ok_or_else(|| anyhow!("Unknown job: {}", id))?;
+    if data.jobs[index].running {
+        return Err(anyhow!("Job {} is running already", id));
+    }
+    Ok(index)
+}
+
+// 30s, 60s, 120s, ... up to 1 hour.
+fn backoff_secs(attempts: u32) -> i64 {
+    let exp = attempts.saturating_sub(1).min(16);
+    (BACKOFF_BASE_SECS << exp).min(BACKOFF_MAX_SECS)
+}
+
+/**
+ * The failure which may succeed later without any change to the entry.
+ * e.g. OpenAI API is overloaded, the network is down, lame is not installed yet.
+ */
+pub fn is_transient(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        if let Some(api_error) = cause.downcast_ref::<openai::ApiError>() {
+            return api_error.is_transient();
+        }
+        if let Some(reqwest_error) = cause.downcast_ref::<reqwest::Error>() {
+            return reqwest_error.is_timeout() || reqwest_error.is_connect();
+        }
+        if cause.downcast_ref::<MissingCommand>().is_some() {
+            return true;
+        }
+        matches!(cause.downcast_ref::<io::Error>(), Some(io_error) if io_error.kind() == io::ErrorKind::TimedOut)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+
+    fn time(src: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(src, "%Y%m%d%H%M%S").unwrap()
+    }
+
+    fn server_error() -> anyhow::Error {
+        anyhow::Error::new(openai::ApiError {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            message: "overloaded".to_string(),
+        }).context("Cannot transcribe")
+    }
+
+    #[test]
+    fn test_queue() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("job_queue.json");
+        let queue = JobQueue::open(path.clone())?;
+        let now = time("20240101100000");
+
+        let first = queue.push(JobCommand::All, "/data/a", now)?;
+        let second = queue.push(JobCommand::All, "/data/b", now)?;
+        let third = queue.push(JobCommand::RegenerateSummary, "/data/c", now)?;
+        // Already queued
+        assert_eq!(queue.push(JobCommand::All, "/data/a", now)?.id, first.id);
+
+        queue.cancel(second.id)?;
+        queue.set_priority(third.id, 10)?;
+        assert_eq!(queue.list().iter().map(|job| job.id).collect::<Vec<_>>(), vec![third.id, first.id]);
+
+        let job = queue.start_next(now)?.unwrap();
+        assert_eq!(job.id, third.id);
+        assert!(queue.cancel(third.id).is_err());
+        queue.complete(third.id)?;
+
+        let job = queue.start_next(now)?.unwrap();
+        assert_eq!(job.id, first.id);
+        queue.fail(job.id, &server_error(), now)?;
+        let retried = &queue.list()[0];
+        assert_eq!(retried.command, JobCommand::Retry);
+        assert_eq!(retried.not_before.as_deref(), Some("2024-01-01T10:00:30"));
+        // Backing off
+        assert_eq!(queue.start_next(now)?, None);
+
+        // Survives the restart
+        drop(queue);
+        let queue = JobQueue::open(path)?;
+        let job = queue.start_next(time("20240101100030"))?.unwrap();
+        assert_eq!(job.attempts, 2);
+        queue.fail(job.id, &anyhow!("Cannot parse VTT"), now)?;
+        assert!(queue.list().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_interrupted() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("job_queue.json");
+        let queue = JobQueue::open(path.clone())?;
+        queue.push(JobCommand::All, "/data/a", time("20240101100000"))?;
+        queue.start_next(time("20240101100000"))?.unwrap();
+
+        let queue = JobQueue::open(path.clone())?;
+        let job = queue.start_next(time("20240101100000"))?.unwrap();
+        assert_eq!(job.command, JobCommand::Retry);
+
+        // Moved to the per-day directory.
+        queue.remap_paths(|path| (path == "/data/a").then(|| "/data/20240101/a".to_string()))?;
+        let queue = JobQueue::open(path)?;
+        assert_eq!(queue.list()[0].path, "/data/20240101/a");
+        Ok(())
+    }
+
+    #[test]
+    fn test_same_entry() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let queue = JobQueue::open(tmp.path().join("job_queue.json"))?;
+        let now = time("20240101100000");
+        let first = queue.push(JobCommand::All, "/data/a", now)?;
+        queue.push(JobCommand::RegenerateSummary, "/data/a", now)?;
+        let other = queue.push(JobCommand::All, "/data/b", now)?;
+
+        assert_eq!(queue.start_next(now)?.unwrap().id, first.id);
+        // "/data/a" is running in the other worker.
+        assert_eq!(queue.start_next(now)?.unwrap().id, other.id);
+        assert_eq!(queue.start_next(now)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_transient() {
+        assert!(is_transient(&server_error()));
+        assert!(is_transient(&anyhow::Error::new(MissingCommand("lame".to_string()))));
+        assert!(!is_transient(&anyhow::Error::new(openai::ApiError {
+            status: StatusCode::UNAUTHORIZED,
+            message: "invalid api key".to_string(),
+        })));
+        assert!(!is_transient(&anyhow!("Cannot parse VTT")));
+        assert_eq!(backoff_secs(1), 30);
+        assert_eq!(backoff_secs(3), 120);
+        assert_eq!(backoff_secs(10), 3600);
+    }
+}
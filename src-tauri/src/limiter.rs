@@ -0,0 +1,101 @@
+use std::sync::{Condvar, Mutex};
+use lazy_static::lazy_static;
+use crate::config::MeetNoteConfig;
+
+lazy_static! {
+    // whisper.cpp, merging the audio, encoders
+    pub static ref CPU_LIMITER: Limiter = Limiter::new(1);
+    // OpenAI API
+    pub static ref NETWORK_LIMITER: Limiter = Limiter::new(2);
+}
+
+/**
+ * Apply the limits in the configuration. The running steps are not interrupted.
+ */
+pub fn configure(config: &MeetNoteConfig) {
+    CPU_LIMITER.set_limit(config.max_cpu_jobs);
+    NETWORK_LIMITER.set_limit(config.max_network_jobs);
+}
+
+struct LimiterState {
+    limit: usize,
+    in_use: usize,
+}
+
+/**
+ * Counting semaphore to limit the number of the steps which run at once.
+ */
+pub struct Limiter {
+    state: Mutex<LimiterState>,
+    released: Condvar,
+}
+
+pub struct Permit<'a> {
+    limiter: &'a Limiter,
+}
+
+impl Limiter {
+    pub fn new(limit: usize) -> Limiter {
+        Limiter {
+            state: Mutex::new(LimiterState { limit: limit.max(1), in_use: 0 }),
+            released: Condvar::new(),
+        }
+    }
+
+    pub fn set_limit(&self, limit: usize) {
+        self.state.lock().unwrap().limit = limit.max(1);
+        self.released.notify_all();
+    }
+
+    /**
+     * Block until the slot is available. The slot is released when the permit is dropped.
+     */
+    pub fn acquire(&self) -> Permit<'_> {
+        let mut state = self.state.lock().unwrap();
+        while state.in_use >= state.limit {
+            state = self.released.wait(state).unwrap();
+        }
+        state.in_use += 1;
+        Permit { limiter: self }
+    }
+
+    pub fn in_use(&self) -> usize {
+        self.state.lock().unwrap().in_use
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.limiter.state.lock().unwrap().in_use -= 1;
+        self.limiter.released.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_limit() {
+        let limiter = Arc::new(Limiter::new(2));
+        let max_running = Arc::new(AtomicUsize::new(0));
+        let threads = (0..6).map(|_| {
+            let limiter = limiter.clone();
+            let max_running = max_running.clone();
+            thread::spawn(move || {
+                let _permit = limiter.acquire();
+                max_running.fetch_max(limiter.in_use(), Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(20));
+            })
+        }).collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(max_running.load(Ordering::SeqCst), 2);
+        assert_eq!(limiter.in_use(), 0);
+    }
+}
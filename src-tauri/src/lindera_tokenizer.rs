@@ -1,5 +1,6 @@
 use lindera_analyzer::analyzer::{Analyzer, AnalyzerConfig};
 use regex::Regex;
+use serde_json::Value;
 
 pub  struct LinderaTokenizer {
     analyzer: Analyzer,
@@ -7,6 +8,34 @@ pub  struct LinderaTokenizer {
 
 impl LinderaTokenizer {
     pub(crate) fn new() -> anyhow::Result<LinderaTokenizer> {
+        let analyzer = Analyzer::from_config(&Self::analyzer_config(true)?).unwrap();
+
+        Ok(LinderaTokenizer { analyzer })
+    }
+
+    /**
+     * Tokenizer without the stop tags and the stemming. Every token is kept, it's useful to
+     * segment the Japanese text into words.
+     */
+    pub(crate) fn segmenter() -> anyhow::Result<LinderaTokenizer> {
+        let analyzer = Analyzer::from_config(&Self::analyzer_config(false)?).unwrap();
+
+        Ok(LinderaTokenizer { analyzer })
+    }
+
+    /**
+     * Split the text into the normalized tokens, without filtering.
+     */
+    pub fn segment(&self, src: &str) -> anyhow::Result<Vec<String>> {
+        let tokens = self.analyzer.analyze(src)
+            .map_err(|err| anyhow::anyhow!("Cannot analyze text: {:?}", err))?;
+        Ok(tokens.iter()
+            .map(|token| token.text.to_string())
+            .filter(|text| !text.trim().is_empty())
+            .collect())
+    }
+
+    fn analyzer_config(with_token_filters: bool) -> anyhow::Result<AnalyzerConfig> {
         let json5_str = r#"
 {
   "character_filters": [
@@ -82,11 +111,12 @@ impl LinderaTokenizer {
 }        "#;
         let re = Regex::new(r"//.*").unwrap();
         let json_str = re.replace_all(json5_str, "");
-        let json = json_str.as_bytes();
-        let analyzer_config = AnalyzerConfig::from_slice(json).unwrap();
-        let analyzer = Analyzer::from_config(&analyzer_config).unwrap();
-
-        Ok(LinderaTokenizer { analyzer })
+        let mut json: Value = serde_json::from_str(&json_str)?;
+        if !with_token_filters {
+            json["token_filters"] = Value::Array(vec![]);
+        }
+        let analyzer_config = AnalyzerConfig::from_slice(serde_json::to_vec(&json)?.as_slice()).unwrap();
+        Ok(analyzer_config)
     }
 }
 
@@ -155,4 +185,12 @@ mod tests {
 
         assert_eq!(vec, Vec::<String>::new());
     }
+
+    #[test]
+    fn test_segment() {
+        let tokenizer = LinderaTokenizer::segmenter().unwrap();
+        let vec = tokenizer.segment("私の名前は中野です。").unwrap();
+
+        assert_eq!(vec, vec!["私", "の", "名前", "は", "中野", "です", "。"]);
+    }
 }
@@ -3,7 +3,9 @@
 
 mod mic_audio;
 mod window;
-mod mp3;
+mod encoder;
+mod dsp;
+mod capture_format;
 mod openai;
 mod postprocess;
 mod recording_proc;
@@ -21,25 +23,49 @@ mod tf_idf_summarizer;
 mod transcriber;
 mod openai_transcriber;
 mod entry;
+mod entry_meta;
+mod transcript_export;
+mod transcript_cleaner;
+mod evaluation;
+mod search_index;
+mod retention;
+mod entry_archive;
+mod processing_state;
+mod job_queue;
+mod limiter;
+mod progress;
+mod hooks;
+mod vad;
 
+use std::fs;
 use std::fs::File;
-use std::path::PathBuf;
-use std::sync::mpsc;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, mpsc};
 use std::sync::mpsc::Sender;
 use std::thread;
+use std::time::Duration;
 use anyhow::anyhow;
 use simplelog::ColorChoice;
 use tauri::{CustomMenuItem, Menu, MenuItem, Submenu, WindowBuilder, Manager, AboutMetadata};
 use crate::config::MeetNoteConfig;
-use crate::data_repo::DataRepo;
-use crate::entry::Entry;
-use crate::postprocess::{PostProcessEvent, PostProcessStatus};
+use crate::data_repo::{DataRepo, EntryList, EntryQuery, TrashItem};
+use crate::entry::{EncodedAudio, Entry};
+use crate::entry_archive::ArchiveFormat;
+use crate::evaluation::{EvaluationReport, TranscriberCandidate};
+use crate::job_queue::{Job, JobCommand, JobQueue};
+use crate::processing_state::ProcessingState;
+use crate::progress::{JobStatus, ProgressSink};
 use crate::recording_proc::RecordingEvent;
-use crate::window::WindowInfo;
+use crate::retention::RetentionReport;
+use crate::search_index::SearchHit;
+use crate::transcript_export::ExportFormat;
+use crate::webvtt::Caption;
+use crate::entry_meta::EntryMeta;
+use crate::window::{WindowInfo, WindowPattern};
 
 pub struct MyState {
     pub recording_tx: Sender<RecordingEvent>,
-    pub postprocess_tx: Sender<PostProcessEvent>,
+    pub job_queue: Arc<JobQueue>,
     pub data_repo: DataRepo,
 }
 
@@ -68,19 +94,33 @@ fn get_windows() -> Vec<WindowInfo> {
 }
 
 #[tauri::command]
-fn start_postprocess(command: String, dir: String, state: tauri::State<MyState>) -> Result<(), String> {
-    let entry = Entry::new(PathBuf::from(dir));
-    state.postprocess_tx.send(PostProcessEvent {
-        command,
-        entry,
-    })
+fn start_postprocess(command: JobCommand, dir: String, state: tauri::State<MyState>) -> Result<(), String> {
+    state.job_queue.push(command, dir.as_str(), chrono::Local::now().naive_local())
+        .map(|_| ())
         .map_err(|err| format!("Cannot start postprocess: {:?}", err))
 }
 
 #[tauri::command]
-fn call_recording_process(command: String, path: Option<String>, state: tauri::State<MyState>) -> Result<(), String> {
+fn list_postprocess_queue(state: tauri::State<MyState>) -> Vec<Job> {
+    state.job_queue.list()
+}
+
+#[tauri::command]
+fn cancel_postprocess_job(id: u64, state: tauri::State<MyState>) -> Result<(), String> {
+    state.job_queue.cancel(id)
+        .map_err(|err| format!("Cannot cancel job: {:?}", err))
+}
+
+#[tauri::command]
+fn set_postprocess_job_priority(id: u64, priority: i32, state: tauri::State<MyState>) -> Result<(), String> {
+    state.job_queue.set_priority(id, priority)
+        .map_err(|err| format!("Cannot change priority: {:?}", err))
+}
+
+#[tauri::command]
+fn call_recording_process(command: String, path: Option<String>, window_pattern: Option<WindowPattern>, state: tauri::State<MyState>) -> Result<(), String> {
     state.recording_tx.send(RecordingEvent {
-        command, path
+        command, path, window_pattern
     })
         .map_err(|err| format!("Cannot send message: {:?}", err))
 }
@@ -93,8 +133,203 @@ fn new_entry_path(state: tauri::State<MyState>) -> Result<String, String> {
 }
 
 #[tauri::command]
-fn postprocess_status() -> PostProcessStatus {
-    postprocess::postprocess_status()
+fn list_entries(query: Option<EntryQuery>, state: tauri::State<MyState>) -> Result<EntryList, String> {
+    state.data_repo.list_entries(&query.unwrap_or_default())
+        .map_err(|err| format!("Cannot list entries: {:?}", err))
+}
+
+#[tauri::command]
+fn postprocess_status() -> Vec<JobStatus> {
+    // The events are pushed. This is for the window which is opened after the job was started.
+    progress::in_flight_jobs()
+}
+
+#[tauri::command]
+fn load_transcript(dir: String) -> Result<Vec<Caption>, String> {
+    Entry::new(PathBuf::from(dir)).read_captions()
+        .map_err(|err| format!("Cannot load transcript: {:?}", err))
+}
+
+#[tauri::command]
+fn save_transcript(dir: String, captions: Vec<Caption>) -> Result<(), String> {
+    let entry = Entry::new(PathBuf::from(dir));
+    entry.save_captions(&captions)
+        .map_err(|err| format!("Cannot save transcript: {:?}", err))?;
+    if let Err(err) = search_index::update_entry(&entry) {
+        log::error!("Cannot update search index for {:?}: {:?}", entry.dir, err);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn export_transcript(dir: String, format: ExportFormat) -> Result<String, String> {
+    transcript_export::export_entry(&Entry::new(PathBuf::from(dir)), format)
+        .map_err(|err| format!("Cannot export transcript: {:?}", err))
+        .map(|path| path.to_str().unwrap().to_string())
+}
+
+#[tauri::command]
+fn import_srt(dir: String, srt_file: String) -> Result<(), String> {
+    transcript_export::import_srt(&Entry::new(PathBuf::from(dir)), srt_file.as_str())
+        .map_err(|err| format!("Cannot import SRT file: {:?}", err))
+}
+
+#[tauri::command]
+fn merge_entries(first_dir: String, second_dir: String, state: tauri::State<MyState>) -> Result<String, String> {
+    let entry = state.data_repo.merge_entries(
+        &Entry::new(PathBuf::from(first_dir)),
+        &Entry::new(PathBuf::from(second_dir)),
+    ).map_err(|err| format!("Cannot merge entries: {:?}", err))?;
+
+    let path = entry.dir.to_str().unwrap().to_string();
+    state.job_queue.push(JobCommand::RegenerateSummary, path.as_str(), chrono::Local::now().naive_local())
+        .map_err(|err| format!("Cannot start postprocess: {:?}", err))?;
+    Ok(path)
+}
+
+#[tauri::command]
+fn clip_entry(dir: String, start_millis: u64, end_millis: u64, state: tauri::State<MyState>) -> Result<String, String> {
+    let entry = state.data_repo.clip_entry(
+        &Entry::new(PathBuf::from(dir)),
+        Duration::from_millis(start_millis),
+        Duration::from_millis(end_millis),
+    ).map_err(|err| format!("Cannot clip entry: {:?}", err))?;
+
+    let path = entry.dir.to_str().unwrap().to_string();
+    state.job_queue.push(JobCommand::RegenerateSummary, path.as_str(), chrono::Local::now().naive_local())
+        .map_err(|err| format!("Cannot start postprocess: {:?}", err))?;
+    Ok(path)
+}
+
+#[tauri::command]
+fn evaluate_transcript(reference_file: String, hypothesis_file: String) -> Result<EvaluationReport, String> {
+    let config = config::load_config_or_default();
+    evaluation::evaluate_files(reference_file.as_str(), hypothesis_file.as_str(), config.language.as_str())
+        .map_err(|err| format!("Cannot evaluate transcript: {:?}", err))
+}
+
+// Transcribing takes long time. Run it outside of the main thread.
+#[tauri::command(async)]
+fn evaluate_transcribers(wav_file: String, reference_file: String, candidates: Vec<TranscriberCandidate>) -> Result<Vec<EvaluationReport>, String> {
+    let config = config::load_config_or_default();
+    evaluation::evaluate_transcribers(&config, wav_file.as_str(), reference_file.as_str(), &candidates)
+        .map_err(|err| format!("Cannot evaluate transcribers: {:?}", err))
+}
+
+#[tauri::command]
+fn save_summary(dir: String, summary: String) -> Result<(), String> {
+    let entry = Entry::new(PathBuf::from(dir));
+    fs::write(entry.md_path(), summary)
+        .map_err(|err| format!("Cannot save summary: {:?}", err))?;
+    if let Err(err) = search_index::update_entry(&entry) {
+        log::error!("Cannot update search index for {:?}: {:?}", entry.dir, err);
+    }
+    Ok(())
+}
+
+#[tauri::command(async)]
+fn search_entries(query: String, limit: Option<usize>) -> Result<Vec<SearchHit>, String> {
+    search_index::search(query.as_str(), limit.unwrap_or(100))
+        .map_err(|err| format!("Cannot search entries: {:?}", err))
+}
+
+#[tauri::command]
+fn delete_entry(dir: String, state: tauri::State<MyState>) -> Result<(), String> {
+    if state.job_queue.has_job(dir.as_str()) {
+        return Err(format!("Cannot delete entry: {} is queued for postprocessing", dir));
+    }
+    let entry = Entry::new(PathBuf::from(dir));
+    state.data_repo.trash_entry(&entry, chrono::Local::now().naive_local())
+        .map_err(|err| format!("Cannot delete entry: {:?}", err))?;
+    if let Err(err) = search_index::remove_entry(&entry) {
+        log::error!("Cannot remove {:?} from search index: {:?}", entry.dir, err);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn list_trash(state: tauri::State<MyState>) -> Result<Vec<TrashItem>, String> {
+    state.data_repo.list_trash()
+        .map_err(|err| format!("Cannot list trash: {:?}", err))
+}
+
+#[tauri::command]
+fn restore_entry(trash_path: String, state: tauri::State<MyState>) -> Result<String, String> {
+    let entry = state.data_repo.restore_from_trash(&PathBuf::from(trash_path))
+        .map_err(|err| format!("Cannot restore entry: {:?}", err))?;
+    if let Err(err) = search_index::update_entry(&entry) {
+        log::error!("Cannot update search index for {:?}: {:?}", entry.dir, err);
+    }
+    Ok(entry.dir.to_str().unwrap().to_string())
+}
+
+#[tauri::command]
+fn empty_trash(state: tauri::State<MyState>) -> Result<usize, String> {
+    state.data_repo.purge_trash(None)
+        .map_err(|err| format!("Cannot empty trash: {:?}", err))
+}
+
+#[tauri::command(async)]
+fn export_entry_archive(dir: String, format: ArchiveFormat, output_dir: String) -> Result<String, String> {
+    entry_archive::export_entry(&Entry::new(PathBuf::from(dir)), format, &PathBuf::from(output_dir))
+        .map_err(|err| format!("Cannot export entry: {:?}", err))
+        .map(|path| path.to_str().unwrap().to_string())
+}
+
+#[tauri::command(async)]
+fn import_entry_archive(archive: String, state: tauri::State<MyState>) -> Result<String, String> {
+    let entry = entry_archive::import_entry(&state.data_repo, &PathBuf::from(archive))
+        .map_err(|err| format!("Cannot import entry: {:?}", err))?;
+    if let Err(err) = search_index::update_entry(&entry) {
+        log::error!("Cannot update search index for {:?}: {:?}", entry.dir, err);
+    }
+    Ok(entry.dir.to_str().unwrap().to_string())
+}
+
+// Dry run of the retention policy. Nothing is deleted.
+#[tauri::command(async)]
+fn retention_report(state: tauri::State<MyState>) -> Result<RetentionReport, String> {
+    let config = config::load_config_or_default();
+    retention::plan(&state.data_repo, &config.retention, chrono::Local::now().naive_local())
+        .map_err(|err| format!("Cannot plan retention: {:?}", err))
+}
+
+#[tauri::command]
+fn load_entry_meta(dir: String) -> Result<EntryMeta, String> {
+    Entry::new(PathBuf::from(dir)).load_meta()
+        .map_err(|err| format!("Cannot load metadata: {:?}", err))
+}
+
+#[tauri::command]
+fn save_entry_meta(dir: String, meta: EntryMeta) -> Result<(), String> {
+    // Only the fields which the user edits. The others are written by the postprocess.
+    Entry::new(PathBuf::from(dir)).update_meta(|current| {
+        current.title = meta.title;
+        current.tags = meta.tags;
+        current.participants = meta.participants;
+        current.notes = meta.notes;
+    }).map_err(|err| format!("Cannot save metadata: {:?}", err))
+}
+
+#[tauri::command]
+fn load_processing_state(dir: String) -> Result<ProcessingState, String> {
+    Entry::new(PathBuf::from(dir)).load_processing_state()
+        .map_err(|err| format!("Cannot load processing state: {:?}", err))
+}
+
+#[tauri::command]
+fn find_encoded_audio(dir: String) -> Option<EncodedAudio> {
+    Entry::new(PathBuf::from(dir)).find_encoded_audio()
+}
+
+struct AppProgressSink(tauri::AppHandle);
+
+impl ProgressSink for AppProgressSink {
+    fn emit(&self, event: &str, payload: serde_json::Value) {
+        if let Err(err) = self.0.emit_all(event, payload) {
+            log::error!("Cannot emit {}: {:?}", event, err);
+        }
+    }
 }
 
 fn main() -> anyhow::Result<()> {
@@ -144,7 +379,9 @@ fn main() -> anyhow::Result<()> {
 
     let misc_menu = Submenu::new("Misc", Menu::new()
         .add_item(CustomMenuItem::new("configuration", "Configuration")
-            .accelerator("Command+,")));
+            .accelerator("Command+,"))
+        .add_item(CustomMenuItem::new("trash", "Trash"))
+        .add_item(CustomMenuItem::new("postprocess_queue", "Postprocess queue")));
     let file_menu = Submenu::new(
         "File",
         Menu::new()
@@ -180,27 +417,77 @@ fn main() -> anyhow::Result<()> {
         .add_submenu(window_menu)
         .add_submenu(misc_menu);
 
-    let (postprocess_tx, postprocess_rx) = mpsc::channel::<PostProcessEvent>();
-    thread::spawn(move || {
-        postprocess::start_postprocess_thread(postprocess_rx)
-    });
+    let data_repo = DataRepo::new()?;
+    // Before the UI loads the entry paths.
+    match data_repo.migrate_to_sharded_layout() {
+        Ok(migrated) => log::info!("Migrated {} entries to the per-day directories", migrated),
+        Err(err) => log::error!("Cannot migrate the data directory: {:?}", err),
+    }
+    let job_queue = Arc::new(JobQueue::open(data_repo::get_app_data_dir()?.join("job_queue.json"))?);
+    // The jobs queued before the migration. Before the workers pick them up.
+    job_queue.remap_paths(|path| data_repo.migrated_entry_dir(Path::new(path))
+        .map(|dir| dir.to_str().unwrap().to_string()))?;
+    for _ in 0..config::load_config_or_default().postprocess_workers.max(1) {
+        let job_queue = job_queue.clone();
+        thread::spawn(move || {
+            postprocess::start_postprocess_thread(job_queue)
+        });
+    }
     let (recording_tx, recording_rx) = mpsc::channel::<RecordingEvent>();
     {
-        let postprocess_tx = postprocess_tx.clone();
+        let job_queue = job_queue.clone();
         thread::spawn(move || {
-            recording_proc::start_recording_process_ex(recording_rx, postprocess_tx);
+            recording_proc::start_recording_process_ex(recording_rx, job_queue);
         });
     }
 
-    let data_repo = DataRepo::new()?;
+    {
+        let job_queue = job_queue.clone();
+        thread::spawn(move || {
+            loop {
+                // Reload every time. The user may change the policy.
+                let config = config::load_config_or_default();
+                let result = DataRepo::new()
+                    .and_then(|data_repo| {
+                        let deleted_before = chrono::Local::now().naive_local()
+                            - chrono::Duration::days(config.trash_retention_days as i64);
+                        let purged = data_repo.purge_trash(Some(deleted_before))?;
+                        if purged > 0 {
+                            log::info!("Purged {} entries from the trash", purged);
+                        }
+                        retention::run(&data_repo, &config.retention, &job_queue)
+                    });
+                if let Err(err) = result {
+                    log::error!("Cannot apply retention policy: {:?}", err);
+                }
+                thread::sleep(Duration::from_secs(60 * 60));
+            }
+        });
+    }
+    thread::spawn(|| {
+        // Catch up the entries which were changed outside of the app.
+        let result = DataRepo::new()
+            .and_then(|data_repo| search_index::refresh(&data_repo));
+        if let Err(err) = result {
+            log::error!("Cannot refresh search index: {:?}", err);
+        }
+        loop {
+            thread::sleep(Duration::from_secs(60));
+            if let Err(err) = search_index::flush() {
+                log::error!("Cannot save search index: {:?}", err);
+            }
+        }
+    });
     tauri::Builder::default()
         .manage(MyState {
             recording_tx,
-            postprocess_tx,
+            job_queue,
             data_repo,
         })
         .menu(menu)
         .setup(|app| {
+            progress::set_sink(Box::new(AppProgressSink(app.handle())));
+
             let window = WindowBuilder::new(
                 app,
                 "main-window".to_string(),
@@ -241,6 +528,26 @@ fn main() -> anyhow::Result<()> {
                         log::error!("Cannot open configuration window: {:?}", err);
                     };
                 }
+                "trash" => {
+                    if let Err(err) = WindowBuilder::new(
+                        &event.window().app_handle(),
+                        "trash-window".to_string(),
+                        tauri::WindowUrl::App("trash.html".into()),
+                    )
+                        .build() {
+                        log::error!("Cannot open trash window: {:?}", err);
+                    };
+                }
+                "postprocess_queue" => {
+                    if let Err(err) = WindowBuilder::new(
+                        &event.window().app_handle(),
+                        "queue-window".to_string(),
+                        tauri::WindowUrl::App("queue.html".into()),
+                    )
+                        .build() {
+                        log::error!("Cannot open queue window: {:?}", err);
+                    };
+                }
                 "window_close" => {
                     log::info!("Closing window: '{:?}'", event.window().title());
                     if let Err(err) = event.window().close() {
@@ -254,10 +561,22 @@ fn main() -> anyhow::Result<()> {
             get_input_devices,
             load_config, save_config,
             get_windows,
-            start_postprocess,
+            start_postprocess, list_postprocess_queue, cancel_postprocess_job, set_postprocess_job_priority,
             call_recording_process,
             postprocess_status,
             new_entry_path,
+            list_entries,
+            load_transcript, save_transcript,
+            export_transcript, import_srt,
+            merge_entries, clip_entry,
+            evaluate_transcript, evaluate_transcribers,
+            load_entry_meta, save_entry_meta,
+            load_processing_state,
+            find_encoded_audio,
+            save_summary, search_entries,
+            retention_report,
+            export_entry_archive, import_entry_archive,
+            delete_entry, list_trash, restore_entry, empty_trash,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
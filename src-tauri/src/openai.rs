@@ -6,6 +6,8 @@ use std::time::Duration;
 use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 
+pub const TRANSCRIPTION_MODEL: &str = "whisper-1";
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Message {
     pub role: String,
@@ -45,6 +47,29 @@ pub struct ChatCompletionResponse {
     pub usage: ChatCompletionUsage,
 }
 
+/**
+ * Non-200 response from the API. 429 and 5xx are worth retrying later.
+ */
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn is_transient(&self) -> bool {
+        self.status == StatusCode::TOO_MANY_REQUESTS || self.status.is_server_error()
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "OpenAI API error. Status: {:?}, {}", self.status, self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
 pub struct OpenAICustomizedClient {
     open_ai_api_key: String,
     client: Client,
@@ -69,7 +94,7 @@ impl OpenAICustomizedClient {
             .file_name(file_path.to_string());
 
         let form = multipart::Form::new()
-            .text("model", "whisper-1")
+            .text("model", TRANSCRIPTION_MODEL)
             .text("language", language.to_string())
             .text("response_format", "vtt")
             .part("file", part);
@@ -82,7 +107,8 @@ impl OpenAICustomizedClient {
         if res.status() == StatusCode::OK {
             Ok(res.text()?)
         } else {
-            Err(anyhow::anyhow!("Transcription failed. Status: {:?}", res.status()))
+            let status = res.status();
+            Err(ApiError { status, message: format!("Transcription failed: {}", res.text()?) }.into())
         }
     }
 
@@ -98,12 +124,10 @@ impl OpenAICustomizedClient {
             let status = res.status();
             let headers = res.headers().clone();
             let text = res.text()?;
-            Err(anyhow::anyhow!(
-                "Chat completion failed. Status: status={:?}, headers={:?}, text={:?}",
+            Err(ApiError {
                 status,
-                headers,
-                text
-            ))
+                message: format!("Chat completion failed. headers={:?}, text={:?}", headers, text),
+            }.into())
         }
     }
 }
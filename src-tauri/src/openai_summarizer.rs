@@ -1,22 +1,20 @@
-use anyhow::anyhow;
-use regex::Regex;
 use tiktoken_rs::{cl100k_base, CoreBPE};
 use crate::openai;
 use crate::openai::OpenAICustomizedClient;
 use crate::summarizer::Summarizer;
+use crate::webvtt::parse_webvtt;
+
+pub const CHAT_MODEL: &str = "gpt-4-32k";
 
 pub struct OpenAISummarizer {
     openai: OpenAICustomizedClient,
-    pub timecode_regex: Regex,
 }
 
 impl OpenAISummarizer {
     pub fn new (openai_api_key: &str) -> anyhow::Result<OpenAISummarizer> {
         let openai = openai::OpenAICustomizedClient::new(openai_api_key)?;
-        let timecode_regex = Regex::new(r"^\d{2}:\d{2}:\d{2}\.\d{3} --> \d{2}:\d{2}:\d{2}\.\d{3}$")?;
         Ok(OpenAISummarizer {
             openai,
-            timecode_regex,
         })
     }
 }
@@ -25,8 +23,9 @@ impl Summarizer for OpenAISummarizer {
     fn summarize(&self, src: &str) -> anyhow::Result<String> {
         let bpe = cl100k_base()?;
 
-        let chunks = self.split(bpe, src, 20000);
+        let chunks = self.split(bpe, src, 20000)?;
         let mut buffer = String::new();
+        let mut last_error = None;
         for chunk in chunks {
             match self.do_summarize(chunk.as_str()) {
                 Ok(result) => {
@@ -34,27 +33,32 @@ impl Summarizer for OpenAISummarizer {
                 }
                 Err(err) => {
                     log::error!("Cannot summarized by openai: {:?}", err);
+                    last_error = Some(err);
                 }
             }
         }
 
-        Ok(buffer)
+        // Nothing was summarized. Fail to retry it later, instead of saving the empty summary.
+        match last_error {
+            Some(err) if buffer.is_empty() => Err(err),
+            _ => Ok(buffer),
+        }
     }
 }
 
 impl OpenAISummarizer {
-    fn split(&self, bpe: CoreBPE, src: &str, chunk_size: usize) -> Vec<String> {
+    fn split(&self, bpe: CoreBPE, src: &str, chunk_size: usize) -> anyhow::Result<Vec<String>> {
         let mut chunks = Vec::new();
         let mut current_chunk = String::new();
         let mut current_token_count = 0;
 
-        for line in src.lines() {
-            if line.is_empty() {
-                continue;
-            }
-            if self.timecode_regex.is_match(line) {
+        for caption in parse_webvtt(src)? {
+            // "Name: text" in a line, so the LLM can tell who said what.
+            let line = caption.labeled_text().replace('\n', " ");
+            if line.trim().is_empty() {
                 continue;
             }
+            let line = line.as_str();
 
             let tokens = bpe.encode_with_special_tokens(line);
             if current_token_count + tokens.len() > chunk_size {
@@ -72,7 +76,7 @@ impl OpenAISummarizer {
             chunks.push(current_chunk);
         }
 
-        chunks
+        Ok(chunks)
     }
 
 
@@ -99,12 +103,12 @@ impl OpenAISummarizer {
         // TODO cleanup webvtt before post
         // TODO split into multiple parts if it's too large
         self.openai.chat_completion(&openai::ChatCompletionRequest {
-            model: "gpt-4-32k".to_string(),
+            model: CHAT_MODEL.to_string(),
             messages: chat_messages,
         }).map(|resp| {
             resp.choices[0].message.content.clone()
         }).map_err(|err| {
-            anyhow!("Cannot generate summary from vtt file: {}",err)
+            err.context("Cannot generate summary from vtt file")
         })
     }
 }
@@ -115,7 +119,8 @@ mod tests {
 
     #[test]
     fn test_split_into_chunks() {
-        let vtt_content = r#"
+        let vtt_content = r#"WEBVTT
+
 00:00:00.000 --> 00:00:02.000
 This is the first line.
 
@@ -128,10 +133,29 @@ This is the third line.
 
         let summarizer = OpenAISummarizer::new("test")
             .unwrap();
-        let got = summarizer.split(cl100k_base().unwrap(), vtt_content, 15);
+        let got = summarizer.split(cl100k_base().unwrap(), vtt_content, 15).unwrap();
         assert_eq!(got, vec![
             "This is the first line.\nThis is the second line.\n",
             "This is the third line.\n"
         ])
     }
+
+    #[test]
+    fn test_split_with_speakers() {
+        let vtt_content = r#"WEBVTT
+
+00:00:00.000 --> 00:00:02.000
+<v Alice>I will send the report by Friday.
+
+00:00:02.000 --> 00:00:04.000
+<v Bob>Thanks.
+"#;
+
+        let summarizer = OpenAISummarizer::new("test")
+            .unwrap();
+        let got = summarizer.split(cl100k_base().unwrap(), vtt_content, 1000).unwrap();
+        assert_eq!(got, vec![
+            "Alice: I will send the report by Friday.\nBob: Thanks.\n",
+        ])
+    }
 }
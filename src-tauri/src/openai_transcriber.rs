@@ -15,7 +15,7 @@ impl OpenAITranscriber {
 }
 
 impl Transcriber for OpenAITranscriber {
-    fn transcribe(&self, in_file: &str, out_file: &str) -> anyhow::Result<()> {
+    fn transcribe(&self, in_file: &str, out_file: &str, _on_progress: &dyn Fn(f32)) -> anyhow::Result<()> {
         match self.openai.transcript(in_file, &self.language) {
             Ok(txt) => {
                 if let Err(err) = fs::write(out_file, txt) {
@@ -1,186 +1,400 @@
+use std::cell::RefCell;
 use std::fs;
-use std::process::Command;
-use std::sync::mpsc::Receiver;
-use crate::mp3;
-use anyhow::{anyhow, Result};
-use lazy_static::lazy_static;
-use crate::config::{load_config_or_default, MeetNoteConfig, TranscriberType};
-use crate::openai::OpenAICustomizedClient;
-use crate::openai_transcriber::OpenAITranscriber;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::sync::Arc;
+use crate::{capture_format, dsp, hooks, progress, search_index, vad};
+use anyhow::{anyhow, Context, Result};
+use crate::dsp::{AudioSource, WavFormat};
+use crate::encoder::ChannelMode;
+use crate::config::{load_config_or_default, MeetNoteConfig, SummarizerType, TranscriberType};
 use crate::summarizer::Summarizer;
-use crate::transcriber::Transcriber;
-use crate::whisper_cpp::WhisperTranscriber;
-use std::sync::RwLock;
-use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+use chrono::Local;
 use crate::entry::Entry;
-
-#[derive(Debug)]
-pub struct PostProcessEvent {
-    pub command: String,
-    pub entry: Entry,
-}
-
-#[derive(Clone, Serialize, Deserialize)]
-pub struct PostProcessStatus {
-    // now processing path
-    path: String,
-    // current status of the 'path' processing
-    message: String,
-    // proceeded paths. once it's proceeded, it's cleared.
-    processed_paths: Vec<String>,
-}
-
-lazy_static! {
-    static ref POSTPROCEDSS_STATE : RwLock<PostProcessStatus> = RwLock::new(PostProcessStatus {
-        path: "".to_string(),
-        message: "".to_string(),
-        processed_paths: Vec::new(),
-    });
-}
-
-pub fn postprocess_status() -> PostProcessStatus {
-    let result = POSTPROCEDSS_STATE.read().unwrap().clone();
-    POSTPROCEDSS_STATE.write().unwrap().processed_paths.clear();
-    return result;
-}
+use crate::entry_meta::EntryMeta;
+use crate::job_queue::{JobCommand, JobQueue};
+use crate::limiter::{CPU_LIMITER, Limiter, NETWORK_LIMITER};
+use crate::limiter;
+use crate::processing_state::{ProcessingState, ProcessingStep, remaining_steps, SilenceTrim, validate_pipeline};
+use crate::progress::{ProgressEvent, ProgressThrottle};
+use crate::transcript_cleaner::TranscriptCleaner;
+use crate::webvtt::{interleave_captions, parse_webvtt, write_webvtt};
 
 pub struct PostProcessor {
     summarizer: Box<dyn Summarizer>,
+    // The entry which is processed by this processor.
+    path: RefCell<String>,
 }
 
 impl PostProcessor {
     pub fn new(summarizer: Box<dyn Summarizer>) -> Box<PostProcessor> {
-        Box::new(PostProcessor { summarizer })
+        Box::new(PostProcessor { summarizer, path: RefCell::new(String::new()) })
     }
 
-    fn clear_state(&self) {
-        // clear the status
-        let mut state = POSTPROCEDSS_STATE.write().unwrap();
-        state.message = "".to_string();
-        state.path = "".to_string();
+    fn path(&self) -> String {
+        self.path.borrow().clone()
     }
 
     fn set_state_path(&self, path: String) {
-        let mut state = POSTPROCEDSS_STATE.write().unwrap();
-        state.path = path;
+        *self.path.borrow_mut() = path.clone();
+        progress::emit(ProgressEvent::Started { path });
     }
 
     fn set_state_message(&self, message: &str) {
-        let mut state = POSTPROCEDSS_STATE.write().unwrap();
-        state.message = message.to_string();
+        progress::emit(ProgressEvent::Message { path: self.path(), message: message.to_string() });
+    }
+
+    fn report_progress(&self, step: ProcessingStep, percent: f32) {
+        progress::emit(ProgressEvent::StepProgress { path: self.path(), step, percent });
     }
 
-    fn push_postprocesssed_entries(&self, entry: &Entry) {
-        let mut state = POSTPROCEDSS_STATE.write().unwrap();
-        state.processed_paths.push(entry.dir.to_str().unwrap().to_string());
+    fn finish_state(&self, success: bool) {
+        progress::emit(ProgressEvent::Finished { path: self.path(), success });
     }
 
     pub fn postprocess(&self, entry: Entry, config: MeetNoteConfig) -> Result<()> {
+        self.postprocess_from(entry, config, ProcessingStep::Merge)
+    }
+
+    /**
+     * Resume the postprocessing from the failed(or the next) step.
+     */
+    pub fn retry(&self, entry: Entry, config: MeetNoteConfig) -> Result<()> {
+        let state = entry.load_processing_state()?;
+        match state.resume_step(&config.pipeline) {
+            Some(step) => {
+                log::info!("Retrying {:?} from {:?}", entry.dir, step);
+                self.postprocess_from(entry, config, step)
+            }
+            None => {
+                log::info!("{:?} is processed already", entry.dir);
+                Ok(())
+            }
+        }
+    }
+
+    fn postprocess_from(&self, entry: Entry, config: MeetNoteConfig, from: ProcessingStep) -> Result<()> {
         let path = entry.dir.to_str().unwrap();
         self.set_state_path(path.to_string());
+        update_processing_state(&entry, |state| state.begin_attempt());
 
-        let result = self.do_postprocess(&entry, config);
-        self.clear_state();
-        self.push_postprocesssed_entries(&entry);
+        let result = self.do_postprocess(&entry, config, from);
+        update_search_index(&entry);
+        self.finish_state(result.is_ok());
         result
     }
 
-    pub fn regenerate_summary(&self, entry: Entry) -> Result<()> {
+    pub fn regenerate_summary(&self, entry: Entry, config: MeetNoteConfig) -> Result<()> {
         let path = entry.dir.to_str().unwrap();
         self.set_state_path(path.to_string());
-        self.set_state_message("Summarizing again");
+        update_processing_state(&entry, |state| state.begin_attempt());
+
+        let result = self.run_step(&entry, ProcessingStep::Summarize, step_limiter(ProcessingStep::Summarize, &config), || {
+            self.do_regenerate_summary(&entry, &config)
+        }).and_then(|_| self.run_hooks(&entry, ProcessingStep::Summarize, &config));
+
+        update_search_index(&entry);
+        self.finish_state(result.is_ok());
+        result
+    }
+
+    fn do_regenerate_summary(&self, entry: &Entry, config: &MeetNoteConfig) -> Result<()> {
+        // The entries transcribed before the cleanup stage was introduced. Keep the
+        // original one and clean it up.
+        // If there's the original VTT already, the VTT is cleaned(and possibly edited by the user).
+        if !Path::new(&entry.original_webvtt_path_string()).exists() {
+            self.set_state_message("Cleaning transcript");
+            fs::copy(entry.webvtt_path_string(), entry.original_webvtt_path_string())
+                .map_err(|err| anyhow!("Cannot keep the original VTT file({}): {:?}", entry.webvtt_path_string(), err))?;
+            self.clean_transcript(config, entry)?;
+        }
 
+        self.set_state_message("Summarizing again");
         let summary_file = entry.md_path();
         let vtt_file = entry.webvtt_path_string();
-        let result = self.summarize(vtt_file.as_str(), summary_file.as_str());
-
-        self.clear_state();
-        self.push_postprocesssed_entries(&entry);
+        self.summarize(vtt_file.as_str(), summary_file.as_str())?;
+        record_summarizer(entry, config);
+        Ok(())
+    }
 
+    /**
+     * Run the step, and record the result in the processing state of the entry.
+     */
+    fn run_step<F: FnOnce() -> Result<()>>(&self, entry: &Entry, step: ProcessingStep, limiter: Option<&Limiter>, f: F) -> Result<()> {
+        let _permit = limiter.map(|limiter| {
+            self.set_state_message(format!("Waiting for {:?}", step).as_str());
+            limiter.acquire()
+        });
+
+        update_processing_state(entry, |state| state.start_step(step, Local::now().naive_local()));
+        progress::emit(ProgressEvent::StepStarted { path: self.path(), step });
+        let start = Instant::now();
+        let result = f();
+        update_processing_state(entry, |state| {
+            let now = Local::now().naive_local();
+            match &result {
+                Ok(_) => state.finish_step(step, now),
+                Err(err) => state.fail_step(step, format!("{:?}", err), now),
+            }
+        });
+        progress::emit(match &result {
+            Ok(_) => ProgressEvent::StepFinished {
+                path: self.path(),
+                step,
+                duration_millis: start.elapsed().as_millis() as u64,
+            },
+            Err(err) => ProgressEvent::StepFailed { path: self.path(), step, error: format!("{:?}", err) },
+        });
         result
     }
 
-    fn do_postprocess(&self, entry: &Entry, config: MeetNoteConfig) -> Result<()>{
+    fn do_postprocess(&self, entry: &Entry, config: MeetNoteConfig, from: ProcessingStep) -> Result<()>{
+        validate_pipeline(&config.pipeline)?;
+        for step in remaining_steps(&config.pipeline, from) {
+            self.run_step(entry, step, step_limiter(step, &config), || match step {
+                ProcessingStep::Merge => self.merge(entry, &config),
+                ProcessingStep::Encode => self.encode(entry, &config),
+                ProcessingStep::Transcribe => self.transcribe_entry(entry, &config),
+                ProcessingStep::Summarize => self.summarize_entry(entry, &config),
+                ProcessingStep::Cleanup => {
+                    self.set_state_message("Cleanup");
+                    self.cleanup(entry)
+                }
+            })?;
+            self.run_hooks(entry, step, &config)?;
+        }
+        update_processing_state(entry, |state| state.complete());
+        Ok(())
+    }
+
+    /**
+     * Run the user hooks after the step. The result is in the processing log of the entry.
+     */
+    fn run_hooks(&self, entry: &Entry, step: ProcessingStep, config: &MeetNoteConfig) -> Result<()> {
+        for hook in config.hooks.iter().filter(|hook| hook.after == step) {
+            self.set_state_message(format!("Running hook: {}", hook.command).as_str());
+            let run = hooks::run_hook(hook, entry, Local::now().naive_local());
+            let success = run.success;
+            let exit_code = run.exit_code;
+            update_processing_state(entry, |state| state.record_hook(run));
+
+            if !success && hook.required {
+                let err = anyhow!("Hook after {:?} failed(exit code: {:?}): {}", step, exit_code, hook.command);
+                update_processing_state(entry, |state| {
+                    state.fail_step(step, format!("{:?}", err), Local::now().naive_local())
+                });
+                progress::emit(ProgressEvent::StepFailed { path: self.path(), step, error: format!("{:?}", err) });
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    fn merge(&self, entry: &Entry, config: &MeetNoteConfig) -> Result<()> {
         self.set_state_message("Merging wave files");
-        let merged_wav_file = merge_audio_files(&entry)?;
+        let merged_wav_file = merge_audio_files(entry, config.audio_channel_mode)?;
+        match wav_duration(&merged_wav_file) {
+            Ok(duration) => {
+                update_meta(entry, |meta| {
+                    meta.duration_millis = Some(duration.as_millis() as u64);
+                });
+            }
+            Err(err) => {
+                log::error!("Cannot get the duration of {}: {:?}", merged_wav_file, err);
+            }
+        }
+        Ok(())
+    }
 
-        // convert to MP3
-        self.set_state_message("Convert to MP3");
-        let mp3_file = entry.mp3_path_string();
-        if let Err(e) = mp3::convert_to_mp3(&merged_wav_file, &mp3_file) {
-            return Err(anyhow!("Cannot convert to mp3({} to {}): {:?}", merged_wav_file, mp3_file, e))
+    fn encode(&self, entry: &Entry, config: &MeetNoteConfig) -> Result<()> {
+        let format = config.audio_format;
+        self.set_state_message(format!("Convert to {:?}", format).as_str());
+        let merged_wav_file = entry.merged_wav_path_string();
+        let audio_file = entry.encoded_audio_path_string(format);
+        let throttle = ProgressThrottle::new(|percent| self.report_progress(ProcessingStep::Encode, percent));
+        if let Err(e) = config.build_encoder().encode(&merged_wav_file, &audio_file, &|percent| throttle.report(percent)) {
+            return Err(e.context(format!("Cannot convert to {:?}({} to {})", format, merged_wav_file, audio_file)))
         }
+        update_meta(entry, |meta| {
+            meta.audio_format = Some(format);
+            meta.audio_channel_mode = Some(config.audio_channel_mode);
+        });
+        Ok(())
+    }
 
+    fn transcribe_entry(&self, entry: &Entry, config: &MeetNoteConfig) -> Result<()> {
         // convert to VTT
         self.set_state_message("Transcribing");
-        let vtt_file = entry.webvtt_path_string();
-        self.transcribe(&config, &merged_wav_file, &vtt_file)?;
+        let orig_vtt_file = entry.original_webvtt_path_string();
+        let throttle = ProgressThrottle::new(|percent| self.report_progress(ProcessingStep::Transcribe, percent));
+        let trims = if config.speaker_attribution {
+            self.transcribe_by_speaker(config, entry, &orig_vtt_file, &|percent| throttle.report(percent))?
+        } else {
+            self.transcribe(config, &entry.merged_wav_path_string(), &orig_vtt_file, None, &|percent| throttle.report(percent))?
+                .into_iter()
+                .collect()
+        };
+        update_processing_state(entry, |state| state.silence_trims = trims);
+        update_meta(entry, |meta| {
+            let (transcriber, model) = config.transcriber_description();
+            meta.language = Some(config.language.clone());
+            meta.transcriber = Some(transcriber);
+            meta.transcriber_model = Some(model);
+        });
+
+        // Remove the hallucinations
+        self.set_state_message("Cleaning transcript");
+        self.clean_transcript(config, entry)
+    }
 
+    fn summarize_entry(&self, entry: &Entry, config: &MeetNoteConfig) -> Result<()> {
         // Summarize VTT
         self.set_state_message("Summarizing");
+        let vtt_file = entry.webvtt_path_string();
         let summary_file = entry.md_path();
         self.summarize(vtt_file.as_str(), summary_file.as_str())?;
-
-        // cleanup files
-        self.set_state_message("Cleanup");
-        self.cleanup(&entry)?;
-
+        record_summarizer(entry, config);
         Ok(())
     }
 
+    /**
+     * Remove the intermediate audio files. The files removed already are fine, the step may be retried.
+     */
     pub fn cleanup(&self, entry: &Entry) -> anyhow::Result<()> {
         file_remove(entry.merged_wav_path_string().as_str())?;
         file_remove(entry.mic_wav_path_string().as_str())?;
         for path in entry.list_raw_files()? {
-            file_remove(path.unwrap().to_str().unwrap())?;
+            let path = path?;
+            file_remove(path.to_str().unwrap())?;
+            file_remove(capture_format::header_path(&path).to_str().unwrap())?;
         }
         Ok(())
     }
 
-    pub fn transcribe(&self, config: &MeetNoteConfig, wav_file: &String, vtt_file: &String) -> anyhow::Result<()> {
-        log::info!("Convert {} to {}", wav_file, vtt_file);
+    /**
+     * Transcribe the speech in the wave file. Returns how much silence was skipped, if it's trimmed.
+     */
+    pub fn transcribe(&self, config: &MeetNoteConfig, wav_file: &String, vtt_file: &String, speaker: Option<&str>, on_progress: &dyn Fn(f32)) -> anyhow::Result<Option<SilenceTrim>> {
+        if !config.trim_silence {
+            self.run_transcriber(config, wav_file, vtt_file, on_progress)?;
+            return Ok(None);
+        }
 
-        let transcriber: Box<dyn Transcriber> = match config.transcriber_type {
-            TranscriberType::WhisperCppTranscriberType => {
-                Box::new(WhisperTranscriber::new(
-                    // 1.5.2 to 1.5.4 are not works.
-                    "v1.5.1".to_string(), config.whisper_model.to_string(), config.language.to_string(),
-                ))
-            }
-            TranscriberType::OpenAITranscriberType => {
-                let token = match &config.openai_api_token {
-                    Some(token) => { token }
-                    None => {
-                        return Err(anyhow!("OpenAI transcriber requires OpenAI token. But it's missing."));
-                    }
-                };
-                let openai = match OpenAICustomizedClient::new(
-                    token.as_str()
-                ) {
-                    Ok(openai) => { openai }
-                    Err(err) => {
-                        return Err(anyhow!("Cannot create openai client: {:?}", err))
-                    }
-                };
-
-                Box::new(OpenAITranscriber::new(
-                    openai,
-                    config.language.to_string(),
-                ))
-            }
+        let speech_wav_tmp = new_temp_file(".wav")?;
+        let speech_wav_file = speech_wav_tmp.path().to_str().unwrap().to_string();
+        let trimmed = vad::trim_silence(Path::new(wav_file), speech_wav_tmp.path())
+            .context(format!("Cannot detect the speech in {}", wav_file))?;
+        let speech = trimmed.map.speech_duration().min(trimmed.total);
+        let trim = SilenceTrim {
+            speaker: speaker.map(|speaker| speaker.to_string()),
+            total_millis: trimmed.total.as_millis() as u64,
+            speech_millis: speech.as_millis() as u64,
+            skipped_millis: (trimmed.total - speech).as_millis() as u64,
+            regions: trimmed.map.regions(),
         };
-        match transcriber.transcribe(wav_file, vtt_file) {
+        log::info!("Skipping the silence in {}: {:?}", wav_file, trim);
+
+        if trimmed.map.is_empty() {
+            // Nothing to transcribe. whisper.cpp hallucinates in the silence.
+            fs::write(vtt_file, write_webvtt(&[]))
+                .map_err(|err| anyhow!("Cannot write to file({}): {:?}", vtt_file, err))?;
+            on_progress(100.0);
+            return Ok(Some(trim));
+        }
+
+        let speech_vtt_tmp = new_temp_file(".vtt")?;
+        let speech_vtt_file = speech_vtt_tmp.path().to_str().unwrap().to_string();
+        self.run_transcriber(config, &speech_wav_file, &speech_vtt_file, on_progress)?;
+
+        // Back to the timeline of the recording
+        let content = fs::read_to_string(&speech_vtt_file)?;
+        let captions = parse_webvtt(content.as_str())
+            .map_err(|err| anyhow!("Cannot parse the transcript of {}: {}", wav_file, err))?
+            .into_iter()
+            .map(|caption| trimmed.map.map_caption(caption))
+            .collect::<Vec<_>>();
+        fs::write(vtt_file, write_webvtt(&captions))
+            .map_err(|err| anyhow!("Cannot write to file({}): {:?}", vtt_file, err))?;
+        Ok(Some(trim))
+    }
+
+    fn run_transcriber(&self, config: &MeetNoteConfig, wav_file: &String, vtt_file: &String, on_progress: &dyn Fn(f32)) -> anyhow::Result<()> {
+        log::info!("Convert {} to {}", wav_file, vtt_file);
+
+        let transcriber = config.build_transcriber()?;
+        match transcriber.transcribe(wav_file, vtt_file, on_progress) {
             Ok(_) => {
                 log::info!("Wrote transcript to \"{}\"", vtt_file);
             }
             Err(e) => {
-                return Err(anyhow!("Cannot transcribe from wave file: {:?}, {:?}", wav_file, e))
+                return Err(e.context(format!("Cannot transcribe from wave file: {:?}", wav_file)))
             }
         }
 
         Ok(())
     }
 
+    /**
+     * Transcribe the mic audio and the system audio separately. Cues from the mic are labeled as
+     * the local user, and the others are labeled as the remote participants.
+     */
+    pub fn transcribe_by_speaker(&self, config: &MeetNoteConfig, entry: &Entry, vtt_file: &String, on_progress: &dyn Fn(f32)) -> anyhow::Result<Vec<SilenceTrim>> {
+        let mic_wav_tmp = new_temp_file(".wav")?;
+        let mic_wav_file = mic_wav_tmp.path().to_str().unwrap().to_string();
+        normalize_mic_wav(entry, &mic_wav_file)?;
+
+        let screen_wav_tmp = new_temp_file(".wav")?;
+        let screen_wav_file = screen_wav_tmp.path().to_str().unwrap().to_string();
+        merge_raw_files(entry, &screen_wav_file)?;
+
+        let mut sources = Vec::new();
+        let mut trims = Vec::new();
+        for (i, (wav_file, speaker)) in [
+            (&mic_wav_file, config.local_speaker_name.as_str()),
+            (&screen_wav_file, config.remote_speaker_name.as_str()),
+        ].into_iter().enumerate() {
+            let source_vtt_tmp = new_temp_file(".vtt")?;
+            let source_vtt_file = source_vtt_tmp.path().to_str().unwrap().to_string();
+            // The first half is the mic, and the second half is the system audio.
+            let trim = self.transcribe(config, wav_file, &source_vtt_file, Some(speaker), &|percent| on_progress((i as f32 * 100.0 + percent) / 2.0))?;
+            trims.extend(trim);
+
+            let content = fs::read_to_string(&source_vtt_file)?;
+            let captions = parse_webvtt(content.as_str())
+                .map_err(|err| anyhow!("Cannot parse the transcript of {}: {}", speaker, err))?;
+            sources.push(captions.into_iter()
+                .map(|caption| caption.with_speaker(speaker))
+                .collect::<Vec<_>>());
+        }
+
+        let captions = interleave_captions(sources);
+        fs::write(vtt_file, write_webvtt(&captions))
+            .map_err(|err| anyhow!("Cannot write to file({}): {:?}", vtt_file, err))?;
+        log::info!("Wrote speaker labeled transcript to \"{}\"", vtt_file);
+        Ok(trims)
+    }
+
+    /**
+     * Clean up the original VTT file, and write the result to the VTT file.
+     */
+    pub fn clean_transcript(&self, config: &MeetNoteConfig, entry: &Entry) -> anyhow::Result<()> {
+        let orig_vtt_file = entry.original_webvtt_path_string();
+        let content = fs::read_to_string(&orig_vtt_file)
+            .map_err(|err| anyhow!("Cannot read VTT file({}): {:?}", orig_vtt_file, err))?;
+        let captions = parse_webvtt(content.as_str())
+            .map_err(|err| anyhow!("Cannot parse VTT file({}): {}", orig_vtt_file, err))?;
+
+        let cleaner = TranscriptCleaner::new(&config.hallucination_blocklist);
+        let captions = cleaner.clean(captions);
+
+        let vtt_file = entry.webvtt_path_string();
+        fs::write(&vtt_file, write_webvtt(&captions))
+            .map_err(|err| anyhow!("Cannot write to file({}): {:?}", vtt_file, err))?;
+        log::info!("Wrote cleaned transcript to \"{}\"", vtt_file);
+        Ok(())
+    }
+
     pub fn summarize(&self, vtt_file: &str, summary_file: &str) -> anyhow::Result<()> {
         let vtt_result = fs::read_to_string(vtt_file);
         let Ok(vtt_content) = vtt_result else {
@@ -193,7 +407,7 @@ impl PostProcessor {
             vtt_file, summary_file);
 
         let summary = self.summarizer.summarize(vtt_content.as_str())
-            .map_err(|err| { anyhow!("Cannot postprocess summarization process {:?}: {:?}", vtt_file, err)})?;
+            .map_err(|err| err.context(format!("Cannot postprocess summarization process {:?}", vtt_file)))?;
 
         if let Err(e) = fs::write(summary_file, summary) {
             return Err(anyhow!("Cannot write to file({}): {:?}",
@@ -207,140 +421,250 @@ impl PostProcessor {
 }
 
 
+// The metadata is informative. Don't fail the postprocess for it.
+fn update_meta<F: FnOnce(&mut EntryMeta)>(entry: &Entry, f: F) {
+    if let Err(err) = entry.update_meta(f) {
+        log::error!("Cannot update metadata of {:?}: {:?}", entry.dir, err);
+    }
+}
+
+/**
+ * The resource which the step uses heavily.
+ */
+fn step_limiter(step: ProcessingStep, config: &MeetNoteConfig) -> Option<&'static Limiter> {
+    match step {
+        ProcessingStep::Merge | ProcessingStep::Encode => Some(&CPU_LIMITER),
+        ProcessingStep::Transcribe => match config.transcriber_type {
+            TranscriberType::WhisperCppTranscriberType => Some(&CPU_LIMITER),
+            TranscriberType::OpenAITranscriberType => Some(&NETWORK_LIMITER),
+        },
+        ProcessingStep::Summarize => match config.summarizer_type {
+            // TF-IDF is cheap enough.
+            SummarizerType::TFIDFSummarizerType => None,
+            SummarizerType::OpenAISummarizerType => Some(&NETWORK_LIMITER),
+        },
+        ProcessingStep::Cleanup => None,
+    }
+}
+
+// The processing state is informative too.
+fn update_processing_state<F: FnOnce(&mut ProcessingState)>(entry: &Entry, f: F) {
+    if let Err(err) = entry.update_processing_state(f) {
+        log::error!("Cannot update processing state of {:?}: {:?}", entry.dir, err);
+    }
+}
+
+// The search index is the cache. Don't fail the postprocessing.
+fn update_search_index(entry: &Entry) {
+    if let Err(err) = search_index::update_entry(entry) {
+        log::error!("Cannot update search index for {:?}: {:?}", entry.dir, err);
+    }
+}
+
+fn record_summarizer(entry: &Entry, config: &MeetNoteConfig) {
+    update_meta(entry, |meta| {
+        let (summarizer, model) = config.summarizer_description();
+        meta.summarizer = Some(summarizer);
+        meta.summarizer_model = model;
+    });
+}
+
+fn wav_duration(wav_file: &str) -> anyhow::Result<Duration> {
+    let reader = hound::WavReader::open(wav_file)?;
+    let spec = reader.spec();
+    Ok(Duration::from_secs_f64(reader.duration() as f64 / spec.sample_rate as f64))
+}
+
 fn file_remove(filename: &str) -> anyhow::Result<()> {
     match fs::remove_file(filename) {
         Ok(_) => {
             log::info!("Removed {:?}", filename);
             Ok(())
         }
+        // Removed by the previous attempt.
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
         Err(err) => {
             Err(anyhow!("Cannot remove {:?}: {:?}", filename, err))
         }
     }
 }
 
-fn merge_audio_files(entry: &Entry) -> anyhow::Result<String> {
-    let mic_wav_file = entry.mic_wav_path_string();
-    let output_wave_file = entry.merged_wav_path_string();
-
-    // merge raw files to 1 wav file
-    let screen_tmp = tempfile::Builder::new()
-        .suffix(".wav")
+fn new_temp_file(suffix: &str) -> anyhow::Result<NamedTempFile> {
+    let file = tempfile::Builder::new()
+        .suffix(suffix)
         .rand_bytes(5)
-        .tempfile()
-        .unwrap();
-    {
-        let raw_files = entry.list_raw_files()?;
-        // log::info!("Processing raw files: {:?}", raw_files);
-
-        let mut path_count = 0;
-
-        let mut command = Command::new("sox");
-        for x in raw_files {
-            // TODO more flexible format support...
-            command
-                .arg("-t").arg("raw")
-                .arg("-r").arg("48000")
-                .arg("-e").arg("floating-point")
-                .arg("-b").arg("32")
-                .arg("-c").arg("1")
-                .arg("--endian").arg("little");
-            command.arg(x.unwrap().to_str().unwrap());
-            path_count += 1;
-        }
-        if path_count == 0 {
-            return Err(anyhow!("Missing raw files for {:?}", mic_wav_file))
-        }
+        .tempfile()?;
+    Ok(file)
+}
 
-        command.arg(screen_tmp.path().to_str().unwrap());
-        command.arg("norm");
-        log::info!("Merge & normalize raw file: {:?}", command);
-        let output = command.output()?;
-        if !output.status.success() {
-            return Err(anyhow!("Cannot run sox: {:?}: {}", command, String::from_utf8_lossy(&output.stderr)));
-        }
+/**
+ * The external command is not installed. It may be installed later, the job is retried.
+ */
+#[derive(Debug)]
+pub struct MissingCommand(pub String);
+
+impl std::fmt::Display for MissingCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Command not found: {}", self.0)
     }
+}
 
-    // normalize mic.wav file.
-    let mic_wav_tmp = tempfile::Builder::new()
-        .suffix(".wav")
-        .rand_bytes(5)
-        .tempfile()
-        .unwrap();
-    {
-        let mut command = Command::new("sox");
-        command
-            .arg(mic_wav_file)
-            .arg(mic_wav_tmp.path().to_str().unwrap())
-            .arg("norm");
-        log::info!("normalize mic wave file: {:?}", command);
-        let output = command.output()?;
-        if !output.status.success() {
-            log::error!("Cannot run sox: {:?}: {}", command, String::from_utf8_lossy(&output.stderr));
-        }
+impl std::error::Error for MissingCommand {}
+
+/**
+ * The system audio. Each raw file is one of the buffers in the sample buffer, e.g. one channel of
+ * the non-interleaved stereo, so the files are mixed, not concatenated.
+ */
+fn open_raw_files(entry: &Entry) -> Result<AudioSource> {
+    let raw_files = entry.list_raw_files()?
+        .collect::<Result<Vec<_>, _>>()?;
+    if raw_files.is_empty() {
+        return Err(anyhow!("Missing raw files for {:?}", entry.dir));
     }
+    let mut sources = Vec::new();
+    for raw_file in raw_files {
+        let format = capture_format::load_header(&raw_file)?;
+        sources.push(dsp::open_raw(&raw_file, &format)?);
+    }
+    // in the sample rate of the first buffer. They are the same unless the header is broken.
+    let sample_rate = sources[0].sample_rate;
+    dsp::mix(sources.into_iter().map(|source| dsp::resample(source, sample_rate)).collect())
+}
+
+// merge raw files to 1 wav file
+fn merge_raw_files(entry: &Entry, output_wav_file: &str) -> anyhow::Result<()> {
+    log::info!("Merge & normalize raw files: {:?}", entry.raw_prefix_path_string());
+    let source = dsp::normalize(|| open_raw_files(entry), 1.0)?;
+    dsp::write_wav(source, Path::new(output_wav_file), WavFormat::Float32)?;
+    Ok(())
+}
 
-    // mix wave files
-    let mut command = Command::new("sox");
-    command
-        .arg("-m") // mix
-        .arg(mic_wav_tmp.path().to_str().unwrap())
-        .arg(screen_tmp.path().to_str().unwrap())
-        .arg(output_wave_file.clone())
-        ;
-    log::info!("Merge audio files: {:?}", command);
-    let output = command
-        .output()?;
-    if !output.status.success() {
-        log::error!("Cannot run sox: {:?}: {}", command, String::from_utf8_lossy(&output.stderr));
+// normalize mic.wav file.
+fn normalize_mic_wav(entry: &Entry, output_wav_file: &str) -> anyhow::Result<()> {
+    log::info!("normalize mic wave file: {}", entry.mic_wav_path_string());
+    let mic_wav_file = entry.mic_wav_path_string();
+    let source = dsp::normalize(|| dsp::open_wav(Path::new(&mic_wav_file)), 1.0)?;
+    dsp::write_wav(source, Path::new(output_wav_file), WavFormat::Float32)?;
+    Ok(())
+}
+
+fn merge_audio_files(entry: &Entry, channel_mode: ChannelMode) -> anyhow::Result<String> {
+    let output_wave_file = entry.merged_wav_path_string();
+
+    let mic_wav_file = entry.mic_wav_path_string();
+    let mic = dsp::normalize(|| dsp::open_wav(Path::new(&mic_wav_file)), 1.0)?;
+    let screen = dsp::normalize(|| open_raw_files(entry), 1.0)?;
+
+    // in the sample rate of the system audio
+    log::info!("Merge audio files: {} and {} to {}({:?})", mic_wav_file, entry.raw_prefix_path_string(), output_wave_file, channel_mode);
+    let sample_rate = screen.sample_rate;
+    let mic = dsp::resample(mic, sample_rate);
+    match channel_mode {
+        ChannelMode::Mono => {
+            let mixed = dsp::mix(vec![mic, screen])?;
+            dsp::write_wav(mixed, Path::new(&output_wave_file), WavFormat::Float32)?;
+        }
+        // The mic on the left, the system audio on the right.
+        ChannelMode::Stereo => {
+            dsp::write_wav_channels(vec![mic, screen], Path::new(&output_wave_file), WavFormat::Float32)?;
+        }
     }
 
     Ok(output_wave_file)
 }
 
-pub fn start_postprocess_thread(rx: Receiver<PostProcessEvent>) {
+/**
+ * The worker. `postprocess_workers` workers take the jobs from the same queue.
+ */
+pub fn start_postprocess_thread(queue: Arc<JobQueue>) {
     loop {
-        match rx.recv() {
-            Ok(event) => {
-                let command = event.command.as_str();
-                let entry = event.entry;
-                let config = load_config_or_default();
-                let summarizer = config.build_summarizer()
-                    .unwrap();
+        let job = queue.wait_next();
+        log::info!("Starting job: {:?}", job);
+
+        let entry = Entry::new(PathBuf::from(&job.path));
+        let config = load_config_or_default();
+        limiter::configure(&config);
+        let result = config.build_summarizer()
+            .and_then(|summarizer| {
                 let post_processor = PostProcessor::new(summarizer);
-
-                let path = entry.dir.to_str().unwrap().to_string();
-                match command {
-                    "ALL" => {
-                        // run all postprocess for normal processing.
-                        match post_processor.postprocess(entry, config) {
-                            Ok(_) => {
-                                log::info!("Successfully processed: {}", path);
-                            }
-                            Err(e) => {
-                                log::error!("Cannot process {}: {:?}", path, e)
-                            }
-                        }
-                    }
-                    "REGENERATE_SUMMARY" => {
-                        // run all postprocess for normal processing.
-                        match post_processor.regenerate_summary(entry) {
-                            Ok(_) => {
-                                log::info!("Successfully processed: {}", path);
-                            }
-                            Err(e) => {
-                                log::error!("Cannot process {}: {:?}", path, e)
-                            }
-                        }
-                    }
-                    _ => {
-                        log::error!("Unknown command '{:?}' for path '{:?}'", command, path);
-                    }
+                match job.command {
+                    // run all postprocess for normal processing.
+                    JobCommand::All => post_processor.postprocess(entry, config),
+                    // resume from the failed step
+                    JobCommand::Retry => post_processor.retry(entry, config),
+                    JobCommand::RegenerateSummary => post_processor.regenerate_summary(entry, config),
                 }
+            });
+
+        let result = match result {
+            Ok(_) => {
+                log::info!("Successfully processed: {}", job.path);
+                queue.complete(job.id)
             }
             Err(err) => {
-                log::error!("Cannot receive message: {:?}", err);
+                log::error!("Cannot process {}: {:?}", job.path, err);
+                queue.fail(job.id, &err, Local::now().naive_local())
             }
+        };
+        if let Err(err) = result {
+            log::error!("Cannot update the job queue: {:?}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing_state::ProcessingStage;
+
+    struct StubSummarizer;
+
+    impl Summarizer for StubSummarizer {
+        fn summarize(&self, _webvtt: &str) -> anyhow::Result<String> {
+            Ok("# summary".to_string())
         }
     }
+
+    #[test]
+    fn test_cleanup() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let entry = Entry::new(tmp.path().join("20240101100000"));
+        fs::create_dir_all(&entry.dir)?;
+        let raw_file = PathBuf::from(format!("{}-0.raw", entry.raw_prefix_path_string()));
+        fs::write(&raw_file, [0u8; 8])?;
+        capture_format::save_header(&raw_file, &capture_format::CaptureFormat::legacy())?;
+        fs::write(entry.merged_wav_path_string(), "")?;
+        fs::write(entry.webvtt_path_string(), "WEBVTT\n")?;
+
+        let processor = PostProcessor::new(Box::new(StubSummarizer));
+        processor.cleanup(&entry)?;
+        let mut files = fs::read_dir(&entry.dir)?
+            .map(|it| it.map(|it| it.file_name().to_str().unwrap().to_string()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        files.sort();
+        assert_eq!(files, vec!["20240101100000.vtt"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_retry_summarize_after_cleanup() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let entry = Entry::new(tmp.path().join("20240101100000"));
+        fs::create_dir_all(&entry.dir)?;
+        fs::write(entry.webvtt_path_string(), "WEBVTT\n\n00:00:00.000 --> 00:00:01.000\nhello\n")?;
+        // The audio files were cleaned up by the previous attempt, then the summary failed.
+        let mut state = ProcessingState::default();
+        state.fail_step(ProcessingStep::Summarize, "timeout".to_string(), Local::now().naive_local());
+        entry.save_processing_state(&state)?;
+
+        let config = MeetNoteConfig::default();
+        let step = entry.load_processing_state()?.resume_step(&config.pipeline).unwrap();
+        assert_eq!(step, ProcessingStep::Summarize);
+        let processor = PostProcessor::new(Box::new(StubSummarizer));
+        processor.do_postprocess(&entry, config, step)?;
+
+        assert_eq!(fs::read_to_string(entry.md_path())?, "# summary");
+        assert_eq!(entry.load_processing_state()?.stage, ProcessingStage::Completed);
+        Ok(())
+    }
 }
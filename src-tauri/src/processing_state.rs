@@ -0,0 +1,286 @@
+use anyhow::anyhow;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+const TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.3f";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum ProcessingStage {
+    Recording,
+    #[default]
+    Recorded,
+    Merged,
+    Encoded,
+    Transcribed,
+    Summarized,
+    // Every step in the pipeline is finished.
+    Completed,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum ProcessingStep {
+    Merge,
+    Encode,
+    // Transcribe and clean up the transcript
+    Transcribe,
+    Summarize,
+    // Remove the intermediate audio files
+    Cleanup,
+}
+
+impl ProcessingStep {
+    pub const ALL: [ProcessingStep; 5] = [
+        ProcessingStep::Merge,
+        ProcessingStep::Encode,
+        ProcessingStep::Transcribe,
+        ProcessingStep::Summarize,
+        ProcessingStep::Cleanup,
+    ];
+
+    fn position(&self) -> usize {
+        ProcessingStep::ALL.iter().position(|step| step == self).unwrap()
+    }
+
+    /**
+     * The stage after this step succeeded.
+     */
+    fn completed_stage(&self) -> ProcessingStage {
+        match self {
+            ProcessingStep::Merge => ProcessingStage::Merged,
+            ProcessingStep::Encode => ProcessingStage::Encoded,
+            ProcessingStep::Transcribe => ProcessingStage::Transcribed,
+            ProcessingStep::Summarize | ProcessingStep::Cleanup => ProcessingStage::Summarized,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StepTiming {
+    pub step: ProcessingStep,
+    pub started_at: String,
+    pub finished_at: Option<String>,
+    pub duration_millis: Option<u64>,
+}
+
+/**
+ * The steps should be in the order of `ProcessingStep::ALL`. Some of them can be omitted, but the
+ * merged wave file is required to encode and to transcribe.
+ */
+pub fn validate_pipeline(pipeline: &[ProcessingStep]) -> anyhow::Result<()> {
+    if pipeline.windows(2).any(|pair| pair[0].position() >= pair[1].position()) {
+        return Err(anyhow!("Steps must be in the order of {:?}: {:?}", ProcessingStep::ALL, pipeline));
+    }
+    let needs_merge = pipeline.iter().any(|step| matches!(step, ProcessingStep::Encode | ProcessingStep::Transcribe));
+    if needs_merge && !pipeline.contains(&ProcessingStep::Merge) {
+        return Err(anyhow!("Merge step is required to encode or transcribe: {:?}", pipeline));
+    }
+    Ok(())
+}
+
+/**
+ * The steps in the pipeline, from the step. The steps before it are done already.
+ */
+pub fn remaining_steps(pipeline: &[ProcessingStep], from: ProcessingStep) -> Vec<ProcessingStep> {
+    pipeline.iter()
+        .filter(|step| step.position() >= from.position())
+        .copied()
+        .collect()
+}
+
+// The result of the user hook command.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HookRun {
+    pub after: ProcessingStep,
+    pub command: String,
+    pub started_at: String,
+    pub duration_millis: u64,
+    // None if it's killed by the signal, or it can't be started.
+    pub exit_code: Option<i32>,
+    pub success: bool,
+    // Tail of the stdout and the stderr.
+    pub output: String,
+}
+
+// The silence which was skipped before the transcription.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SilenceTrim {
+    // The speaker, if the sources are transcribed separately.
+    pub speaker: Option<String>,
+    pub total_millis: u64,
+    pub speech_millis: u64,
+    pub skipped_millis: u64,
+    pub regions: usize,
+}
+
+// The number of the hook results to keep.
+const HOOK_RUNS_LIMIT: usize = 50;
+
+/**
+ * Progress of the postprocessing, stored in `processing.json` in the entry directory.
+ * It survives the restart, unlike the status of the postprocess thread.
+ */
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct ProcessingState {
+    #[serde(default)]
+    pub stage: ProcessingStage,
+    // The step which failed, and why.
+    #[serde(default)]
+    pub failed_step: Option<ProcessingStep>,
+    #[serde(default)]
+    pub error: Option<String>,
+    // The last timing of each step
+    #[serde(default)]
+    pub steps: Vec<StepTiming>,
+    // How many times the postprocessing was started.
+    #[serde(default)]
+    pub attempts: u32,
+    // The processing log of the hook commands, oldest first.
+    #[serde(default)]
+    pub hook_runs: Vec<HookRun>,
+    // Of the last transcription
+    #[serde(default)]
+    pub silence_trims: Vec<SilenceTrim>,
+}
+
+impl ProcessingState {
+    pub fn recording() -> ProcessingState {
+        ProcessingState { stage: ProcessingStage::Recording, ..Default::default() }
+    }
+
+    pub fn begin_attempt(&mut self) {
+        self.attempts += 1;
+        self.failed_step = None;
+        self.error = None;
+    }
+
+    pub fn start_step(&mut self, step: ProcessingStep, now: NaiveDateTime) {
+        let timing = StepTiming {
+            step,
+            started_at: now.format(TIME_FORMAT).to_string(),
+            finished_at: None,
+            duration_millis: None,
+        };
+        match self.steps.iter_mut().find(|timing| timing.step == step) {
+            Some(existing) => *existing = timing,
+            None => self.steps.push(timing),
+        }
+    }
+
+    pub fn finish_step(&mut self, step: ProcessingStep, now: NaiveDateTime) {
+        self.stop_timer(step, now);
+        self.stage = step.completed_stage();
+    }
+
+    pub fn fail_step(&mut self, step: ProcessingStep, error: String, now: NaiveDateTime) {
+        self.stop_timer(step, now);
+        self.stage = ProcessingStage::Failed;
+        self.failed_step = Some(step);
+        self.error = Some(error);
+    }
+
+    pub fn complete(&mut self) {
+        self.stage = ProcessingStage::Completed;
+    }
+
+    pub fn record_hook(&mut self, run: HookRun) {
+        self.hook_runs.push(run);
+        if self.hook_runs.len() > HOOK_RUNS_LIMIT {
+            self.hook_runs.remove(0);
+        }
+    }
+
+    fn stop_timer(&mut self, step: ProcessingStep, now: NaiveDateTime) {
+        if let Some(timing) = self.steps.iter_mut().find(|timing| timing.step == step) {
+            timing.finished_at = Some(now.format(TIME_FORMAT).to_string());
+            timing.duration_millis = NaiveDateTime::parse_from_str(timing.started_at.as_str(), TIME_FORMAT).ok()
+                .and_then(|started_at| (now - started_at).to_std().ok())
+                .map(|duration| duration.as_millis() as u64);
+        }
+    }
+
+    /**
+     * The step to run next. None if there's nothing to do.
+     * The recording is treated as finished, the app may have been quit while recording.
+     * The cleanup may be left after the summary, it's the only step which doesn't change the stage.
+     */
+    pub fn resume_step(&self, pipeline: &[ProcessingStep]) -> Option<ProcessingStep> {
+        match self.stage {
+            ProcessingStage::Recording | ProcessingStage::Recorded => Some(ProcessingStep::Merge),
+            ProcessingStage::Merged => Some(ProcessingStep::Encode),
+            ProcessingStage::Encoded => Some(ProcessingStep::Transcribe),
+            ProcessingStage::Transcribed => Some(ProcessingStep::Summarize),
+            ProcessingStage::Summarized => pipeline.contains(&ProcessingStep::Cleanup)
+                .then_some(ProcessingStep::Cleanup),
+            ProcessingStage::Completed => None,
+            ProcessingStage::Failed => self.failed_step.or(Some(ProcessingStep::Merge)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(src: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(src, "%Y%m%d%H%M%S").unwrap()
+    }
+
+    #[test]
+    fn test_transitions() {
+        let mut state = ProcessingState::recording();
+        assert_eq!(state.resume_step(&ProcessingStep::ALL), Some(ProcessingStep::Merge));
+
+        state.begin_attempt();
+        state.start_step(ProcessingStep::Merge, time("20240101100000"));
+        state.finish_step(ProcessingStep::Merge, time("20240101100003"));
+        assert_eq!(state.stage, ProcessingStage::Merged);
+        assert_eq!(state.steps[0].duration_millis, Some(3000));
+        assert_eq!(state.steps[0].finished_at.as_deref(), Some("2024-01-01T10:00:03.000"));
+
+        state.start_step(ProcessingStep::Encode, time("20240101100003"));
+        state.fail_step(ProcessingStep::Encode, "lame is missing".to_string(), time("20240101100004"));
+        assert_eq!(state.stage, ProcessingStage::Failed);
+        assert_eq!(state.error.as_deref(), Some("lame is missing"));
+        assert_eq!(state.resume_step(&ProcessingStep::ALL), Some(ProcessingStep::Encode));
+
+        // Retry
+        state.begin_attempt();
+        assert_eq!(state.attempts, 2);
+        assert_eq!(state.error, None);
+        state.start_step(ProcessingStep::Encode, time("20240101110000"));
+        state.finish_step(ProcessingStep::Encode, time("20240101110010"));
+        assert_eq!(state.steps.len(), 2);
+        assert_eq!(state.steps[1].duration_millis, Some(10_000));
+        assert_eq!(state.resume_step(&ProcessingStep::ALL), Some(ProcessingStep::Transcribe));
+
+        state.finish_step(ProcessingStep::Summarize, time("20240101110010"));
+        assert_eq!(state.resume_step(&ProcessingStep::ALL), Some(ProcessingStep::Cleanup));
+        assert_eq!(state.resume_step(&[ProcessingStep::Merge, ProcessingStep::Summarize]), None);
+        state.finish_step(ProcessingStep::Cleanup, time("20240101110010"));
+        state.complete();
+        assert_eq!(state.resume_step(&ProcessingStep::ALL), None);
+    }
+
+    #[test]
+    fn test_pipeline() {
+        assert!(validate_pipeline(&ProcessingStep::ALL).is_ok());
+        // Keep the raw files, no MP3.
+        let pipeline = [ProcessingStep::Merge, ProcessingStep::Transcribe, ProcessingStep::Summarize];
+        assert!(validate_pipeline(&pipeline).is_ok());
+        assert!(validate_pipeline(&[ProcessingStep::Transcribe, ProcessingStep::Merge]).is_err());
+        assert!(validate_pipeline(&[ProcessingStep::Merge, ProcessingStep::Merge]).is_err());
+        assert!(validate_pipeline(&[ProcessingStep::Transcribe]).is_err());
+
+        assert_eq!(remaining_steps(&pipeline, ProcessingStep::Encode),
+                   vec![ProcessingStep::Transcribe, ProcessingStep::Summarize]);
+        assert_eq!(remaining_steps(&pipeline, ProcessingStep::Cleanup), vec![]);
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let state: ProcessingState = serde_json::from_str(r#"{"stage": "Transcribed"}"#).unwrap();
+        assert_eq!(state.stage, ProcessingStage::Transcribed);
+        assert_eq!(state.attempts, 0);
+    }
+}
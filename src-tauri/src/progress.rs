@@ -0,0 +1,200 @@
+use std::cell::Cell;
+use std::sync::RwLock;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use crate::processing_state::ProcessingStep;
+
+// Every progress of the postprocessing.
+pub const PROGRESS_EVENT: &str = "postprocess_progress";
+// The entry is postprocessed. The payload is the entry path.
+pub const PROCESSED_EVENT: &str = "postprocessed_entry";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind")]
+pub enum ProgressEvent {
+    Started { path: String },
+    Message { path: String, message: String },
+    StepStarted { path: String, step: ProcessingStep },
+    // 0.0 to 100.0
+    StepProgress { path: String, step: ProcessingStep, percent: f32 },
+    StepFinished { path: String, step: ProcessingStep, duration_millis: u64 },
+    StepFailed { path: String, step: ProcessingStep, error: String },
+    Finished { path: String, success: bool },
+}
+
+impl ProgressEvent {
+    pub fn path(&self) -> &str {
+        match self {
+            ProgressEvent::Started { path }
+            | ProgressEvent::Message { path, .. }
+            | ProgressEvent::StepStarted { path, .. }
+            | ProgressEvent::StepProgress { path, .. }
+            | ProgressEvent::StepFinished { path, .. }
+            | ProgressEvent::StepFailed { path, .. }
+            | ProgressEvent::Finished { path, .. } => path,
+        }
+    }
+}
+
+/**
+ * The in-flight job, for the window which is opened after the job was started.
+ */
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct JobStatus {
+    pub path: String,
+    // current status of the 'path' processing
+    pub message: String,
+    pub step: Option<ProcessingStep>,
+    pub percent: Option<f32>,
+}
+
+/**
+ * Where the events go. It's the Tauri app in the app.
+ */
+pub trait ProgressSink: Send + Sync {
+    fn emit(&self, event: &str, payload: serde_json::Value);
+}
+
+lazy_static! {
+    static ref JOBS: RwLock<Vec<JobStatus>> = RwLock::new(Vec::new());
+    static ref SINK: RwLock<Option<Box<dyn ProgressSink>>> = RwLock::new(None);
+}
+
+pub fn set_sink(sink: Box<dyn ProgressSink>) {
+    *SINK.write().unwrap() = Some(sink);
+}
+
+pub fn in_flight_jobs() -> Vec<JobStatus> {
+    JOBS.read().unwrap().clone()
+}
+
+pub fn emit(event: ProgressEvent) {
+    apply_event(&mut JOBS.write().unwrap(), &event);
+
+    if let Some(sink) = SINK.read().unwrap().as_ref() {
+        match serde_json::to_value(&event) {
+            Ok(payload) => sink.emit(PROGRESS_EVENT, payload),
+            Err(err) => log::error!("Cannot serialize {:?}: {:?}", event, err),
+        }
+        if let ProgressEvent::Finished { path, .. } = &event {
+            sink.emit(PROCESSED_EVENT, serde_json::Value::String(path.clone()));
+        }
+    }
+}
+
+fn apply_event(jobs: &mut Vec<JobStatus>, event: &ProgressEvent) {
+    if let ProgressEvent::Started { path } = event {
+        // The job is retried, or the status is loaded already.
+        let job = JobStatus { path: path.clone(), message: "".to_string(), step: None, percent: None };
+        match jobs.iter_mut().find(|job| job.path == *path) {
+            Some(existing) => *existing = job,
+            None => jobs.push(job),
+        }
+        return;
+    }
+    if let ProgressEvent::Finished { path, .. } = event {
+        jobs.retain(|job| job.path != *path);
+        return;
+    }
+
+    let Some(job) = jobs.iter_mut().find(|job| job.path == event.path()) else {
+        return;
+    };
+    match event {
+        ProgressEvent::Message { message, .. } => {
+            job.message = message.clone();
+        }
+        ProgressEvent::StepStarted { step, .. } => {
+            job.step = Some(*step);
+            job.percent = None;
+        }
+        ProgressEvent::StepProgress { percent, .. } => {
+            job.percent = Some(*percent);
+        }
+        ProgressEvent::StepFinished { .. } | ProgressEvent::StepFailed { .. } => {
+            job.step = None;
+            job.percent = None;
+        }
+        ProgressEvent::Started { .. } | ProgressEvent::Finished { .. } => {}
+    }
+}
+
+/**
+ * Report the progress when the integer percentage changes. The commands report it too often.
+ */
+pub struct ProgressThrottle<F: Fn(f32)> {
+    last: Cell<Option<u32>>,
+    f: F,
+}
+
+impl<F: Fn(f32)> ProgressThrottle<F> {
+    pub fn new(f: F) -> ProgressThrottle<F> {
+        ProgressThrottle { last: Cell::new(None), f }
+    }
+
+    pub fn report(&self, percent: f32) {
+        let current = percent.clamp(0.0, 100.0) as u32;
+        if self.last.get() != Some(current) {
+            self.last.set(Some(current));
+            (self.f)(current as f32);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_apply_event() {
+        let path = "/data/a".to_string();
+        let mut jobs = Vec::new();
+        apply_event(&mut jobs, &ProgressEvent::Started { path: path.clone() });
+        apply_event(&mut jobs, &ProgressEvent::StepStarted { path: path.clone(), step: ProcessingStep::Encode });
+        apply_event(&mut jobs, &ProgressEvent::StepProgress { path: path.clone(), step: ProcessingStep::Encode, percent: 40.0 });
+        // Unknown job
+        apply_event(&mut jobs, &ProgressEvent::Message { path: "/data/b".to_string(), message: "foo".to_string() });
+        assert_eq!(jobs, vec![JobStatus {
+            path: path.clone(),
+            message: "".to_string(),
+            step: Some(ProcessingStep::Encode),
+            percent: Some(40.0),
+        }]);
+
+        apply_event(&mut jobs, &ProgressEvent::Finished { path, success: true });
+        assert!(jobs.is_empty());
+    }
+
+    #[test]
+    fn test_apply_event_started_again() {
+        let path = "/data/a".to_string();
+        let mut jobs = Vec::new();
+        apply_event(&mut jobs, &ProgressEvent::Started { path: "/data/b".to_string() });
+        apply_event(&mut jobs, &ProgressEvent::Started { path: path.clone() });
+        apply_event(&mut jobs, &ProgressEvent::StepStarted { path: path.clone(), step: ProcessingStep::Encode });
+        apply_event(&mut jobs, &ProgressEvent::Started { path: path.clone() });
+        assert_eq!(jobs.iter().map(|job| (job.path.as_str(), job.step)).collect::<Vec<_>>(),
+                   vec![("/data/b", None), ("/data/a", None)]);
+
+        apply_event(&mut jobs, &ProgressEvent::Finished { path, success: false });
+        assert_eq!(jobs.len(), 1);
+    }
+
+    #[test]
+    fn test_serialize() {
+        let event = ProgressEvent::StepProgress { path: "/data/a".to_string(), step: ProcessingStep::Transcribe, percent: 5.0 };
+        assert_eq!(serde_json::to_string(&event).unwrap(),
+                   r#"{"kind":"StepProgress","path":"/data/a","step":"Transcribe","percent":5.0}"#);
+    }
+
+    #[test]
+    fn test_throttle() {
+        let reported = RefCell::new(Vec::new());
+        let throttle = ProgressThrottle::new(|percent| reported.borrow_mut().push(percent));
+        for percent in [0.1, 0.5, 1.2, 1.9, 50.0, 120.0] {
+            throttle.report(percent);
+        }
+        assert_eq!(*reported.borrow(), vec![0.0, 1.0, 50.0, 100.0]);
+    }
+}
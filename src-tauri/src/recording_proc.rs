@@ -1,17 +1,22 @@
 use std::path::PathBuf;
-use std::sync::mpsc::{Receiver, Sender};
+use std::sync::Arc;
+use std::sync::mpsc::Receiver;
 use cpal::traits::DeviceTrait;
 use crate::mic_audio;
 use crate::screen_audio::ScreenAudioRecorder;
 use std::time::Instant;
 use mic_audio::MicAudioRecorder;
 use crate::entry::Entry;
-use crate::postprocess::PostProcessEvent;
+use crate::job_queue::{JobCommand, JobQueue};
+use crate::processing_state::{ProcessingStage, ProcessingState};
+use crate::window::WindowPattern;
 
 #[derive(Debug)]
 pub struct RecordingEvent {
     pub command: String,
     pub path: Option<String>,
+    // The window pattern which triggered the recording
+    pub window_pattern: Option<WindowPattern>,
 }
 
 pub struct RecordingProc {
@@ -44,7 +49,7 @@ impl RecordingProc {
         config.target_device
     }
 
-    pub fn start(&mut self, path: String) -> anyhow::Result<()> {
+    pub fn start(&mut self, path: String, window_pattern: Option<WindowPattern>) -> anyhow::Result<()> {
         self.stop();
 
         let target_device = Self::get_target_device();
@@ -71,6 +76,15 @@ impl RecordingProc {
         };
         self.screen_audio_recorder = Some(screen_audio_recorder);
 
+        if let Err(err) = entry.update_meta(|meta| {
+            meta.window_pattern = window_pattern;
+        }) {
+            log::error!("Cannot save metadata: {:?}", err);
+        }
+        if let Err(err) = entry.save_processing_state(&ProcessingState::recording()) {
+            log::error!("Cannot save processing state: {:?}", err);
+        }
+
         self.entry = Some(entry);
 
         Ok(())
@@ -90,11 +104,19 @@ impl RecordingProc {
             self.screen_audio_recorder.take(); // clear
         }
 
-        self.entry.take()
+        let entry = self.entry.take();
+        if let Some(entry) = &entry {
+            if let Err(err) = entry.update_processing_state(|state| {
+                state.stage = ProcessingStage::Recorded;
+            }) {
+                log::error!("Cannot save processing state: {:?}", err);
+            }
+        }
+        entry
     }
 }
 
-pub fn start_recording_process_ex(recording_rx: Receiver<RecordingEvent>, postprocess_tx: Sender<PostProcessEvent>) {
+pub fn start_recording_process_ex(recording_rx: Receiver<RecordingEvent>, job_queue: Arc<JobQueue>) {
     let mut recording_proc = RecordingProc::new();
 
     log::info!("Ready to processing...");
@@ -104,7 +126,7 @@ pub fn start_recording_process_ex(recording_rx: Receiver<RecordingEvent>, postpr
             Ok(event) => {
                 match event.command.as_str() {
                     "START" => {
-                        if let Err(err) = recording_proc.start(event.path.unwrap()) {
+                        if let Err(err) = recording_proc.start(event.path.unwrap(), event.window_pattern) {
                             log::error!("Cannot start recording proc: {:?}", err);
                         }
                     }
@@ -115,10 +137,8 @@ pub fn start_recording_process_ex(recording_rx: Receiver<RecordingEvent>, postpr
                             // usually, under 50milli seconds.
                             log::info!("`stop` took: {:?}", duration);
 
-                            if let Err(err) = postprocess_tx.send(PostProcessEvent {
-                                command: "ALL".to_string(),
-                                entry
-                            }) {
+                            let path = entry.dir.to_str().unwrap();
+                            if let Err(err) = job_queue.push(JobCommand::All, path, chrono::Local::now().naive_local()) {
                                 log::error!("Cannot start postprocess: {:?}", err);
                             }
                         }
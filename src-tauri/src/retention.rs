@@ -0,0 +1,296 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use crate::data_repo::DataRepo;
+use crate::entry::Entry;
+use crate::job_queue::JobQueue;
+use crate::processing_state::ProcessingStage;
+use crate::search_index;
+
+/**
+ * Rules to remove the old recordings. Everything is disabled by default.
+ */
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct RetentionPolicy {
+    // Apply the policy periodically in the background.
+    #[serde(default)]
+    pub enabled: bool,
+    // Delete the audio files older than this. The VTT and the summary are kept.
+    #[serde(default)]
+    pub delete_audio_after_days: Option<u32>,
+    // Delete the whole entry if the transcript has no speech.
+    #[serde(default)]
+    pub delete_entries_without_speech: bool,
+    // Delete the oldest audio files until the total size of the audio files fits in this.
+    #[serde(default)]
+    pub max_audio_storage_gb: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum RetentionReason {
+    Expired,
+    NoSpeech,
+    OverQuota,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RetentionItem {
+    // Entry directory
+    pub path: String,
+    // Files to delete. Empty if the whole entry is deleted.
+    pub files: Vec<String>,
+    pub bytes: u64,
+    pub reason: RetentionReason,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RetentionReport {
+    pub items: Vec<RetentionItem>,
+    // Total size of the audio files before applying the policy.
+    pub audio_bytes: u64,
+    pub deleted_bytes: u64,
+}
+
+struct Candidate {
+    entry: Entry,
+    audio_files: Vec<(PathBuf, u64)>,
+}
+
+/**
+ * Decide what to delete. Nothing is deleted here, the report is the dry run.
+ *
+ * The entries which are not processed completely are never touched. They may be recording,
+ * waiting for the postprocessing, or waiting for the retry of the failed step, which needs the audio.
+ */
+pub fn plan(data_repo: &DataRepo, policy: &RetentionPolicy, now: NaiveDateTime) -> anyhow::Result<RetentionReport> {
+    let mut entries = data_repo.entries()?;
+    entries.sort_by(|a, b| a.basename.cmp(&b.basename));
+
+    let mut items = Vec::new();
+    let mut audio_bytes = 0;
+    // Audio bytes after applying the rules
+    let mut remaining_bytes = 0;
+    let mut remaining: Vec<Candidate> = Vec::new();
+    for entry in entries {
+        let audio_files = entry.audio_files()?.into_iter()
+            .map(|path| {
+                let size = fs::metadata(&path).map(|it| it.len()).unwrap_or(0);
+                (path, size)
+            })
+            .collect::<Vec<_>>();
+        let entry_audio_bytes = audio_files.iter().map(|(_, size)| size).sum::<u64>();
+        audio_bytes += entry_audio_bytes;
+
+        if !is_processed(&entry) {
+            remaining_bytes += entry_audio_bytes;
+            continue;
+        }
+
+        if policy.delete_entries_without_speech && !has_speech(&entry) {
+            items.push(RetentionItem {
+                path: path_string(&entry.dir),
+                files: Vec::new(),
+                bytes: dir_size(&entry.dir),
+                reason: RetentionReason::NoSpeech,
+            });
+            continue;
+        }
+
+        if audio_files.is_empty() {
+            continue;
+        }
+
+        let expired = match (policy.delete_audio_after_days, entry.started_at()) {
+            (Some(days), Some(started_at)) => now - started_at > chrono::Duration::days(days as i64),
+            _ => false,
+        };
+        if expired {
+            items.push(audio_item(&entry, &audio_files, RetentionReason::Expired));
+            continue;
+        }
+
+        remaining_bytes += entry_audio_bytes;
+        remaining.push(Candidate { entry, audio_files });
+    }
+
+    if let Some(max_gb) = policy.max_audio_storage_gb {
+        let max_bytes = (max_gb * 1024.0 * 1024.0 * 1024.0) as u64;
+        // Oldest first
+        for candidate in remaining {
+            if remaining_bytes <= max_bytes {
+                break;
+            }
+            let item = audio_item(&candidate.entry, &candidate.audio_files, RetentionReason::OverQuota);
+            remaining_bytes -= item.bytes;
+            items.push(item);
+        }
+    }
+
+    let deleted_bytes = items.iter().map(|item| item.bytes).sum();
+    Ok(RetentionReport { items, audio_bytes, deleted_bytes })
+}
+
+/**
+ * Delete the files in the report. The whole entries are moved into the trash, they are purged
+ * after the trash retention period. The failures are logged, and the rest are still deleted.
+ */
+pub fn apply(data_repo: &DataRepo, report: &RetentionReport, now: NaiveDateTime) {
+    for item in &report.items {
+        if item.files.is_empty() {
+            log::info!("Moving entry {} to the trash ({:?})", item.path, item.reason);
+            if let Err(err) = data_repo.trash_entry(&Entry::new(PathBuf::from(&item.path)), now) {
+                log::error!("Cannot move {} to the trash: {:?}", item.path, err);
+            }
+        } else {
+            for file in &item.files {
+                log::info!("Deleting {} ({:?})", file, item.reason);
+                if let Err(err) = fs::remove_file(file) {
+                    log::error!("Cannot delete {}: {:?}", file, err);
+                }
+            }
+        }
+    }
+}
+
+/**
+ * Apply the policy, if it's enabled.
+ */
+pub fn run(data_repo: &DataRepo, policy: &RetentionPolicy, job_queue: &JobQueue) -> anyhow::Result<()> {
+    if !policy.enabled {
+        return Ok(());
+    }
+
+    let now = chrono::Local::now().naive_local();
+    let mut report = plan(data_repo, policy, now)?;
+    // The postprocessing reads the files.
+    report.items.retain(|item| !job_queue.has_job(item.path.as_str()));
+    log::info!("Applying retention policy: {} items, {} bytes", report.items.len(),
+        report.items.iter().map(|item| item.bytes).sum::<u64>());
+    apply(data_repo, &report, now);
+
+    for item in report.items.iter().filter(|item| item.files.is_empty()) {
+        if let Err(err) = search_index::remove_entry(&Entry::new(PathBuf::from(&item.path))) {
+            log::error!("Cannot remove {} from search index: {:?}", item.path, err);
+        }
+    }
+    Ok(())
+}
+
+fn is_processed(entry: &Entry) -> bool {
+    match entry.load_processing_state() {
+        Ok(state) => state.stage == ProcessingStage::Completed
+            // Processed before `processing.json` was introduced.
+            || (state.stage == ProcessingStage::Summarized && !entry.processing_state_path().exists()),
+        Err(err) => {
+            log::error!("Cannot read the processing state of {:?}: {:?}", entry.dir, err);
+            false
+        }
+    }
+}
+
+fn has_speech(entry: &Entry) -> bool {
+    match entry.read_captions() {
+        Ok(captions) => captions.iter().any(|caption| !caption.text.trim().is_empty()),
+        Err(err) => {
+            // Don't delete the entry which we can't read.
+            log::error!("Cannot read the transcript of {:?}: {:?}", entry.dir, err);
+            true
+        }
+    }
+}
+
+fn audio_item(entry: &Entry, audio_files: &[(PathBuf, u64)], reason: RetentionReason) -> RetentionItem {
+    RetentionItem {
+        path: path_string(&entry.dir),
+        files: audio_files.iter().map(|(path, _)| path_string(path)).collect(),
+        bytes: audio_files.iter().map(|(_, size)| size).sum(),
+        reason,
+    }
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    walkdir::WalkDir::new(dir).into_iter()
+        .filter_map(|it| it.ok())
+        .filter_map(|it| it.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+fn path_string(path: &Path) -> String {
+    path.to_str().unwrap().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::test_support::EntryFixture;
+    use crate::processing_state::ProcessingState;
+
+    fn create_entry(repo: &DataRepo, basename: &str, vtt: Option<&str>, mp3_bytes: usize, stage: ProcessingStage) -> Entry {
+        let entry = EntryFixture { vtt, mp3_bytes, ..Default::default() }.create(repo.entry_dir(basename));
+        entry.save_processing_state(&ProcessingState { stage, ..Default::default() }).unwrap();
+        entry
+    }
+
+    const SPEECH: &str = "WEBVTT\n\n00:00:01.000 --> 00:00:02.000\nhello\n";
+    const SILENCE: &str = "WEBVTT\n";
+
+    fn now() -> NaiveDateTime {
+        NaiveDateTime::parse_from_str("20240201000000", "%Y%m%d%H%M%S").unwrap()
+    }
+
+    #[test]
+    fn test_plan() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let repo = DataRepo::with_dir(tmp.path());
+        let old = create_entry(&repo, "20240101100000", Some(SPEECH), 100, ProcessingStage::Completed);
+        // Transcribed, but the summary failed. The retry needs the audio.
+        let unfinished = create_entry(&repo, "20240101110000", Some(SILENCE), 100, ProcessingStage::Failed);
+        let silent = create_entry(&repo, "20240120100000", Some(SILENCE), 100, ProcessingStage::Completed);
+        let recent = create_entry(&repo, "20240130100000", Some(SPEECH), 100, ProcessingStage::Completed);
+        let latest = create_entry(&repo, "20240131100000", Some(SPEECH), 100, ProcessingStage::Completed);
+        // Not transcribed yet.
+        let pending = create_entry(&repo, "20240131110000", None, 100, ProcessingStage::Recorded);
+
+        let disabled = plan(&repo, &RetentionPolicy::default(), now())?;
+        assert!(disabled.items.is_empty());
+        assert_eq!(disabled.audio_bytes, 600);
+
+        let policy = RetentionPolicy {
+            enabled: true,
+            delete_audio_after_days: Some(30),
+            delete_entries_without_speech: true,
+            // The pending and the unfinished entries count, but they can't be deleted.
+            max_audio_storage_gb: Some(350.0 / 1024.0 / 1024.0 / 1024.0),
+        };
+        let report = plan(&repo, &policy, now())?;
+        assert_eq!(report.items.iter().map(|item| (item.path.as_str(), item.reason)).collect::<Vec<_>>(), vec![
+            (path_string(&old.dir).as_str(), RetentionReason::Expired),
+            (path_string(&silent.dir).as_str(), RetentionReason::NoSpeech),
+            (path_string(&recent.dir).as_str(), RetentionReason::OverQuota),
+        ]);
+        // The whole directory of the silent entry, including the VTT file.
+        let silent_state_bytes = fs::metadata(silent.processing_state_path())?.len();
+        assert_eq!(report.deleted_bytes, 300 + SILENCE.len() as u64 + silent_state_bytes);
+
+        // Deleted already. The others are still deleted.
+        fs::remove_file(old.mp3_path_string())?;
+        apply(&repo, &report, now());
+        assert!(!PathBuf::from(old.mp3_path_string()).exists());
+        assert!(PathBuf::from(old.webvtt_path_string()).exists());
+        assert!(!silent.dir.exists());
+        // The silent entry can be restored until the trash is purged.
+        let trash = repo.list_trash()?;
+        assert_eq!(trash.iter().map(|item| item.original_path.as_str()).collect::<Vec<_>>(), vec![path_string(&silent.dir).as_str()]);
+        assert!(Path::new(&trash[0].path).join(format!("{}.vtt", silent.basename)).exists());
+        assert!(!PathBuf::from(recent.mp3_path_string()).exists());
+        assert!(PathBuf::from(latest.mp3_path_string()).exists());
+        assert!(PathBuf::from(pending.mp3_path_string()).exists());
+        assert!(PathBuf::from(unfinished.mp3_path_string()).exists());
+
+        assert!(plan(&repo, &policy, now())?.items.is_empty());
+        Ok(())
+    }
+}
@@ -1,6 +1,8 @@
 use std::fs::OpenOptions;
 use std::io::Write;
 use std::ops::Deref;
+use std::path::Path;
+use std::sync::Mutex;
 use anyhow::anyhow;
 use screencapturekit::cm_sample_buffer::CMSampleBuffer;
 use screencapturekit::sc_content_filter::{InitParams, SCContentFilter};
@@ -9,6 +11,8 @@ use screencapturekit::sc_output_handler::{SCStreamOutputType, StreamOutput};
 use screencapturekit::sc_shareable_content::SCShareableContent;
 use screencapturekit::sc_stream::SCStream;
 use screencapturekit::sc_stream_configuration::SCStreamConfiguration;
+use crate::capture_format;
+use crate::capture_format::CaptureFormat;
 
 
 struct ErrorHandler;
@@ -19,29 +23,85 @@ impl StreamErrorHandler for ErrorHandler {
     }
 }
 
+#[derive(Clone, Copy)]
+struct BufferFormat {
+    format: CaptureFormat,
+    // The samples in the other format are dropped.
+    changed: bool,
+}
+
 struct StoreAudioHandler {
     file_prefix: String,
+    // The format of each buffer, which is written in the header.
+    formats: Mutex<Vec<Option<BufferFormat>>>,
+}
+
+impl StoreAudioHandler {
+    /**
+     * Write the header with the first format. Returns false if the format is changed after that, the
+     * samples can't be appended to the raw file in the other format.
+     */
+    fn record_format(&self, raw_file: &str, index: usize, format: CaptureFormat) -> bool {
+        let mut formats = self.formats.lock().unwrap();
+        if formats.len() <= index {
+            formats.resize(index + 1, None);
+        }
+        match formats[index] {
+            None => {
+                log::info!("Capture format of {}: {:?}", raw_file, format);
+                if let Err(err) = capture_format::save_header(Path::new(raw_file), &format) {
+                    log::error!("Cannot save the capture format of {}: {:?}", raw_file, err);
+                }
+                formats[index] = Some(BufferFormat { format, changed: false });
+                true
+            }
+            Some(recorded) if recorded.format != format => {
+                // The raw files are mixed, not concatenated. The new file would be out of sync with the others.
+                if !recorded.changed {
+                    log::error!("Capture format of {} is changed from {:?} to {:?}. The rest of the system audio is not recorded",
+                        raw_file, recorded.format, format);
+                    formats[index] = Some(BufferFormat { changed: true, ..recorded });
+                }
+                false
+            }
+            Some(_) => true,
+        }
+    }
 }
 
 impl StreamOutput for StoreAudioHandler {
     fn did_output_sample_buffer(&self, sample: CMSampleBuffer, _of_type: SCStreamOutputType) {
-        // let format_description = sample.sys_ref.get_format_description()
-        //     .expect("format description");
-        // let description = format_description.audio_format_description_get_stream_basic_description()
-        //     .expect("Get AudioStreamBasicDescription");
-        // println!("format_description={:?}", description);
+        let description = sample.sys_ref.get_format_description()
+            .and_then(|format_description| format_description.audio_format_description_get_stream_basic_description());
 
         let audio_buffers = sample.sys_ref.get_av_audio_buffer_list();
         // println!("audio buffer list: number={:?}", audio_buffers.len());
         for i in 0..audio_buffers.len() {
             let buffer = &audio_buffers[i];
             // println!("  {}: channels={}, size={}", i, buffer.number_channels, buffer.data.len());
+            let raw_file = format!("{}-{}.raw", self.file_prefix, i);
+
+            match description.as_ref().map(|description| CaptureFormat::from_stream_description(
+                description.sample_rate,
+                description.format_id,
+                description.format_flags,
+                description.bits_per_channel,
+                buffer.number_channels,
+            )) {
+                Some(Ok(format)) => {
+                    if !self.record_format(&raw_file, i, format) {
+                        continue;
+                    }
+                }
+                Some(Err(err)) => log::error!("Unsupported capture format of {}: {:?}", raw_file, err),
+                None => log::error!("Missing the stream description of {}", raw_file),
+            }
 
             let mut file = OpenOptions::new()
                 .write(true)
                 .create(true)
                 .append(true)  // Use append mode
-                .open(format!("{}-{}.raw", self.file_prefix, i))
+                .open(&raw_file)
                 .expect("failed to open file");
 
             if let Err(e) = file.write_all(buffer.data.deref()) {
@@ -70,7 +130,7 @@ impl ScreenAudioRecorder {
         };
         let filter = SCContentFilter::new(InitParams::Display(display.clone()));
         let mut stream = SCStream::new(filter, config, ErrorHandler);
-        stream.add_output(StoreAudioHandler { file_prefix }, SCStreamOutputType::Audio);
+        stream.add_output(StoreAudioHandler { file_prefix, formats: Mutex::new(Vec::new()) }, SCStreamOutputType::Audio);
 
         Ok(ScreenAudioRecorder {
             stream,
@@ -0,0 +1,530 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+use anyhow::anyhow;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use crate::data_repo::{DataRepo, get_app_data_dir};
+use crate::entry::{Entry, write_atomically};
+use crate::lindera_tokenizer::LinderaTokenizer;
+use crate::webvtt::format_timestamp;
+
+// Length of the snippet around the first match, in characters.
+const SNIPPET_CONTEXT: usize = 40;
+const INDEX_FILE: &str = "index.json";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum SearchSource {
+    Transcript,
+    Summary,
+}
+
+// The unit of the search hit. A cue in the VTT, or a line in the summary.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Segment {
+    source: SearchSource,
+    // "HH:MM:SS.mmm". None for the summary.
+    start_time: Option<String>,
+    text: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct IndexedEntry {
+    path: String,
+    basename: String,
+    // mtime of the vtt and md files in millis. Used to detect the stale entries.
+    modified: Vec<Option<u64>>,
+    // Every term in the segments, to remove the postings on re-indexing.
+    terms: Vec<String>,
+}
+
+type DocId = u32;
+
+/**
+ * The segments are not in the index, they are in `segments/{doc id}.json` which is written only
+ * when the entry is indexed.
+ */
+#[derive(Serialize, Deserialize, Default)]
+struct SearchIndex {
+    next_id: DocId,
+    entries: HashMap<DocId, IndexedEntry>,
+    // term -> (doc id, segment index)
+    postings: HashMap<String, BTreeSet<(DocId, u32)>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SnippetFragment {
+    pub text: String,
+    pub highlighted: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SearchHit {
+    pub path: String,
+    pub basename: String,
+    pub source: SearchSource,
+    pub start_time: Option<String>,
+    pub snippet: Vec<SnippetFragment>,
+}
+
+/**
+ * The inverted index over the transcripts and the summaries of the every entry.
+ *
+ * The changes are saved by `flush`, not on every update. If the app exits before that, the
+ * entries are re-indexed by `refresh` since they are stale in the saved index.
+ */
+pub struct SearchEngine {
+    index_dir: PathBuf,
+    segmenter: LinderaTokenizer,
+    index: SearchIndex,
+    // entry path -> doc id
+    ids: HashMap<String, DocId>,
+    dirty: bool,
+}
+
+impl SearchEngine {
+    pub fn open(index_dir: PathBuf) -> anyhow::Result<SearchEngine> {
+        let index_path = index_dir.join(INDEX_FILE);
+        let index: SearchIndex = if index_path.exists() {
+            let content = fs::read_to_string(&index_path)?;
+            serde_json::from_str(content.as_str()).unwrap_or_else(|err| {
+                // It's the cache. Rebuild it from scratch.
+                log::error!("Cannot parse search index({:?}), rebuilding: {:?}", index_path, err);
+                SearchIndex::default()
+            })
+        } else {
+            SearchIndex::default()
+        };
+
+        let ids = index.entries.iter()
+            .map(|(id, indexed)| (indexed.path.clone(), *id))
+            .collect();
+        Ok(SearchEngine {
+            index_dir,
+            segmenter: LinderaTokenizer::segmenter()?,
+            index,
+            ids,
+            dirty: false,
+        })
+    }
+
+    /**
+     * Save the index, if it's changed.
+     */
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let content = serde_json::to_string(&self.index)?;
+        write_atomically(&self.index_dir.join(INDEX_FILE), content.as_str())?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    fn segments_path(&self, id: DocId) -> PathBuf {
+        self.index_dir.join("segments").join(format!("{}.json", id))
+    }
+
+    fn load_segments(&self, id: DocId) -> anyhow::Result<Vec<Segment>> {
+        let path = self.segments_path(id);
+        let content = fs::read_to_string(&path)
+            .map_err(|err| anyhow!("Cannot read {:?}: {:?}", path, err))?;
+        Ok(serde_json::from_str(content.as_str())?)
+    }
+
+    /**
+     * Split the text into the normalized terms. The ASCII words are lower cased, and the others
+     * are segmented by lindera.
+     */
+    fn terms(&self, text: &str) -> anyhow::Result<Vec<String>> {
+        let mut terms = Vec::new();
+        for chunk in text.split(|c: char| !c.is_alphanumeric()).filter(|chunk| !chunk.is_empty()) {
+            if chunk.is_ascii() {
+                terms.push(chunk.to_lowercase());
+            } else {
+                for token in self.segmenter.segment(chunk)? {
+                    let token = token.to_lowercase();
+                    if token.chars().any(|c| c.is_alphanumeric()) {
+                        terms.push(token);
+                    }
+                }
+            }
+        }
+        Ok(terms)
+    }
+
+    fn remove(&mut self, path: &str) {
+        let Some(id) = self.ids.remove(path) else {
+            return;
+        };
+        self.dirty = true;
+        let Some(indexed) = self.index.entries.remove(&id) else {
+            return;
+        };
+        for term in indexed.terms {
+            if let Some(postings) = self.index.postings.get_mut(&term) {
+                postings.retain(|(posting_id, _)| *posting_id != id);
+                if postings.is_empty() {
+                    self.index.postings.remove(&term);
+                }
+            }
+        }
+        if let Err(err) = fs::remove_file(self.segments_path(id)) {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                log::error!("Cannot remove the segments of {}: {:?}", path, err);
+            }
+        }
+    }
+
+    fn index_entry(&mut self, entry: &Entry) -> anyhow::Result<()> {
+        let path = entry.dir.to_str().unwrap().to_string();
+        self.remove(path.as_str());
+
+        let mut segments = Vec::new();
+        if Path::new(&entry.webvtt_path_string()).exists() {
+            for caption in entry.read_captions()? {
+                segments.push(Segment {
+                    source: SearchSource::Transcript,
+                    start_time: Some(format_timestamp(caption.start_time)),
+                    text: caption.labeled_text(),
+                });
+            }
+        }
+        if let Ok(summary) = fs::read_to_string(entry.md_path()) {
+            for line in summary.lines().filter(|line| !line.trim().is_empty()) {
+                segments.push(Segment {
+                    source: SearchSource::Summary,
+                    start_time: None,
+                    text: line.to_string(),
+                });
+            }
+        }
+
+        let mut segment_terms = Vec::new();
+        for segment in &segments {
+            segment_terms.push(self.terms(segment.text.as_str())?);
+        }
+
+        let id = self.index.next_id;
+        write_atomically(&self.segments_path(id), serde_json::to_string(&segments)?.as_str())?;
+        self.index.next_id += 1;
+        self.dirty = true;
+
+        let mut terms = BTreeSet::new();
+        for (i, segment_terms) in segment_terms.into_iter().enumerate() {
+            for term in segment_terms {
+                self.index.postings.entry(term.clone())
+                    .or_default()
+                    .insert((id, i as u32));
+                terms.insert(term);
+            }
+        }
+
+        self.index.entries.insert(id, IndexedEntry {
+            path: path.clone(),
+            basename: entry.basename.clone(),
+            modified: modified_times(entry),
+            terms: terms.into_iter().collect(),
+        });
+        self.ids.insert(path, id);
+        Ok(())
+    }
+
+    pub fn update_entry(&mut self, entry: &Entry) -> anyhow::Result<()> {
+        self.index_entry(entry)
+    }
+
+    pub fn remove_entry(&mut self, path: &str) {
+        self.remove(path);
+    }
+
+    /**
+     * Index the new or modified entries, and drop the removed ones.
+     */
+    pub fn refresh(&mut self, data_repo: &DataRepo) -> anyhow::Result<()> {
+        let entries = data_repo.entries()?;
+        let paths: BTreeSet<String> = entries.iter()
+            .map(|entry| entry.dir.to_str().unwrap().to_string())
+            .collect();
+
+        let removed: Vec<String> = self.ids.keys()
+            .filter(|path| !paths.contains(*path))
+            .cloned()
+            .collect();
+        for path in &removed {
+            self.remove(path);
+        }
+
+        let mut updated = 0;
+        for entry in &entries {
+            let path = entry.dir.to_str().unwrap();
+            let fresh = self.ids.get(path)
+                .and_then(|id| self.index.entries.get(id))
+                .is_some_and(|indexed| indexed.modified == modified_times(entry));
+            if fresh {
+                continue;
+            }
+            if let Err(err) = self.index_entry(entry) {
+                log::error!("Cannot index {:?}: {:?}", entry.dir, err);
+                continue;
+            }
+            updated += 1;
+        }
+
+        log::info!("Refreshed search index: updated={}, removed={}", updated, removed.len());
+        self.flush()
+    }
+
+    /**
+     * Find the segments which contain all terms in the query. Newer entries come first.
+     */
+    pub fn search(&self, query: &str, limit: usize) -> anyhow::Result<Vec<SearchHit>> {
+        let terms: BTreeSet<String> = self.terms(query)?.into_iter().collect();
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut matched: Option<BTreeSet<(DocId, u32)>> = None;
+        for term in &terms {
+            let Some(postings) = self.index.postings.get(term) else {
+                return Ok(Vec::new());
+            };
+            matched = Some(match matched {
+                Some(matched) => matched.intersection(postings).cloned().collect(),
+                None => postings.clone(),
+            });
+        }
+
+        let mut hits: Vec<(DocId, &IndexedEntry, u32)> = matched.unwrap_or_default().iter()
+            .filter_map(|(id, i)| self.index.entries.get(id).map(|indexed| (*id, indexed, *i)))
+            .collect();
+        hits.sort_by(|(_, a, a_i), (_, b, b_i)| b.basename.cmp(&a.basename).then(a_i.cmp(b_i)));
+
+        // Only the segments of the hits are loaded.
+        let terms: Vec<String> = terms.into_iter().collect();
+        let mut segments: HashMap<DocId, Option<Vec<Segment>>> = HashMap::new();
+        let mut results = Vec::new();
+        for (id, indexed, i) in hits.into_iter().take(limit) {
+            let loaded = segments.entry(id).or_insert_with(|| match self.load_segments(id) {
+                Ok(loaded) => Some(loaded),
+                Err(err) => {
+                    log::error!("Cannot load the segments of {}: {:?}", indexed.path, err);
+                    None
+                }
+            });
+            let Some(segment) = loaded.as_ref().and_then(|loaded| loaded.get(i as usize)) else {
+                continue;
+            };
+            results.push(SearchHit {
+                path: indexed.path.clone(),
+                basename: indexed.basename.clone(),
+                source: segment.source,
+                start_time: segment.start_time.clone(),
+                snippet: highlight(segment.text.as_str(), &terms),
+            });
+        }
+        Ok(results)
+    }
+}
+
+fn modified_times(entry: &Entry) -> Vec<Option<u64>> {
+    [entry.webvtt_path_string(), entry.md_path()].iter()
+        .map(|path| fs::metadata(path).ok()
+            .and_then(|metadata| metadata.modified().ok())
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_millis() as u64))
+        .collect()
+}
+
+/**
+ * Cut the text around the first match, and split it into the highlighted fragments.
+ * The terms are matched case-insensitively.
+ */
+fn highlight(text: &str, terms: &[String]) -> Vec<SnippetFragment> {
+    let chars: Vec<char> = text.chars().collect();
+    let lower: Vec<char> = chars.iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect();
+
+    let mut marked = vec![false; chars.len()];
+    for term in terms {
+        let term: Vec<char> = term.chars().collect();
+        if term.is_empty() || term.len() > lower.len() {
+            continue;
+        }
+        for start in 0..=(lower.len() - term.len()) {
+            if lower[start..start + term.len()] == term[..] {
+                marked[start..start + term.len()].fill(true);
+            }
+        }
+    }
+
+    let first = marked.iter().position(|m| *m).unwrap_or(0);
+    let from = first.saturating_sub(SNIPPET_CONTEXT);
+    let to = (first + SNIPPET_CONTEXT * 2).min(chars.len());
+
+    let mut fragments: Vec<SnippetFragment> = Vec::new();
+    for i in from..to {
+        match fragments.last_mut() {
+            Some(fragment) if fragment.highlighted == marked[i] => fragment.text.push(chars[i]),
+            _ => fragments.push(SnippetFragment { text: chars[i].to_string(), highlighted: marked[i] }),
+        }
+    }
+    if from > 0 {
+        fragments.insert(0, SnippetFragment { text: "…".to_string(), highlighted: false });
+    }
+    if to < chars.len() {
+        fragments.push(SnippetFragment { text: "…".to_string(), highlighted: false });
+    }
+    fragments
+}
+
+lazy_static! {
+    // Loaded on the first use. The dictionary loading is not cheap.
+    static ref SEARCH_ENGINE: Mutex<Option<SearchEngine>> = Mutex::new(None);
+}
+
+fn with_engine<T, F: FnOnce(&mut SearchEngine) -> anyhow::Result<T>>(f: F) -> anyhow::Result<T> {
+    let mut engine = SEARCH_ENGINE.lock()
+        .map_err(|err| anyhow!("Cannot lock search index: {:?}", err))?;
+    if engine.is_none() {
+        let app_data_dir = get_app_data_dir()?;
+        // The index in the single file, before the segments were split out.
+        let legacy_path = app_data_dir.join("search_index.json");
+        if legacy_path.exists() {
+            fs::remove_file(&legacy_path)?;
+        }
+        *engine = Some(SearchEngine::open(app_data_dir.join("search_index"))?);
+    }
+    f(engine.as_mut().unwrap())
+}
+
+pub fn search(query: &str, limit: usize) -> anyhow::Result<Vec<SearchHit>> {
+    with_engine(|engine| engine.search(query, limit))
+}
+
+pub fn update_entry(entry: &Entry) -> anyhow::Result<()> {
+    with_engine(|engine| engine.update_entry(entry))
+}
+
+pub fn remove_entry(entry: &Entry) -> anyhow::Result<()> {
+    with_engine(|engine| {
+        engine.remove_entry(entry.dir.to_str().unwrap());
+        Ok(())
+    })
+}
+
+/**
+ * Save the changes since the last flush. Called periodically, not to rewrite the index on every update.
+ */
+pub fn flush() -> anyhow::Result<()> {
+    let mut engine = SEARCH_ENGINE.lock()
+        .map_err(|err| anyhow!("Cannot lock search index: {:?}", err))?;
+    match engine.as_mut() {
+        Some(engine) => engine.flush(),
+        None => Ok(()),
+    }
+}
+
+pub fn refresh(data_repo: &DataRepo) -> anyhow::Result<()> {
+    with_engine(|engine| engine.refresh(data_repo))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::test_support::EntryFixture;
+
+    fn create_entry(data_dir: &Path, basename: &str, vtt: &str, summary: &str) -> Entry {
+        EntryFixture { vtt: Some(vtt), summary: Some(summary), ..Default::default() }.create(data_dir.join(basename))
+    }
+
+    fn snippet_text(hit: &SearchHit) -> String {
+        hit.snippet.iter()
+            .map(|fragment| if fragment.highlighted { format!("[{}]", fragment.text) } else { fragment.text.clone() })
+            .collect()
+    }
+
+    #[test]
+    fn test_search() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let data_repo = DataRepo::with_dir(tmp.path());
+        create_entry(&data_repo.data_dir, "20240101100000",
+                     "WEBVTT\n\n00:00:01.000 --> 00:00:03.000\n<v Alice>Let's talk about the Budget\n\n00:00:03.000 --> 00:00:05.000\nOK\n",
+                     "# Summary\n\n- budget review\n");
+        let second = create_entry(&data_repo.data_dir, "20240102100000",
+                                  "WEBVTT\n\n00:01:00.000 --> 00:01:03.000\nThe budget is tight\n",
+                                  "# Summary\n");
+
+        let mut engine = SearchEngine::open(tmp.path().join("search_index"))?;
+        engine.refresh(&data_repo)?;
+
+        let hits = engine.search("BUDGET", 10)?;
+        assert_eq!(hits.len(), 3);
+        assert_eq!(hits[0].basename, "20240102100000");
+        assert_eq!(hits[0].start_time.as_deref(), Some("00:01:00.000"));
+        assert_eq!(snippet_text(&hits[0]), "The [budget] is tight");
+        assert_eq!(hits[1].source, SearchSource::Transcript);
+        assert_eq!(snippet_text(&hits[1]), "Alice: Let's talk about the [Budget]");
+        assert_eq!(hits[2].source, SearchSource::Summary);
+        assert_eq!(hits[2].start_time, None);
+
+        assert_eq!(engine.search("budget alice", 10)?.len(), 1);
+        assert!(engine.search("nothing", 10)?.is_empty());
+        assert!(engine.search("   ", 10)?.is_empty());
+
+        // The index is persisted.
+        let engine = SearchEngine::open(tmp.path().join("search_index"))?;
+        assert_eq!(engine.search("budget", 10)?.len(), 3);
+
+        // Incremental update
+        let mut engine = engine;
+        fs::write(second.webvtt_path_string(), "WEBVTT\n\n00:00:01.000 --> 00:00:02.000\nNothing to say\n")?;
+        engine.update_entry(&second)?;
+        assert_eq!(engine.search("budget", 10)?.len(), 2);
+        assert_eq!(engine.search("nothing", 10)?.len(), 1);
+        assert!(engine.index.postings["nothing"].iter().all(|(id, _)| engine.index.entries[id].path == second.dir.to_str().unwrap()));
+
+        // Not saved until the flush. The stale entry is re-indexed by the refresh.
+        let mut reopened = SearchEngine::open(tmp.path().join("search_index"))?;
+        assert!(reopened.search("nothing", 10)?.is_empty());
+        reopened.refresh(&data_repo)?;
+        assert_eq!(reopened.search("nothing", 10)?.len(), 1);
+        engine.flush()?;
+        assert_eq!(SearchEngine::open(tmp.path().join("search_index"))?.search("nothing", 10)?.len(), 1);
+
+        fs::remove_dir_all(&second.dir)?;
+        engine.refresh(&data_repo)?;
+        assert!(engine.search("nothing", 10)?.is_empty());
+        assert!(!engine.index.postings.contains_key("nothing"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_ja() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let data_repo = DataRepo::with_dir(tmp.path());
+        create_entry(&data_repo.data_dir, "20240101100000",
+                     "WEBVTT\n\n00:00:01.000 --> 00:00:03.000\n明日の会議について\n",
+                     "");
+
+        let mut engine = SearchEngine::open(tmp.path().join("search_index"))?;
+        engine.refresh(&data_repo)?;
+
+        let hits = engine.search("会議", 10)?;
+        assert_eq!(hits.len(), 1);
+        assert_eq!(snippet_text(&hits[0]), "明日の[会議]について");
+        Ok(())
+    }
+
+    #[test]
+    fn test_highlight() {
+        let text = format!("{}needle{}", "a".repeat(50), "b".repeat(100));
+        let fragments = highlight(text.as_str(), &["needle".to_string()]);
+        assert_eq!(fragments.first().unwrap().text, "…");
+        assert_eq!(fragments.last().unwrap().text, "…");
+        assert!(fragments.contains(&SnippetFragment { text: "needle".to_string(), highlighted: true }));
+    }
+}
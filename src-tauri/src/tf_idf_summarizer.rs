@@ -2,50 +2,36 @@ use std::collections::{HashMap, HashSet};
 use crate::lindera_tokenizer::LinderaTokenizer;
 use crate::summarizer::Summarizer;
 use crate::tokenizer::Tokenizer;
-use crate::webvtt::{Caption, parse_webvtt};
+use std::time::Duration;
+use crate::webvtt::{Caption, format_timestamp, parse_webvtt};
 
 /**
  * TF-IDF を用いて、対象の時間帯において、特徴的な文を抽出する。
  */
 pub struct TFIDFSummarizer {
     tokenizer: LinderaTokenizer,
-    ignore_list: HashSet<String>
 }
 
 impl TFIDFSummarizer {
     pub fn new() -> anyhow::Result<TFIDFSummarizer> {
         let tokenizer = LinderaTokenizer::new()?;
 
-        // black list
-        // "ご視聴ありがとうございました" をやたらと whisper.cpp は生成する。
-        // "(ボタンを押す音)" はタイピング音だけのときに文字起こしされる
-        let mut ignore_list = HashSet::new();
-        ignore_list.insert(String::from("ご視聴ありがとうございました"));
-        ignore_list.insert(String::from("(ボタンを押す音)"));
-        ignore_list.insert(String::from("[音声なし]"));
-        ignore_list.insert(String::from("(笑い声)"));
-        ignore_list.insert(String::from("[音楽]"));
-        ignore_list.insert(String::from("(音楽)"));
-
-        Ok(TFIDFSummarizer { tokenizer, ignore_list })
+        // whisper.cpp のハルシネーションは transcript_cleaner で事前に取り除かれている。
+        Ok(TFIDFSummarizer { tokenizer })
     }
 }
 
 struct Row {
-    start_time: u32,
+    start_time: Duration,
     tokens: Vec<String>,
     caption: Caption,
 }
 
 impl Summarizer for TFIDFSummarizer {
     fn summarize(&self, webvtt: &str) -> anyhow::Result<String> {
-        let vec: Vec<Caption> = parse_webvtt(webvtt);
-        let rows: Vec<Row> = vec.iter().filter(
-            |row| {
-                !self.ignore_list.contains(&row.text)
-            }
-        ).map(|row| {
-            let start_time = row.parse_start_time();
+        let vec: Vec<Caption> = parse_webvtt(webvtt)?;
+        let rows: Vec<Row> = vec.iter().map(|row| {
+            let start_time = row.start_time;
             let tokens = self.tokenizer.tokenize(row.text.clone()).unwrap().to_vec();
             Row { start_time, tokens, caption: (*row).clone() }
         }).collect();
@@ -76,9 +62,9 @@ impl Summarizer for TFIDFSummarizer {
             tfidf.insert(term, tf * idf);
         }
 
-        let mut sectioned_captions: HashMap<u32, Row> = HashMap::new();
+        let mut sectioned_captions: HashMap<u64, Row> = HashMap::new();
         for row in rows {
-            let bucket = row.start_time / (5 * 60 * 1000);
+            let bucket = row.start_time.as_secs() / (5 * 60);
             if sectioned_captions.contains_key(&bucket) {
                 let current = sectioned_captions.get(&bucket).unwrap();
                 let current_score:f64 = current.tokens.iter().map(|token| tfidf.get(token).unwrap()).sum();
@@ -91,12 +77,12 @@ impl Summarizer for TFIDFSummarizer {
             }
         }
 
-        let mut keys: Vec<&u32> = sectioned_captions.keys().collect();
+        let mut keys: Vec<&u64> = sectioned_captions.keys().collect();
         keys.sort();
 
         Ok(keys.iter().map(|f| {
             let row = sectioned_captions.get(*f).unwrap();
-            format!("[{}] {}", row.caption.start_time, row.caption.text)
+            format!("[{}] {}", format_timestamp(row.caption.start_time), row.caption.labeled_text())
         }).collect::<Vec<_>>().join("\n"))
     }
 }
@@ -217,4 +203,22 @@ I also like Tan-shio (salted tongue).
             "[00:00:33.000] The speed is definitely good, and it's also nice that it supports Tauri.\n[00:05:10.000] I also like Tan-shio (salted tongue).",
         );
     }
+
+    #[test]
+    fn test_summarize_with_speaker() {
+        let summarizer = TFIDFSummarizer::new().unwrap();
+        let webvtt = r#"WEBVTT
+
+00:00:00.000 --> 00:00:06.000
+<v Alice>Hello~
+
+00:00:07.000 --> 00:00:10.000
+<v Bob>What programming languages are hot recently?
+"#;
+        let result = summarizer.summarize(webvtt).unwrap();
+        assert_eq!(
+            result,
+            "[00:00:07.000] Bob: What programming languages are hot recently?",
+        );
+    }
 }
@@ -1,3 +1,4 @@
 pub trait Transcriber {
-    fn transcribe(&self, in_file: &str, out_file: &str) -> anyhow::Result<()>;
+    // `on_progress` is called with 0.0 to 100.0, if the transcriber knows the progress.
+    fn transcribe(&self, in_file: &str, out_file: &str, on_progress: &dyn Fn(f32)) -> anyhow::Result<()>;
 }
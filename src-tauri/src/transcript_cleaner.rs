@@ -0,0 +1,209 @@
+use std::collections::HashSet;
+use std::time::Duration;
+use crate::webvtt::Caption;
+
+// Runs of this many (or more) near-identical consecutive cues are whisper.cpp's loop.
+const REPEAT_THRESHOLD: usize = 3;
+// Cues are near-identical if the similarity is larger than or equal to this.
+const SIMILARITY_THRESHOLD: f64 = 0.8;
+// Nobody speaks faster than this.
+const MAX_CHARS_PER_SECOND: f64 = 40.0;
+// Long cues with a few characters are hallucinations during the silence.
+// e.g. "ご視聴ありがとうございました" for 30 seconds.
+const LONG_CUE_DURATION: Duration = Duration::from_secs(10);
+const MIN_CHARS_PER_SECOND: f64 = 0.7;
+
+/**
+ * Remove the junk which whisper.cpp generates, before the summarization.
+ */
+pub struct TranscriptCleaner {
+    blocklist: HashSet<String>,
+}
+
+impl TranscriptCleaner {
+    pub fn new(blocklist: &[String]) -> TranscriptCleaner {
+        let blocklist = blocklist.iter()
+            .map(|phrase| normalize(phrase))
+            .filter(|phrase| !phrase.is_empty())
+            .collect();
+        TranscriptCleaner { blocklist }
+    }
+
+    pub fn clean(&self, captions: Vec<Caption>) -> Vec<Caption> {
+        let total = captions.len();
+
+        let captions: Vec<Caption> = captions.into_iter()
+            .filter(|caption| !self.blocklist.contains(&normalize(&caption.text)))
+            .collect();
+        let blocked = total - captions.len();
+
+        let captions: Vec<Caption> = captions.into_iter()
+            .filter(|caption| !is_contradicting_timespan(caption))
+            .collect();
+        let contradicting = total - blocked - captions.len();
+
+        let captions = collapse_repeats(captions);
+        let repeated = total - blocked - contradicting - captions.len();
+
+        log::info!("Cleaned transcript: total={}, blocked={}, contradicting timespan={}, repeated={}",
+            total, blocked, contradicting, repeated);
+        captions
+    }
+}
+
+fn normalize(text: &str) -> String {
+    text.trim()
+        .trim_end_matches(['。', '.', '!', '！', '?', '？'])
+        .trim()
+        .to_string()
+}
+
+fn is_contradicting_timespan(caption: &Caption) -> bool {
+    let chars = caption.text.chars().filter(|c| !c.is_whitespace()).count();
+    if chars == 0 {
+        return false;
+    }
+
+    let duration = caption.end_time.saturating_sub(caption.start_time);
+    let seconds = duration.as_secs_f64();
+    if seconds == 0.0 {
+        return true;
+    }
+
+    let chars_per_second = chars as f64 / seconds;
+    if chars_per_second > MAX_CHARS_PER_SECOND {
+        return true;
+    }
+    duration >= LONG_CUE_DURATION && chars_per_second < MIN_CHARS_PER_SECOND
+}
+
+fn is_similar(a: &Caption, b: &Caption) -> bool {
+    if a.speaker != b.speaker {
+        return false;
+    }
+    similarity(normalize(&a.text).as_str(), normalize(&b.text).as_str()) >= SIMILARITY_THRESHOLD
+}
+
+// 1 - (edit distance / longer length), in characters.
+fn similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let longer = a.len().max(b.len());
+    if longer == 0 {
+        return 1.0;
+    }
+
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for i in 1..=a.len() {
+        current[0] = i;
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            current[j] = (prev[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(prev[j - 1] + cost);
+        }
+        std::mem::swap(&mut prev, &mut current);
+    }
+
+    1.0 - prev[b.len()] as f64 / longer as f64
+}
+
+// Collapse the runs of near-identical cues into the first cue of the run.
+fn collapse_repeats(captions: Vec<Caption>) -> Vec<Caption> {
+    let mut result = Vec::new();
+    let mut run: Vec<Caption> = Vec::new();
+    for caption in captions {
+        if let Some(first) = run.first() {
+            if !is_similar(first, &caption) {
+                flush_run(&mut result, &mut run);
+            }
+        }
+        run.push(caption);
+    }
+    flush_run(&mut result, &mut run);
+    result
+}
+
+fn flush_run(result: &mut Vec<Caption>, run: &mut Vec<Caption>) {
+    if run.len() >= REPEAT_THRESHOLD {
+        result.push(run.swap_remove(0));
+        run.clear();
+    } else {
+        result.append(run);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caption(start_secs: u64, end_secs: u64, text: &str) -> Caption {
+        Caption::new(Duration::from_secs(start_secs), Duration::from_secs(end_secs), text.to_string())
+    }
+
+    fn texts(captions: &[Caption]) -> Vec<&str> {
+        captions.iter().map(|c| c.text.as_str()).collect()
+    }
+
+    #[test]
+    fn removes_blocked_phrases() {
+        let cleaner = TranscriptCleaner::new(&["ご視聴ありがとうございました".to_string()]);
+        let got = cleaner.clean(vec![
+            caption(0, 3, "こんにちは"),
+            caption(3, 6, "ご視聴ありがとうございました。"),
+            caption(6, 9, "ご視聴ありがとうございましたと言った"),
+        ]);
+        assert_eq!(texts(&got), vec!["こんにちは", "ご視聴ありがとうございましたと言った"]);
+    }
+
+    #[test]
+    fn collapses_repeated_cues() {
+        let cleaner = TranscriptCleaner::new(&[]);
+        let got = cleaner.clean(vec![
+            caption(0, 2, "はい"),
+            caption(2, 4, "はい"),
+            caption(4, 7, "それでは始めましょう"),
+            caption(7, 10, "字幕は自動生成されています"),
+            caption(10, 13, "字幕は自動生成されています。"),
+            caption(13, 16, "字幕は自動生成されていま"),
+            caption(16, 19, "字幕は自動生成されています"),
+            caption(19, 22, "終わります"),
+        ]);
+        assert_eq!(texts(&got), vec![
+            "はい", "はい", "それでは始めましょう", "字幕は自動生成されています", "終わります"
+        ]);
+    }
+
+    #[test]
+    fn repeats_by_different_speakers_are_not_collapsed() {
+        let cleaner = TranscriptCleaner::new(&[]);
+        let got = cleaner.clean(vec![
+            caption(0, 2, "了解です").with_speaker("Me"),
+            caption(2, 4, "了解です").with_speaker("Others"),
+            caption(4, 6, "了解です").with_speaker("Me"),
+        ]);
+        assert_eq!(got.len(), 3);
+    }
+
+    #[test]
+    fn removes_contradicting_timespan() {
+        let cleaner = TranscriptCleaner::new(&[]);
+        let got = cleaner.clean(vec![
+            caption(0, 30, "ありがとう"),
+            caption(30, 31, "これは一秒間で話すにはあまりにも長すぎる文章なので、おそらく whisper.cpp の幻覚だと思われます"),
+            caption(31, 31, "zero"),
+            caption(31, 35, "普通の発話です"),
+            caption(35, 50, ""),
+        ]);
+        assert_eq!(texts(&got), vec!["普通の発話です", ""]);
+    }
+
+    #[test]
+    fn test_similarity() {
+        assert_eq!(similarity("abc", "abc"), 1.0);
+        assert_eq!(similarity("", ""), 1.0);
+        assert_eq!(similarity("abcd", "abce"), 0.75);
+        assert_eq!(similarity("abc", ""), 0.0);
+    }
+}
@@ -0,0 +1,272 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use crate::entry::Entry;
+use crate::webvtt::{Caption, format_timestamp, parse_timestamp};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Srt,
+    Text,
+    Markdown,
+    Json,
+    Csv,
+}
+
+impl ExportFormat {
+    /**
+     * File name suffix for the exported file. "md" is already used by the summary.
+     */
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Srt => "srt",
+            ExportFormat::Text => "txt",
+            ExportFormat::Markdown => "transcript.md",
+            ExportFormat::Json => "json",
+            ExportFormat::Csv => "csv",
+        }
+    }
+}
+
+pub fn render(captions: &[Caption], format: ExportFormat) -> anyhow::Result<String> {
+    Ok(match format {
+        ExportFormat::Srt => to_srt(captions),
+        ExportFormat::Text => to_text(captions),
+        ExportFormat::Markdown => to_markdown(captions),
+        ExportFormat::Json => serde_json::to_string_pretty(captions)?,
+        ExportFormat::Csv => to_csv(captions),
+    })
+}
+
+/**
+ * Render the entry's transcript and write it next to the VTT file.
+ * Returns the path of the exported file.
+ */
+pub fn export_entry(entry: &Entry, format: ExportFormat) -> anyhow::Result<PathBuf> {
+    let captions = entry.read_captions()?;
+    let content = render(&captions, format)?;
+    let path = entry.transcript_export_path(format);
+    fs::write(&path, content)
+        .map_err(|err| anyhow!("Cannot write exported transcript({:?}): {:?}", path, err))?;
+    log::info!("Exported transcript to {:?}", path);
+    Ok(path)
+}
+
+/**
+ * Import the SubRip file as the entry's transcript.
+ */
+pub fn import_srt(entry: &Entry, srt_file: &str) -> anyhow::Result<()> {
+    if PathBuf::from(entry.webvtt_path_string()).exists() {
+        return Err(anyhow!("{} already has a transcript", entry.basename));
+    }
+
+    let content = fs::read_to_string(srt_file)
+        .map_err(|err| anyhow!("Cannot read SRT file({}): {:?}", srt_file, err))?;
+    let mut captions = parse_srt(content.as_str())?;
+    captions.sort_by_key(|caption| caption.start_time);
+    entry.save_captions(&captions)
+}
+
+fn format_srt_timestamp(duration: Duration) -> String {
+    format_timestamp(duration).replace('.', ",")
+}
+
+// Join the multi-line cue text into the single line.
+fn single_line(text: &str) -> String {
+    text.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn to_srt(captions: &[Caption]) -> String {
+    let mut buffer = String::new();
+    for (i, caption) in captions.iter().enumerate() {
+        if i > 0 {
+            buffer.push('\n');
+        }
+        buffer.push_str(format!("{}\n{} --> {}\n",
+                                i + 1,
+                                format_srt_timestamp(caption.start_time),
+                                format_srt_timestamp(caption.end_time)).as_str());
+        // SubRip has no voice span. Prefix the speaker name instead.
+        for line in caption.labeled_text().lines().filter(|line| !line.trim().is_empty()) {
+            buffer.push_str(line.trim());
+            buffer.push('\n');
+        }
+    }
+    buffer
+}
+
+pub fn to_text(captions: &[Caption]) -> String {
+    captions.iter()
+        .map(|caption| format!("[{}] {}\n", format_timestamp(caption.start_time), single_line(&caption.labeled_text())))
+        .collect()
+}
+
+pub fn to_markdown(captions: &[Caption]) -> String {
+    let mut buffer = String::from("# Transcript\n\n");
+    for caption in captions {
+        buffer.push_str(format!("- **{}** {}\n",
+                                format_timestamp(caption.start_time),
+                                single_line(&caption.labeled_text())).as_str());
+    }
+    buffer
+}
+
+fn csv_field(src: &str) -> String {
+    if src.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", src.replace('"', "\"\""))
+    } else {
+        src.to_string()
+    }
+}
+
+pub fn to_csv(captions: &[Caption]) -> String {
+    let mut buffer = String::from("start,end,speaker,text\r\n");
+    for caption in captions {
+        buffer.push_str(format!("{},{},{},{}\r\n",
+                                format_timestamp(caption.start_time),
+                                format_timestamp(caption.end_time),
+                                csv_field(caption.speaker.as_deref().unwrap_or("")),
+                                csv_field(&caption.text)).as_str());
+    }
+    buffer
+}
+
+fn parse_srt_timestamp(src: &str) -> Option<Duration> {
+    // SubRip uses ',' as the decimal separator. Some tools emit '.' though.
+    parse_timestamp(src.trim().replace(',', ".").as_str())
+}
+
+/**
+ * Parse the SubRip document.
+ */
+pub fn parse_srt(srt: &str) -> anyhow::Result<Vec<Caption>> {
+    let normalized = srt.strip_prefix('\u{FEFF}').unwrap_or(srt)
+        .replace("\r\n", "\n")
+        .replace('\r', "\n");
+    let lines: Vec<&str> = normalized.split('\n').collect();
+
+    let mut captions = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        if lines[i].trim().is_empty() {
+            i += 1;
+            continue;
+        }
+
+        let block_start = i;
+        while i < lines.len() && !lines[i].trim().is_empty() {
+            i += 1;
+        }
+        let block = &lines[block_start..i];
+
+        // The sequence number is optional in the wild.
+        let timing_index = if block[0].contains("-->") { 0 } else { 1 };
+        let Some(timing) = block.get(timing_index) else {
+            return Err(anyhow!("SRT parse error at line {}: missing timing line", block_start + 1));
+        };
+        let lineno = block_start + timing_index + 1;
+        let Some((start, rest)) = timing.split_once("-->") else {
+            return Err(anyhow!("SRT parse error at line {}: expected timing line, got {:?}", lineno, timing));
+        };
+        // Ignore the position coordinates(X1:... Y2:...) after the end timestamp.
+        let end = rest.split_whitespace().next().unwrap_or("");
+
+        let start_time = parse_srt_timestamp(start)
+            .ok_or_else(|| anyhow!("SRT parse error at line {}: invalid start timestamp: {:?}", lineno, start.trim()))?;
+        let end_time = parse_srt_timestamp(end)
+            .ok_or_else(|| anyhow!("SRT parse error at line {}: invalid end timestamp: {:?}", lineno, end))?;
+
+        let text = block[timing_index + 1..].iter()
+            .map(|line| line.trim())
+            .collect::<Vec<_>>()
+            .join("\n");
+        captions.push(Caption::new(start_time, end_time, text));
+    }
+
+    Ok(captions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn captions() -> Vec<Caption> {
+        vec![
+            Caption::new(Duration::from_millis(0), Duration::from_millis(1500), "こんにちは。".to_string()),
+            Caption::new(Duration::from_millis(1500), Duration::from_millis(62_000), "Hello, \"world\"\nsecond line".to_string()),
+        ]
+    }
+
+    fn captions_with_speaker() -> Vec<Caption> {
+        vec![
+            Caption::new(Duration::from_millis(0), Duration::from_millis(1500), "こんにちは。".to_string()).with_speaker("Alice"),
+            Caption::new(Duration::from_millis(1500), Duration::from_millis(3000), "Hi".to_string()),
+        ]
+    }
+
+    #[test]
+    fn test_to_srt() {
+        assert_eq!(to_srt(&captions()), "1
+00:00:00,000 --> 00:00:01,500
+こんにちは。
+
+2
+00:00:01,500 --> 00:01:02,000
+Hello, \"world\"
+second line
+");
+    }
+
+    #[test]
+    fn test_to_text() {
+        assert_eq!(to_text(&captions()),
+                   "[00:00:00.000] こんにちは。\n[00:00:01.500] Hello, \"world\" second line\n");
+    }
+
+    #[test]
+    fn test_to_markdown() {
+        assert_eq!(to_markdown(&captions()),
+                   "# Transcript\n\n- **00:00:00.000** こんにちは。\n- **00:00:01.500** Hello, \"world\" second line\n");
+    }
+
+    #[test]
+    fn test_to_csv() {
+        assert_eq!(to_csv(&captions()),
+                   "start,end,speaker,text\r\n00:00:00.000,00:00:01.500,,こんにちは。\r\n00:00:01.500,00:01:02.000,,\"Hello, \"\"world\"\"\nsecond line\"\r\n");
+    }
+
+    #[test]
+    fn test_speaker() {
+        assert_eq!(to_text(&captions_with_speaker()),
+                   "[00:00:00.000] Alice: こんにちは。\n[00:00:01.500] Hi\n");
+        assert_eq!(to_csv(&captions_with_speaker()),
+                   "start,end,speaker,text\r\n00:00:00.000,00:00:01.500,Alice,こんにちは。\r\n00:00:01.500,00:00:03.000,,Hi\r\n");
+        assert!(to_srt(&captions_with_speaker()).starts_with("1\n00:00:00,000 --> 00:00:01,500\nAlice: こんにちは。\n"));
+    }
+
+    #[test]
+    fn test_to_json() {
+        let json = render(&captions(), ExportFormat::Json).unwrap();
+        let got: Vec<Caption> = serde_json::from_str(json.as_str()).unwrap();
+        assert_eq!(got, captions());
+        assert!(json.contains("\"start_time\": \"00:00:01.500\""));
+    }
+
+    #[test]
+    fn test_parse_srt() {
+        assert_eq!(parse_srt(to_srt(&captions()).as_str()).unwrap(), captions());
+
+        let got = parse_srt("\u{FEFF}1\r\n00:00:01,000 --> 00:00:02,000 X1:10 X2:20 Y1:0 Y2:5\r\nfoo\r\n").unwrap();
+        assert_eq!(got, vec![
+            Caption::new(Duration::from_millis(1000), Duration::from_millis(2000), "foo".to_string())
+        ]);
+
+        assert!(parse_srt("1\n00:00:01,000 -> 00:00:02,000\nfoo").is_err());
+    }
+}
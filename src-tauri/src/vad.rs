@@ -0,0 +1,332 @@
+use std::path::Path;
+use std::time::Duration;
+use anyhow::Result;
+use hound::WavWriter;
+use crate::dsp;
+use crate::dsp::AudioSource;
+use crate::webvtt::Caption;
+
+const FRAME_MILLIS: u64 = 30;
+// The frames quieter than this are silence, even if the recording is quiet.
+const MIN_SPEECH_DB: f32 = -55.0;
+// Above the noise floor
+const SPEECH_MARGIN_DB: f32 = 12.0;
+// The noise floor is the quiet speech if the recording has almost no silence. The speech is louder
+// than this below the loudest frames.
+const PEAK_RANGE_DB: f32 = 25.0;
+// The fricatives are quiet, but they cross zero often.
+const FRICATIVE_MARGIN_DB: f32 = 6.0;
+const FRICATIVE_ZERO_CROSSING_RATE: f32 = 0.3;
+// Pauses shorter than this are in the speech.
+const MAX_PAUSE_MILLIS: u64 = 600;
+// Clicks, e.g. typing
+const MIN_SPEECH_MILLIS: u64 = 90;
+// Not to cut the beginning and the end of the words.
+const PADDING_MILLIS: u64 = 250;
+
+/**
+ * The speech in the recording, `start..end` in samples.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpeechRegion {
+    pub start: u64,
+    pub end: u64,
+}
+
+struct Frame {
+    energy_db: f32,
+    zero_crossing_rate: f32,
+}
+
+/**
+ * Find the speech by the energy and the zero-crossing rate of the frames.
+ * The threshold follows the noise floor of the recording, but it's never higher than
+ * `PEAK_RANGE_DB` below the loud frames. It prefers transcribing the noise to dropping the speech.
+ */
+pub fn detect_speech(mut source: AudioSource) -> Result<Vec<SpeechRegion>> {
+    let sample_rate = source.sample_rate as u64;
+    let frame_len = (sample_rate * FRAME_MILLIS / 1000).max(1) as usize;
+    let frames = analyze_frames(source.by_ref(), frame_len);
+    source.check()?;
+    if frames.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut energies = frames.iter().map(|frame| frame.energy_db).collect::<Vec<_>>();
+    energies.sort_by(|a, b| a.total_cmp(b));
+    let noise_floor = energies[energies.len() / 10];
+    // Not the max, a click may be the loudest.
+    let peak = energies[energies.len() * 99 / 100];
+    let threshold = (noise_floor + SPEECH_MARGIN_DB)
+        .min(peak - PEAK_RANGE_DB)
+        .max(MIN_SPEECH_DB);
+
+    let is_speech = frames.iter().map(|frame| {
+        frame.energy_db >= threshold
+            || (frame.energy_db >= threshold - FRICATIVE_MARGIN_DB && frame.zero_crossing_rate >= FRICATIVE_ZERO_CROSSING_RATE)
+    });
+
+    // Runs of the speech frames, in frames.
+    let mut runs: Vec<(u64, u64)> = Vec::new();
+    let max_pause = MAX_PAUSE_MILLIS / FRAME_MILLIS;
+    for (i, speech) in is_speech.enumerate() {
+        if !speech {
+            continue;
+        }
+        let i = i as u64;
+        match runs.last_mut() {
+            Some(last) if i - last.1 <= max_pause => last.1 = i + 1,
+            _ => runs.push((i, i + 1)),
+        }
+    }
+
+    let frame_len = frame_len as u64;
+    let total = frames.len() as u64 * frame_len;
+    let padding = sample_rate * PADDING_MILLIS / 1000;
+    let mut regions: Vec<SpeechRegion> = Vec::new();
+    for (start, end) in runs {
+        if (end - start) * FRAME_MILLIS < MIN_SPEECH_MILLIS {
+            continue;
+        }
+        let region = SpeechRegion {
+            start: (start * frame_len).saturating_sub(padding),
+            end: (end * frame_len + padding).min(total),
+        };
+        match regions.last_mut() {
+            Some(last) if region.start <= last.end => last.end = region.end,
+            _ => regions.push(region),
+        }
+    }
+    Ok(regions)
+}
+
+fn analyze_frames(source: impl Iterator<Item = f32>, frame_len: usize) -> Vec<Frame> {
+    let mut frames = Vec::new();
+    let mut sum_squares = 0.0f64;
+    let mut crossings = 0;
+    let mut previous = 0.0f32;
+    let mut count = 0;
+    for sample in source {
+        sum_squares += (sample as f64) * (sample as f64);
+        if count > 0 && (sample < 0.0) != (previous < 0.0) {
+            crossings += 1;
+        }
+        previous = sample;
+        count += 1;
+        if count == frame_len {
+            frames.push(Frame {
+                energy_db: 10.0 * ((sum_squares / frame_len as f64) + 1e-12).log10() as f32,
+                zero_crossing_rate: crossings as f32 / frame_len as f32,
+            });
+            sum_squares = 0.0;
+            crossings = 0;
+            count = 0;
+        }
+    }
+    // The last partial frame is ignored. It's shorter than 30ms.
+    frames
+}
+
+/**
+ * Where the pieces of the trimmed audio are in the original recording.
+ */
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TimeMap {
+    // (start in the trimmed audio, start in the original, length)
+    segments: Vec<(Duration, Duration, Duration)>,
+}
+
+impl TimeMap {
+    pub fn new(regions: &[SpeechRegion], sample_rate: u32) -> TimeMap {
+        let to_duration = |samples: u64| Duration::from_secs_f64(samples as f64 / sample_rate as f64);
+        let mut trimmed = 0;
+        let segments = regions.iter().map(|region| {
+            let segment = (to_duration(trimmed), to_duration(region.start), to_duration(region.end - region.start));
+            trimmed += region.end - region.start;
+            segment
+        }).collect();
+        TimeMap { segments }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    pub fn regions(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn speech_duration(&self) -> Duration {
+        self.segments.iter().map(|(_, _, length)| *length).sum()
+    }
+
+    /**
+     * The end of the cue stays in the piece which it ends, not to jump over the removed silence.
+     */
+    fn to_original(&self, time: Duration, is_end: bool) -> Duration {
+        let segment = self.segments.iter()
+            .rev()
+            .find(|(trimmed, _, _)| if is_end { *trimmed < time } else { *trimmed <= time })
+            .or(self.segments.first());
+        match segment {
+            Some((trimmed, original, length)) => *original + time.saturating_sub(*trimmed).min(*length),
+            None => time,
+        }
+    }
+
+    pub fn map_caption(&self, mut caption: Caption) -> Caption {
+        let start_time = self.to_original(caption.start_time, false);
+        let end_time = self.to_original(caption.end_time, true);
+        caption.start_time = start_time;
+        caption.end_time = end_time.max(start_time);
+        caption
+    }
+}
+
+/**
+ * How much audio was transcribed.
+ */
+pub struct TrimResult {
+    pub map: TimeMap,
+    pub total: Duration,
+}
+
+/**
+ * Write the speech in the WAV file to the mono WAV file, without the silence between them.
+ */
+pub fn trim_silence(wav_file: &Path, output: &Path) -> Result<TrimResult> {
+    let source = dsp::open_wav(wav_file)?;
+    let sample_rate = source.sample_rate;
+    let regions = detect_speech(source)?;
+
+    let mut source = dsp::open_wav(wav_file)?;
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let mut writer = WavWriter::create(output, spec)?;
+    let mut remaining = regions.iter().peekable();
+    let mut total = 0u64;
+    for (i, sample) in source.by_ref().enumerate() {
+        let i = i as u64;
+        total += 1;
+        while remaining.peek().is_some_and(|region| region.end <= i) {
+            remaining.next();
+        }
+        if remaining.peek().is_some_and(|region| region.start <= i) {
+            writer.write_sample(sample)?;
+        }
+    }
+    writer.finalize()?;
+    source.check()?;
+
+    Ok(TrimResult {
+        map: TimeMap::new(&regions, sample_rate),
+        total: Duration::from_secs_f64(total as f64 / sample_rate as f64),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 16000;
+
+    // Quiet noise, like the room tone.
+    fn noise(seconds: f32, seed: &mut u32) -> Vec<f32> {
+        (0..(RATE as f32 * seconds) as usize).map(|_| {
+            *seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            (*seed >> 8) as f32 / (1 << 24) as f32 * 0.002 - 0.001
+        }).collect()
+    }
+
+    fn tone(seconds: f32) -> Vec<f32> {
+        tone_with_amplitude(seconds, 0.3)
+    }
+
+    fn tone_with_amplitude(seconds: f32, amplitude: f32) -> Vec<f32> {
+        (0..(RATE as f32 * seconds) as usize)
+            .map(|i| amplitude * (2.0 * std::f32::consts::PI * 220.0 * i as f32 / RATE as f32).sin())
+            .collect()
+    }
+
+    fn recording() -> Vec<f32> {
+        let mut seed = 1;
+        let mut samples = noise(1.0, &mut seed);
+        samples.extend(tone(1.0));
+        samples.extend(noise(2.0, &mut seed));
+        // a click
+        samples.extend(tone(0.03));
+        samples.extend(noise(2.0, &mut seed));
+        samples.extend(tone(0.5));
+        samples.extend(noise(1.0, &mut seed));
+        samples
+    }
+
+    fn seconds(region: &SpeechRegion) -> (f32, f32) {
+        (region.start as f32 / RATE as f32, region.end as f32 / RATE as f32)
+    }
+
+    #[test]
+    fn test_detect_speech() {
+        let regions = detect_speech(AudioSource::new(RATE, recording().into_iter())).unwrap();
+        assert_eq!(regions.len(), 2, "{:?}", regions);
+        let (start, end) = seconds(&regions[0]);
+        assert!((start - 0.75).abs() < 0.05 && (end - 2.25).abs() < 0.05, "{:?}", (start, end));
+        let (start, end) = seconds(&regions[1]);
+        assert!((start - 5.78).abs() < 0.05 && (end - 6.78).abs() < 0.05, "{:?}", (start, end));
+
+        // silence only
+        let mut seed = 1;
+        assert!(detect_speech(AudioSource::new(RATE, noise(3.0, &mut seed).into_iter())).unwrap().is_empty());
+        assert!(detect_speech(AudioSource::new(RATE, vec![0.0; 100].into_iter())).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_detect_continuous_speech() {
+        // The loud and the quiet speakers talk in turn, without the silence.
+        let mut samples = Vec::new();
+        for _ in 0..2 {
+            samples.extend(tone_with_amplitude(3.0, 0.3));
+            samples.extend(tone_with_amplitude(3.0, 0.03));
+        }
+        let total = samples.len() as u64;
+        let regions = detect_speech(AudioSource::new(RATE, samples.into_iter())).unwrap();
+        assert_eq!(regions, vec![SpeechRegion { start: 0, end: total }]);
+    }
+
+    #[test]
+    fn test_time_map() {
+        let map = TimeMap::new(&[
+            SpeechRegion { start: RATE as u64, end: 3 * RATE as u64 },
+            SpeechRegion { start: 10 * RATE as u64, end: 11 * RATE as u64 },
+        ], RATE);
+        assert_eq!(map.speech_duration(), Duration::from_secs(3));
+
+        let mapped = map.map_caption(Caption::new(Duration::from_millis(500), Duration::from_millis(2000), "a".to_string()));
+        assert_eq!((mapped.start_time, mapped.end_time), (Duration::from_millis(1500), Duration::from_millis(3000)));
+        // The start in the second piece
+        let mapped = map.map_caption(Caption::new(Duration::from_millis(2000), Duration::from_millis(2500), "b".to_string()));
+        assert_eq!((mapped.start_time, mapped.end_time), (Duration::from_millis(10000), Duration::from_millis(10500)));
+        // Over the end of the audio
+        let mapped = map.map_caption(Caption::new(Duration::from_millis(2800), Duration::from_millis(3500), "c".to_string()));
+        assert_eq!((mapped.start_time, mapped.end_time), (Duration::from_millis(10800), Duration::from_millis(11000)));
+    }
+
+    #[test]
+    fn test_trim_silence() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let wav_file = tmp.path().join("in.wav");
+        let output = tmp.path().join("out.wav");
+        dsp::write_wav(AudioSource::new(RATE, recording().into_iter()), &wav_file, dsp::WavFormat::Float32)?;
+
+        let result = trim_silence(&wav_file, &output)?;
+        assert_eq!(result.total, Duration::from_secs_f64(7.53));
+        let written = hound::WavReader::open(&output)?.duration() as f64 / RATE as f64;
+        assert!((written - result.map.speech_duration().as_secs_f64()).abs() < 0.001, "{}", written);
+        assert!((written - 2.5).abs() < 0.1, "{}", written);
+        Ok(())
+    }
+}
@@ -1,65 +1,430 @@
-use regex::Regex;
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
+use std::time::Duration;
+use anyhow::anyhow;
 use serde::{Deserialize, Serialize};
 
 #[derive(PartialEq, Serialize, Deserialize, Clone)]
 pub struct Caption {
-    pub(crate) start_time: String,
-    end_time: String,
-    pub(crate) text: String,
+    // Optional cue identifier, e.g. "1" in the Zoom output.
+    #[serde(default)]
+    pub identifier: Option<String>,
+    #[serde(with = "timestamp_serde")]
+    pub start_time: Duration,
+    #[serde(with = "timestamp_serde")]
+    pub end_time: Duration,
+    // Raw cue settings following the end timestamp, e.g. "align:start position:0%".
+    #[serde(default)]
+    pub settings: Option<String>,
+    // Speaker name from the `<v Name>` voice span.
+    #[serde(default)]
+    pub speaker: Option<String>,
+    // Cue payload without the voice span. Multi-line cue text is joined with '\n'.
+    pub text: String,
 }
 
 impl Caption {
-    pub fn parse_start_time(&self) -> u32 {
-        let time_parts: Vec<&str> = self.start_time.split(':').collect();
-        let hour: u32 = time_parts[0].parse().unwrap();
-        let minute: u32 = time_parts[1].parse().unwrap();
-        let second: Vec<&str> = time_parts[2].split('.').collect();
-        let sec: u32 = second[0].parse().unwrap();
-        let millisec: u32 = second[1].parse().unwrap();
-        millisec + sec * 1000 + minute * 60 * 1000 + hour * 60 * 60 * 1000
+    pub fn new(start_time: Duration, end_time: Duration, text: String) -> Caption {
+        Caption {
+            identifier: None,
+            start_time,
+            end_time,
+            settings: None,
+            speaker: None,
+            text,
+        }
+    }
+
+    pub fn with_speaker(mut self, speaker: &str) -> Caption {
+        self.speaker = Some(speaker.to_string());
+        self
+    }
+
+    /**
+     * The text prefixed by the speaker name, e.g. "Alice: Hello".
+     */
+    pub fn labeled_text(&self) -> String {
+        match &self.speaker {
+            Some(speaker) => format!("{}: {}", speaker, self.text),
+            None => self.text.clone(),
+        }
     }
 }
 
 impl Debug for Caption {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "start: {}, end: {}, text: {}", self.start_time, self.end_time, self.text)
+        write!(f, "start: {}, end: {}, speaker: {:?}, text: {}",
+               format_timestamp(self.start_time), format_timestamp(self.end_time), self.speaker, self.text)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct WebVttError {
+    // 1-origin line number in the source.
+    pub line: usize,
+    pub message: String,
+}
+
+impl WebVttError {
+    fn new(line: usize, message: impl Into<String>) -> WebVttError {
+        WebVttError { line, message: message.into() }
+    }
+}
+
+impl Display for WebVttError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "WebVTT parse error at line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for WebVttError {}
+
+/**
+ * Parse the timestamp in `HH:MM:SS.mmm` or `MM:SS.mmm` form.
+ * The hours part may have more than two digits.
+ */
+pub fn parse_timestamp(src: &str) -> Option<Duration> {
+    let (hms, millis) = src.split_once('.')?;
+    if millis.len() != 3 || !millis.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let parts: Vec<&str> = hms.split(':').collect();
+    let (hours, minutes, seconds) = match parts.as_slice() {
+        [h, m, s] => {
+            if h.len() < 2 {
+                return None;
+            }
+            (parse_digits(h)?, parse_digits(m)?, parse_digits(s)?)
+        }
+        [m, s] => (0, parse_digits(m)?, parse_digits(s)?),
+        _ => return None,
+    };
+    if parts[parts.len() - 1].len() != 2 || parts[parts.len() - 2].len() != 2 {
+        return None;
+    }
+    if minutes >= 60 || seconds >= 60 {
+        return None;
+    }
+
+    let millis: u64 = millis.parse().ok()?;
+    Some(Duration::from_millis(
+        ((hours * 60 + minutes) * 60 + seconds) * 1000 + millis
+    ))
+}
+
+fn parse_digits(src: &str) -> Option<u64> {
+    if src.is_empty() || !src.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    src.parse().ok()
+}
+
+/**
+ * Format the duration as `HH:MM:SS.mmm`.
+ */
+pub fn format_timestamp(duration: Duration) -> String {
+    let total_millis = duration.as_millis();
+    let millis = total_millis % 1000;
+    let total_seconds = total_millis / 1000;
+    let seconds = total_seconds % 60;
+    let minutes = (total_seconds / 60) % 60;
+    let hours = total_seconds / 3600;
+    format!("{:02}:{:02}:{:02}.{:03}", hours, minutes, seconds, millis)
+}
+
+// Serialize the timestamps as `HH:MM:SS.mmm` strings, that's what the frontend expects.
+mod timestamp_serde {
+    use std::time::Duration;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use serde::de::Error;
+    use crate::webvtt::{format_timestamp, parse_timestamp};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(format_timestamp(*duration).as_str())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let src = String::deserialize(deserializer)?;
+        parse_timestamp(src.as_str())
+            .ok_or_else(|| D::Error::custom(format!("Invalid timestamp: {:?}", src)))
+    }
+}
+
+fn parse_timing_line(line: &str, lineno: usize) -> Result<(Duration, Duration, Option<String>), WebVttError> {
+    let (start, rest) = line.split_once("-->")
+        .ok_or_else(|| WebVttError::new(lineno, "missing '-->' in the timing line"))?;
+
+    let start = start.trim();
+    let start_time = parse_timestamp(start)
+        .ok_or_else(|| WebVttError::new(lineno, format!("invalid start timestamp: {:?}", start)))?;
+
+    let rest = rest.trim_start();
+    let (end, settings) = match rest.find([' ', '\t']) {
+        Some(pos) => (&rest[..pos], rest[pos..].trim()),
+        None => (rest, ""),
+    };
+    let end_time = parse_timestamp(end)
+        .ok_or_else(|| WebVttError::new(lineno, format!("invalid end timestamp: {:?}", end)))?;
+    if end_time < start_time {
+        return Err(WebVttError::new(lineno,
+            format!("end timestamp {} is before start timestamp {}", end, start)));
+    }
+
+    let settings = if settings.is_empty() { None } else { Some(settings.to_string()) };
+    Ok((start_time, end_time, settings))
+}
+
+fn unescape_annotation(src: &str) -> String {
+    src.replace("&lt;", "<").replace("&gt;", ">").replace("&amp;", "&")
+}
+
+fn escape_annotation(src: &str) -> String {
+    src.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/**
+ * Split the leading `<v Name>` (or `<v.class Name>`) voice span from the cue text.
+ */
+fn parse_voice_span(text: &str) -> (Option<String>, String) {
+    let Some(rest) = text.strip_prefix("<v") else {
+        return (None, text.to_string());
+    };
+    if !rest.starts_with(['.', ' ', '\t']) {
+        return (None, text.to_string());
+    }
+    let Some(close) = rest.find('>') else {
+        return (None, text.to_string());
+    };
+
+    // skip the class names
+    let tag = &rest[..close];
+    let annotation = match tag.find([' ', '\t']) {
+        Some(pos) => tag[pos..].trim(),
+        None => "",
+    };
+
+    let body = rest[close + 1..].trim_start();
+    let body = body.strip_suffix("</v>").unwrap_or(body).trim_end();
+
+    if annotation.is_empty() {
+        (None, body.to_string())
+    } else {
+        (Some(unescape_annotation(annotation)), body.to_string())
     }
 }
 
-pub fn parse_webvtt(webvtt: &str) -> Vec<Caption> {
+fn is_block_keyword(line: &str, keyword: &str) -> bool {
+    match line.strip_prefix(keyword) {
+        Some(rest) => rest.is_empty() || rest.starts_with([' ', '\t']),
+        None => false,
+    }
+}
+
+/**
+ * Parse the WebVTT document.
+ *
+ * Cue identifiers, cue settings, multi-line cue payloads, and NOTE/STYLE/REGION blocks are
+ * supported. Comment, style and region blocks are skipped.
+ */
+pub fn parse_webvtt(webvtt: &str) -> Result<Vec<Caption>, WebVttError> {
+    let normalized = webvtt.strip_prefix('\u{FEFF}').unwrap_or(webvtt)
+        .replace("\r\n", "\n")
+        .replace('\r', "\n");
+    let lines: Vec<&str> = normalized.split('\n').collect();
+
+    // Some tools emit leading blank lines before the signature. Be lenient about it.
+    let mut i = 0;
+    while i < lines.len() && lines[i].trim().is_empty() {
+        i += 1;
+    }
+    if i >= lines.len() || !is_block_keyword(lines[i], "WEBVTT") {
+        return Err(WebVttError::new(i + 1, "missing 'WEBVTT' signature"));
+    }
+    // Skip the header block.
+    while i < lines.len() && !lines[i].is_empty() {
+        i += 1;
+    }
+
     let mut captions = Vec::new();
+    let mut seen_cue = false;
+    while i < lines.len() {
+        if lines[i].is_empty() {
+            i += 1;
+            continue;
+        }
 
-    // Matches "HH:MM:SS.sss --> HH:MM:SS.sss"
-    let time_pattern = Regex::new(r"(\d{2}:\d{2}:\d{2}.\d{3}) --> (\d{2}:\d{2}:\d{2}.\d{3})").unwrap();
-
-    let mut current_start_time = String::new();
-    let mut current_end_time = String::new();
-    let mut current_text = String::new();
-
-    for line in webvtt.lines() {
-        if time_pattern.is_match(line) {
-            let caps = time_pattern.captures(line).unwrap();
-            current_start_time = caps.get(1).unwrap().as_str().to_string();
-            current_end_time = caps.get(2).unwrap().as_str().to_string();
-        } else if (!line.is_empty()) && (line != "WEBVTT") {
-            let caption = Caption {
-                start_time: current_start_time.clone().to_string(),
-                end_time: current_end_time.clone().to_string(),
-                text: line.trim().to_string(),
-            };
-            captions.push(caption);
-            current_text.clear();
+        // collect the block
+        let block_start = i;
+        while i < lines.len() && !lines[i].is_empty() {
+            i += 1;
+        }
+        let block = &lines[block_start..i];
+
+        if is_block_keyword(block[0], "NOTE") {
+            continue;
+        }
+        if !seen_cue && (is_block_keyword(block[0], "STYLE") || is_block_keyword(block[0], "REGION")) {
+            continue;
+        }
+
+        let (identifier, timing_index) = if block[0].contains("-->") {
+            (None, 0)
+        } else if block.len() >= 2 && block[1].contains("-->") {
+            (Some(block[0].to_string()), 1)
+        } else {
+            return Err(WebVttError::new(block_start + 1,
+                format!("expected a cue timing line, got {:?}", block[0])));
+        };
+
+        let lineno = block_start + timing_index + 1;
+        let (start_time, end_time, settings) = parse_timing_line(block[timing_index], lineno)?;
+
+        let text = block[timing_index + 1..].iter()
+            .map(|line| line.trim())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let (speaker, text) = parse_voice_span(text.as_str());
+
+        captions.push(Caption {
+            identifier,
+            start_time,
+            end_time,
+            settings,
+            speaker,
+            text,
+        });
+        seen_cue = true;
+    }
+
+    Ok(captions)
+}
+
+/**
+ * Serialize the captions as a WebVTT document.
+ *
+ * Blank lines in the cue text would terminate the cue, so they are dropped. "-->" is escaped
+ * because it's not allowed in the cue payload.
+ */
+pub fn write_webvtt(captions: &[Caption]) -> String {
+    let mut buffer = String::from("WEBVTT\n");
+    for caption in captions {
+        buffer.push('\n');
+        if let Some(identifier) = &caption.identifier {
+            let identifier = identifier.replace(['\r', '\n'], " ").replace("-->", "--&gt;");
+            if !identifier.trim().is_empty() {
+                buffer.push_str(identifier.trim());
+                buffer.push('\n');
+            }
+        }
+
+        buffer.push_str(format_timestamp(caption.start_time).as_str());
+        buffer.push_str(" --> ");
+        buffer.push_str(format_timestamp(caption.end_time).as_str());
+        if let Some(settings) = &caption.settings {
+            let settings = settings.replace(['\r', '\n'], " ");
+            if !settings.trim().is_empty() {
+                buffer.push(' ');
+                buffer.push_str(settings.trim());
+            }
+        }
+        buffer.push('\n');
+
+        let mut lines: Vec<String> = caption.text.lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .map(|line| line.replace("-->", "--&gt;"))
+            .collect();
+        if let Some(speaker) = &caption.speaker {
+            let speaker = escape_annotation(speaker.replace(['\r', '\n'], " ").trim());
+            if !speaker.is_empty() {
+                if lines.is_empty() {
+                    lines.push(String::new());
+                }
+                lines[0] = format!("<v {}>{}", speaker, lines[0]);
+            }
+        }
+        for line in lines {
+            buffer.push_str(line.as_str());
+            buffer.push('\n');
         }
     }
+    buffer
+}
 
+/**
+ * Merge the captions from the multiple sources into the one timeline, ordered by start time.
+ * Cues from the earlier source come first when they start at the same time.
+ */
+pub fn interleave_captions(sources: Vec<Vec<Caption>>) -> Vec<Caption> {
+    let mut captions: Vec<Caption> = sources.into_iter().flatten().collect();
+    captions.sort_by_key(|caption| caption.start_time);
     captions
 }
 
+/**
+ * Delay all cues by the offset.
+ */
+pub fn shift_captions(captions: &[Caption], offset: Duration) -> Vec<Caption> {
+    captions.iter()
+        .map(|caption| Caption {
+            start_time: caption.start_time + offset,
+            end_time: caption.end_time + offset,
+            ..caption.clone()
+        })
+        .collect()
+}
+
+/**
+ * Append the second transcript, which starts `offset` after the first one.
+ */
+pub fn concat_captions(first: &[Caption], second: &[Caption], offset: Duration) -> Vec<Caption> {
+    interleave_captions(vec![first.to_vec(), shift_captions(second, offset)])
+}
+
+/**
+ * Extract the cues in the range [start, end). Cues on the boundary are trimmed to the range, and
+ * the timestamps are rebased so that `start` becomes zero.
+ */
+pub fn clip_captions(captions: &[Caption], start: Duration, end: Duration) -> Vec<Caption> {
+    captions.iter()
+        .filter(|caption| caption.start_time < end && caption.end_time > start)
+        .map(|caption| Caption {
+            start_time: caption.start_time.max(start) - start,
+            end_time: caption.end_time.min(end) - start,
+            ..caption.clone()
+        })
+        .collect()
+}
+
+/**
+ * Validate that the cues are well-formed and ordered by start time. The cues may overlap, e.g. both
+ * speakers talk at once in the speaker attributed transcript.
+ */
+pub fn validate_captions(captions: &[Caption]) -> anyhow::Result<()> {
+    let mut prev: Option<&Caption> = None;
+    for (i, caption) in captions.iter().enumerate() {
+        if caption.end_time < caption.start_time {
+            return Err(anyhow!("Cue #{} ends before it starts: {:?}", i + 1, caption));
+        }
+        if let Some(prev) = prev {
+            if caption.start_time < prev.start_time {
+                return Err(anyhow!("Cue #{} is not ordered by start time: {:?} after {:?}",
+                    i + 1, caption, prev));
+            }
+        }
+        prev = Some(caption);
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
     #[test]
     fn parses_webvtt() {
         let input = "WEBVTT
@@ -70,13 +435,262 @@ Caption text
 00:00:09.300 --> 00:00:18.140
 More caption text";
 
-        let result = parse_webvtt(input);
+        let result = parse_webvtt(input).unwrap();
 
         assert_eq!(
             result, vec![
-                Caption { start_time: "00:00:00.000".to_string(), end_time: "00:00:09.300".to_string(), text: "Caption text".to_string() },
-                Caption { start_time: "00:00:09.300".to_string(), end_time: "00:00:18.140".to_string(), text: "More caption text".to_string() }
+                Caption::new(ms(0), ms(9300), "Caption text".to_string()),
+                Caption::new(ms(9300), ms(18140), "More caption text".to_string()),
             ]
         );
     }
+
+    #[test]
+    fn parses_full_grammar() {
+        let input = "\u{FEFF}WEBVTT - Zoom meeting\r
+Kind: captions\r
+\r
+STYLE\r
+::cue { color: white }\r
+\r
+REGION\r
+id:fred width:40%\r
+\r
+NOTE this is a comment\r
+spanning lines\r
+\r
+1\r
+00:01.000 --> 00:02.500 align:start position:10%\r
+first line\r
+second line\r
+\r
+NOTE\r
+another comment\r
+\r
+01:00:00.000 --> 01:00:01.000\r
+an hour later\r
+";
+
+        let result = parse_webvtt(input).unwrap();
+        assert_eq!(result.len(), 2);
+
+        assert_eq!(result[0].identifier, Some("1".to_string()));
+        assert_eq!(result[0].start_time, ms(1000));
+        assert_eq!(result[0].end_time, ms(2500));
+        assert_eq!(result[0].settings, Some("align:start position:10%".to_string()));
+        assert_eq!(result[0].text, "first line\nsecond line");
+
+        assert_eq!(result[1].identifier, None);
+        assert_eq!(result[1].start_time, ms(3600 * 1000));
+        assert_eq!(result[1].text, "an hour later");
+    }
+
+    #[test]
+    fn reports_errors_with_line_number() {
+        assert_eq!(
+            parse_webvtt("00:00:00.000 --> 00:00:01.000\nfoo").unwrap_err().line,
+            1
+        );
+
+        let err = parse_webvtt("WEBVTT\n\n00:00:00.000 --> 00:00:01.000\nok\n\n00:00:0x.000 --> 00:00:02.000\nng")
+            .unwrap_err();
+        assert_eq!(err.line, 6);
+
+        let err = parse_webvtt("WEBVTT\n\n00:00:03.000 --> 00:00:01.000\nbackwards")
+            .unwrap_err();
+        assert_eq!(err.line, 3);
+
+        let err = parse_webvtt("WEBVTT\n\nhello\nworld")
+            .unwrap_err();
+        assert_eq!(err.line, 3);
+    }
+
+    #[test]
+    fn parses_timestamps() {
+        assert_eq!(parse_timestamp("00:00:09.300"), Some(ms(9300)));
+        assert_eq!(parse_timestamp("01:02.003"), Some(ms(62003)));
+        assert_eq!(parse_timestamp("100:00:00.000"), Some(ms(100 * 3600 * 1000)));
+        assert_eq!(parse_timestamp("00:60:00.000"), None);
+        assert_eq!(parse_timestamp("0:00:00.000"), None);
+        assert_eq!(parse_timestamp("00:00:00,000"), None);
+        assert_eq!(parse_timestamp("00:00.00"), None);
+    }
+
+    #[test]
+    fn formats_timestamps() {
+        assert_eq!(format_timestamp(ms(0)), "00:00:00.000");
+        assert_eq!(format_timestamp(ms(3_723_004)), "01:02:03.004");
+    }
+
+    #[test]
+    fn writes_webvtt() {
+        let mut caption = Caption::new(ms(1000), ms(2500), "first line\n\nsecond --> line".to_string());
+        caption.identifier = Some("1".to_string());
+        caption.settings = Some("align:start".to_string());
+
+        assert_eq!(
+            write_webvtt(&[caption, Caption::new(ms(3_600_000), ms(3_601_000), "later".to_string())]),
+            "WEBVTT
+
+1
+00:00:01.000 --> 00:00:02.500 align:start
+first line
+second --&gt; line
+
+01:00:00.000 --> 01:00:01.000
+later
+"
+        );
+    }
+
+    #[test]
+    fn round_trips_captions() {
+        let mut first = Caption::new(ms(0), ms(1500), "こんにちは。\n二行目".to_string());
+        first.identifier = Some("intro".to_string());
+        first.settings = Some("line:0 position:20%".to_string());
+        let captions = vec![
+            first,
+            Caption::new(ms(1500), ms(4000), "edited in the VttView".to_string()),
+            Caption::new(ms(62_000), ms(63_000), "".to_string()),
+        ];
+
+        let written = write_webvtt(&captions);
+        let parsed = parse_webvtt(written.as_str()).unwrap();
+        assert_eq!(parsed, captions);
+        assert_eq!(write_webvtt(&parsed), written);
+    }
+
+    #[test]
+    fn round_trips_document() {
+        let src = "WEBVTT
+
+00:00:00.000 --> 00:00:09.300
+Caption text
+
+00:00:09.300 --> 00:00:18.140
+More caption text
+";
+        assert_eq!(write_webvtt(&parse_webvtt(src).unwrap()), src);
+    }
+
+    #[test]
+    fn validates_captions() {
+        assert!(validate_captions(&[
+            Caption::new(ms(0), ms(1000), "a".to_string()),
+            Caption::new(ms(1000), ms(2000), "b".to_string()),
+        ]).is_ok());
+
+        // ends before start
+        assert!(validate_captions(&[
+            Caption::new(ms(1000), ms(0), "a".to_string()),
+        ]).is_err());
+
+        // not ordered
+        assert!(validate_captions(&[
+            Caption::new(ms(5000), ms(6000), "a".to_string()),
+            Caption::new(ms(1000), ms(2000), "b".to_string()),
+        ]).is_err());
+
+        // overlapped
+        assert!(validate_captions(&[
+            Caption::new(ms(0), ms(1500), "a".to_string()),
+            Caption::new(ms(1000), ms(2000), "b".to_string()),
+        ]).is_ok());
+    }
+
+    #[test]
+    fn parses_voice_spans() {
+        let input = "WEBVTT
+
+00:00:00.000 --> 00:00:01.000
+<v Alice>Hello
+
+00:00:01.000 --> 00:00:02.000
+<v.loud Bob &amp; Co>Hi
+how are you?</v>
+
+00:00:02.000 --> 00:00:03.000
+<i>no voice</i>";
+
+        let result = parse_webvtt(input).unwrap();
+        assert_eq!(result, vec![
+            Caption::new(ms(0), ms(1000), "Hello".to_string()).with_speaker("Alice"),
+            Caption::new(ms(1000), ms(2000), "Hi\nhow are you?".to_string()).with_speaker("Bob & Co"),
+            Caption::new(ms(2000), ms(3000), "<i>no voice</i>".to_string()),
+        ]);
+        assert_eq!(result[0].labeled_text(), "Alice: Hello");
+        assert_eq!(result[2].labeled_text(), "<i>no voice</i>");
+    }
+
+    #[test]
+    fn round_trips_voice_spans() {
+        let captions = vec![
+            Caption::new(ms(0), ms(1000), "Hello\nworld".to_string()).with_speaker("Alice <host>"),
+            Caption::new(ms(1000), ms(2000), "".to_string()).with_speaker("Bob"),
+            Caption::new(ms(2000), ms(3000), "anonymous".to_string()),
+        ];
+
+        let written = write_webvtt(&captions);
+        assert!(written.contains("<v Alice &lt;host&gt;>Hello\nworld\n"));
+        assert_eq!(parse_webvtt(written.as_str()).unwrap(), captions);
+    }
+
+    #[test]
+    fn interleaves_captions() {
+        let mic = vec![
+            Caption::new(ms(0), ms(1000), "a".to_string()).with_speaker("Me"),
+            Caption::new(ms(5000), ms(6000), "c".to_string()).with_speaker("Me"),
+        ];
+        let system = vec![
+            Caption::new(ms(2000), ms(3000), "b".to_string()).with_speaker("Others"),
+            Caption::new(ms(5000), ms(5500), "d".to_string()).with_speaker("Others"),
+        ];
+
+        let got: Vec<String> = interleave_captions(vec![mic, system]).iter()
+            .map(|caption| caption.labeled_text())
+            .collect();
+        assert_eq!(got, vec!["Me: a", "Others: b", "Me: c", "Others: d"]);
+    }
+
+    #[test]
+    fn shifts_captions() {
+        let captions = vec![Caption::new(ms(0), ms(1000), "a".to_string()).with_speaker("Me")];
+        assert_eq!(shift_captions(&captions, ms(60_000)), vec![
+            Caption::new(ms(60_000), ms(61_000), "a".to_string()).with_speaker("Me"),
+        ]);
+    }
+
+    #[test]
+    fn concats_captions() {
+        let first = vec![
+            Caption::new(ms(0), ms(1000), "a".to_string()),
+            Caption::new(ms(5000), ms(6000), "c".to_string()),
+        ];
+        let second = vec![
+            Caption::new(ms(0), ms(1000), "b".to_string()),
+            Caption::new(ms(3000), ms(4000), "d".to_string()),
+        ];
+        assert_eq!(concat_captions(&first, &second, ms(2000)), vec![
+            Caption::new(ms(0), ms(1000), "a".to_string()),
+            Caption::new(ms(2000), ms(3000), "b".to_string()),
+            Caption::new(ms(5000), ms(6000), "c".to_string()),
+            Caption::new(ms(5000), ms(6000), "d".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn clips_captions() {
+        let captions = vec![
+            Caption::new(ms(0), ms(1000), "a".to_string()),
+            Caption::new(ms(1000), ms(3000), "b".to_string()),
+            Caption::new(ms(3000), ms(4000), "c".to_string()),
+            Caption::new(ms(4000), ms(6000), "d".to_string()),
+            Caption::new(ms(6000), ms(7000), "e".to_string()),
+        ];
+        assert_eq!(clip_captions(&captions, ms(2000), ms(5000)), vec![
+            Caption::new(ms(0), ms(1000), "b".to_string()),
+            Caption::new(ms(1000), ms(2000), "c".to_string()),
+            Caption::new(ms(2000), ms(3000), "d".to_string()),
+        ]);
+    }
 }
@@ -1,11 +1,17 @@
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Command, Stdio};
+use std::thread;
 use anyhow::anyhow;
 use std::time::Instant;
 use uuid::Uuid;
+use crate::dsp;
+use crate::dsp::WavFormat;
 use crate::transcriber::Transcriber;
 
+const WHISPER_SAMPLE_RATE: u32 = 16000;
+
 pub struct WhisperTranscriber {
     version: String,
     model: String,
@@ -19,8 +25,8 @@ impl WhisperTranscriber {
 }
 
 impl Transcriber for WhisperTranscriber {
-    fn transcribe(&self, in_file: &str, out_file: &str) -> anyhow::Result<()> {
-        run_whisper(&self.version, &self.model, &self.language, in_file, out_file)
+    fn transcribe(&self, in_file: &str, out_file: &str, on_progress: &dyn Fn(f32)) -> anyhow::Result<()> {
+        run_whisper(&self.version, &self.model, &self.language, in_file, out_file, on_progress)
     }
 }
 
@@ -36,7 +42,7 @@ fn generate_temp_file_path(ext: &str) -> PathBuf {
     Path::join(&temp_dir, file_name)
 }
 
-fn run_whisper(version_tag: &str, model: &str, language: &str, in_file: &str, out_file: &str) -> anyhow::Result<()> {
+fn run_whisper(version_tag: &str, model: &str, language: &str, in_file: &str, out_file: &str, on_progress: &dyn Fn(f32)) -> anyhow::Result<()> {
     let cache_dir = dirs::cache_dir()
         .ok_or(anyhow!("Cannot get cache directory"))?;
 
@@ -112,69 +118,91 @@ fn run_whisper(version_tag: &str, model: &str, language: &str, in_file: &str, ou
         }
     }
 
-    // 16kbps, 16bit is required for whisper.cpp https://github.com/ggerganov/whisper.cpp
+    // 16kHz, 16bit is required for whisper.cpp https://github.com/ggerganov/whisper.cpp
     let temp_file_path = generate_temp_file_path("wav");
 
-    // Use ffmpeg to convert the input file to the desired sample rate and bit depth
-    log::info!("Starting ffmpeg to convert wave file...");
-    let output = match Command::new("ffmpeg")
-        .args([
-            "-i", in_file,
-            "-ar", "16000",
-            "-acodec", "pcm_s16le",
-            temp_file_path.as_path().to_str().unwrap()
-        ])
-        .output() {
-        Ok(output) => { output }
-        Err(err) => {
-            return Err(anyhow!("Cannot run ffmpeg: {}", err))
-        }
-    };
-    if !output.status.success() {
-        return Err(anyhow!("ffmpeg failed to convert the WAV file: {:?} {:?}",
-            String::from_utf8_lossy(&output.stdout),
-            String::from_utf8_lossy(&output.stderr)
-        ));
+    log::info!("Converting {} to 16kHz 16bit wave file...", in_file);
+    let source = dsp::open_wav(Path::new(in_file))?;
+    if let Err(err) = dsp::write_wav(dsp::resample(source, WHISPER_SAMPLE_RATE), &temp_file_path, WavFormat::Int16) {
+        let _ = fs::remove_file(&temp_file_path);
+        return Err(err.context(format!("Cannot convert {} for whisper.cpp", in_file)));
     }
 
     log::info!("[whisper.cpp] Start transcribing... {} to {}(model={}, version={})",
         in_file, out_file, model, version_tag);
     let start = Instant::now();
-    let output = match Command::new("./main")
+    let mut child = match Command::new("./main")
         .args([
             "--language", language,
             "-m", &format!("models/ggml-{}.bin", model),
             "-ovtt",
+            "--print-progress",
             "-of", &out_file.replace(".vtt", "").to_string(),
             "-f", temp_file_path.to_str().unwrap()
         ])
         .current_dir(&whisper_dir)
-        .output() {
-        Ok(output) => { output }
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn() {
+        Ok(child) => { child }
         Err(err) => {
             return Err(anyhow!("Cannot run whisper: {}", err))
         }
     };
-    if !output.status.success() {
+    // Drain the stdout in the background, not to block whisper.cpp while reading the stderr.
+    let mut stdout = child.stdout.take().unwrap();
+    let stdout_reader = thread::spawn(move || {
+        let mut buf = String::new();
+        let _ = stdout.read_to_string(&mut buf);
+        buf
+    });
+    let mut stderr = String::new();
+    for line in BufReader::new(child.stderr.take().unwrap()).lines() {
+        let line = line?;
+        if let Some(percent) = parse_progress(line.as_str()) {
+            on_progress(percent);
+        }
+        stderr.push_str(line.as_str());
+        stderr.push('\n');
+    }
+    let status = child.wait()?;
+    let stdout = stdout_reader.join().unwrap_or_default();
+
+    if !status.success() {
         if let Err(err) = fs::remove_file(temp_file_path.to_str().unwrap()) {
             return Err(anyhow!("Cannot remove file({:?}): {:?}", temp_file_path, err));
         }
         return Err(anyhow!("Cannot run whisper.cpp: {} {}",
             in_file,
-            String::from_utf8_lossy(&output.stderr)));
+            stderr));
     }
 
     if let Err(err) = fs::remove_file(temp_file_path.to_str().unwrap()) {
         return Err(anyhow!("Cannot remove file({:?}): {:?}", temp_file_path, err));
     }
 
-    log::info!("Ran whisper.cpp: {:?}, {:?}",
-        String::from_utf8_lossy(&output.stdout),
-        String::from_utf8_lossy(&output.stderr)
-    );
+    log::info!("Ran whisper.cpp: {:?}, {:?}", stdout, stderr);
 
     let duration = start.elapsed(); // 経過時間を取得
     log::info!("whisper.cpp execution time: {:?}", duration);
 
     Ok(())
 }
+
+// "whisper_print_progress_callback: progress =  45%"
+fn parse_progress(line: &str) -> Option<f32> {
+    let (_, progress) = line.split_once("progress =")?;
+    progress.trim().strip_suffix('%')?.trim().parse::<f32>().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_progress() {
+        assert_eq!(parse_progress("whisper_print_progress_callback: progress =  45%"), Some(45.0));
+        assert_eq!(parse_progress("whisper_print_progress_callback: progress = 100%"), Some(100.0));
+        assert_eq!(parse_progress("whisper_init_from_file_with_params_no_state: loading model"), None);
+    }
+}
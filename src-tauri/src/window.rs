@@ -1,7 +1,7 @@
 use screencapturekit::sc_shareable_content::SCShareableContent;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct WindowPattern {
     pub(crate) bundle_id: String,
     pub(crate) window_title: String,
@@ -1,7 +1,44 @@
-import {BaseDirectory, createDir, exists, readDir} from "@tauri-apps/api/fs";
 import {Entry} from "./entry";
 import {invoke} from "@tauri-apps/api/tauri";
 
+export type AudioFormat = "Mp3" | "Opus" | "Flac" | "Aac";
+
+export type ProcessingStep = "Merge" | "Encode" | "Transcribe" | "Summarize" | "Cleanup";
+
+export type ProcessingStage = "Recording" | "Recorded" | "Merged" | "Encoded" | "Transcribed" | "Summarized" | "Completed" | "Failed";
+
+// Nothing to resume.
+export function isProcessed(stage: ProcessingStage): boolean {
+    return stage === "Summarized" || stage === "Completed";
+}
+
+export interface EntryListItem {
+    path: string,
+    basename: string,
+    title?: string,
+    duration_millis?: number,
+    has_mic_wav: boolean,
+    audio_format?: AudioFormat,
+    has_vtt: boolean,
+    has_md: boolean,
+    summary_excerpt?: string,
+    processing_stage: ProcessingStage,
+    processing_error?: string,
+}
+
+export interface EntryQuery {
+    offset?: number,
+    limit?: number,
+    since?: string, // YYYY-MM-DD
+    until?: string, // YYYY-MM-DD
+    sort?: "Newest" | "Oldest" | "Longest",
+}
+
+interface EntryList {
+    entries: EntryListItem[],
+    total: number,
+}
+
 export class DataRepo {
     constructor() {
     }
@@ -11,25 +48,8 @@ export class DataRepo {
         return new Entry(path)
     }
 
-    async list_entries(): Promise<Entry[]> {
-        // mkdir -p
-        if (!await exists('data', { dir: BaseDirectory.AppData })) {
-            await createDir('data', {dir: BaseDirectory.AppData, recursive: true });
-        }
-
-        const result: Entry[] = [];
-        const entries = await readDir('data', { dir: BaseDirectory.AppData, recursive: false });
-        for (const fileEntry of entries) {
-            if (fileEntry.path.endsWith(".DS_Store")) { // Ignore .DS_Store
-                continue;
-            }
-
-            if (await exists(fileEntry.path)) {
-                let entry = await Entry.fromPath(fileEntry.path);
-                result.push(entry);
-            }
-        }
-        result.sort((a, b) => b.path.localeCompare(a.path));
-        return result;
+    async list_entries(query: EntryQuery = {}): Promise<Entry[]> {
+        let list: EntryList = await invoke('list_entries', {query: query});
+        return list.entries.map((item) => Entry.fromListItem(item));
     }
 }
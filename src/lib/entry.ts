@@ -1,11 +1,44 @@
 import {fs} from "@tauri-apps/api";
-import {exists, removeDir} from "@tauri-apps/api/fs";
+import {exists} from "@tauri-apps/api/fs";
 import {invoke} from "@tauri-apps/api/tauri";
 import {parseWebVTT} from "./webvtt";
+import type {AudioFormat, EntryListItem, ProcessingStage} from "./data_repo";
+
+export interface EntryMeta {
+    title?: string,
+    tags: string[],
+    participants: string[],
+    notes?: string,
+    window_pattern?: {bundle_id: string, window_title: string},
+    duration_millis?: number,
+    language?: string,
+    transcriber?: string,
+    transcriber_model?: string,
+    summarizer?: string,
+    summarizer_model?: string,
+    audio_format?: AudioFormat,
+    // Stereo: the mic on the left, the system audio on the right.
+    audio_channel_mode?: "Mono" | "Stereo",
+}
+
+const AUDIO_MIME_TYPES: Record<AudioFormat, string> = {
+    Mp3: "audio/mpeg",
+    Opus: "audio/ogg",
+    Flac: "audio/flac",
+    Aac: "audio/mp4",
+};
+
+export interface EncodedAudio {
+    format: AudioFormat,
+    path: string,
+}
 
 export class Entry {
     public path: string; // directory name
     public summary?: string;
+    public meta?: EntryMeta;
+    // Set if the entry was loaded by DataRepo.list_entries
+    public listItem?: EntryListItem;
 
     constructor(path: string) {
         this.path = path;
@@ -18,6 +51,25 @@ export class Entry {
         }
     }
 
+    async readMeta() {
+        this.meta = await invoke("load_entry_meta", {dir: this.path});
+    }
+
+    // Refresh the stage in the list, after the postprocessing.
+    async readProcessingState() {
+        const state: {stage: ProcessingStage, error?: string} = await invoke("load_processing_state", {dir: this.path});
+        if (this.listItem) {
+            this.listItem.processing_stage = state.stage;
+            this.listItem.processing_error = state.error;
+        }
+    }
+
+    async saveMeta(meta: EntryMeta) {
+        await invoke("save_entry_meta", {dir: this.path, meta: meta});
+        // The postprocess may have updated the other fields.
+        await this.readMeta();
+    }
+
     basename() : string {
         const pattern = /[^/\\]+$/;
         const matches = this.path.match(pattern);
@@ -39,10 +91,6 @@ export class Entry {
         return this.buildPath("md");
     }
 
-    mp3Path() {
-        return this.buildPath("mp3");
-    }
-
     vttPath() {
         return this.buildPath("vtt");
     }
@@ -51,8 +99,9 @@ export class Entry {
         return exists(this.micWavPath());
     }
 
-    hasMp3(): Promise<boolean> {
-        return exists(this.mp3Path());
+    // The format is recorded when it's encoded. It's MP3 for the old entries.
+    findEncodedAudio(): Promise<EncodedAudio | null> {
+        return invoke("find_encoded_audio", {dir: this.path});
     }
 
     hasVTT(): Promise<boolean> {
@@ -69,17 +118,20 @@ export class Entry {
     }
 
     async saveSummary(summary: string) {
-        let path = this.mdPath();
-        await fs.writeTextFile(path, summary)
+        // Rust side updates the search index too.
+        await invoke("save_summary", {dir: this.path, summary: summary});
         this.summary = summary;
     }
 
-    async readMp3AsDataUri(): Promise<string> {
-        const mp3Path = this.mp3Path();
+    async readAudioAsDataUri(): Promise<string> {
+        const encoded = await this.findEncodedAudio();
+        if (!encoded) {
+            throw new Error(`Missing encoded audio file in ${this.path}`);
+        }
         try {
-            const mp3Data = await fs.readBinaryFile(mp3Path);
-            const blob = new Blob([mp3Data], {
-                type: "audio/mp3",
+            const audioData = await fs.readBinaryFile(encoded.path);
+            const blob = new Blob([audioData], {
+                type: AUDIO_MIME_TYPES[encoded.format],
             });
             return new Promise(function (res, rej) {
                 const reader = new FileReader();
@@ -93,8 +145,8 @@ export class Entry {
                 reader.readAsDataURL(blob);
             });
         } catch (error) {
-            console.error(`Failed to read mp3 file at ${mp3Path}:`, error);
-            throw new Error(`Could not read mp3 file: ${mp3Path}: ${error}`);
+            console.error(`Failed to read audio file at ${encoded.path}:`, error);
+            throw new Error(`Could not read audio file: ${encoded.path}: ${error}`);
         }
     }
 
@@ -112,13 +164,20 @@ export class Entry {
 
         const weekdays = ['Sun', 'Mon', 'Tue', 'Wed', 'Thu', 'Fri', 'Sat'];
 
-        return `${date.getFullYear()}-${String(date.getMonth() + 1).padStart(2, '0')}-${String(date.getDate()).padStart(2, '0')}(${weekdays[date.getDay()]}) ${String(date.getHours()).padStart(2, '0')}:${String(date.getMinutes()).padStart(2, '0')}`;
+        const metaTitle = this.meta?.title ?? this.listItem?.title;
+        const title = metaTitle ? ` ${metaTitle}` : '';
+        return `${date.getFullYear()}-${String(date.getMonth() + 1).padStart(2, '0')}-${String(date.getDate()).padStart(2, '0')}(${weekdays[date.getDay()]}) ${String(date.getHours()).padStart(2, '0')}:${String(date.getMinutes()).padStart(2, '0')}${title}`;
     }
 
     async remove() {
-        await removeDir(this.path, {
-            recursive: true
-        })
+        // Moved to the trash. It can be restored from "Misc > Trash".
+        await invoke("delete_entry", {dir: this.path});
+    }
+
+    static fromListItem(item: EntryListItem): Entry {
+        let entry = new Entry(item.path);
+        entry.listItem = item;
+        return entry;
     }
 
     static async fromPath(path: string): Promise<Entry> {
@@ -129,6 +188,11 @@ export class Entry {
             // This is not a fatal error... maybe.
             console.error(e);
         }
+        try {
+            await entry.readMeta();
+        } catch (e) {
+            console.error(e);
+        }
         return entry;
     }
 }
@@ -1,5 +1,63 @@
-export interface PostProcessStatus {
+import type {ProcessingStep} from "./data_repo";
+
+export interface JobStatus {
     path: string,
     message: string,
-    processed_paths: string[],
+    step?: ProcessingStep,
+    percent?: number,
+}
+
+export interface PostProcessStatus {
+    // every in-flight job
+    jobs: JobStatus[],
+}
+
+// Pushed by the postprocessor as "postprocess_progress" event.
+export type ProgressEvent =
+    | {kind: "Started", path: string}
+    | {kind: "Message", path: string, message: string}
+    | {kind: "StepStarted", path: string, step: ProcessingStep}
+    | {kind: "StepProgress", path: string, step: ProcessingStep, percent: number}
+    | {kind: "StepFinished", path: string, step: ProcessingStep, duration_millis: number}
+    | {kind: "StepFailed", path: string, step: ProcessingStep, error: string}
+    | {kind: "Finished", path: string, success: boolean};
+
+export function applyProgressEvent(jobs: JobStatus[], event: ProgressEvent): JobStatus[] {
+    switch (event.kind) {
+        case "Started": {
+            // The job is retried, or the status is loaded already.
+            const started = {path: event.path, message: ""};
+            return jobs.some((job) => job.path === event.path)
+                ? jobs.map((job) => job.path === event.path ? started : job)
+                : [...jobs, started];
+        }
+        case "Finished":
+            return jobs.filter((job) => job.path !== event.path);
+    }
+    return jobs.map((job) => {
+        if (job.path !== event.path) {
+            return job;
+        }
+        switch (event.kind) {
+            case "Message":
+                return {...job, message: event.message};
+            case "StepStarted":
+                return {...job, step: event.step, percent: undefined};
+            case "StepProgress":
+                return {...job, percent: event.percent};
+            default:
+                return {...job, step: undefined, percent: undefined};
+        }
+    });
+}
+
+export function findJobStatus(status: PostProcessStatus | undefined, path: string): JobStatus | undefined {
+    return status?.jobs.find((job) => job.path === path);
+}
+
+export function formatJobStatus(job: JobStatus | undefined): string {
+    if (!job) {
+        return "";
+    }
+    return job.percent !== undefined ? `${job.message} (${job.percent}%)` : job.message;
 }
@@ -0,0 +1,18 @@
+import {invoke} from "@tauri-apps/api/tauri";
+
+export interface SnippetFragment {
+    text: string,
+    highlighted: boolean,
+}
+
+export interface SearchHit {
+    path: string,
+    basename: string,
+    source: "Transcript" | "Summary",
+    start_time?: string, // HH:MM:SS.mmm
+    snippet: SnippetFragment[],
+}
+
+export async function searchEntries(query: string, limit?: number): Promise<SearchHit[]> {
+    return await invoke("search_entries", {query: query, limit: limit});
+}
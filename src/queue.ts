@@ -0,0 +1,8 @@
+import "./styles.css";
+import JobQueue from "./JobQueue.svelte";
+
+const app = new JobQueue({
+  target: document.getElementById("app"),
+});
+
+export default app;
@@ -0,0 +1,8 @@
+import "./styles.css";
+import Trash from "./Trash.svelte";
+
+const app = new Trash({
+  target: document.getElementById("app"),
+});
+
+export default app;